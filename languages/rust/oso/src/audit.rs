@@ -0,0 +1,179 @@
+//! Record authorization decisions for an audit trail.
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use polar_core::rules::Rule;
+use polar_core::sources::Context;
+use polar_core::terms::Term;
+use serde::{Serialize, Serializer};
+
+/// A decision made by `Oso::is_allowed`, `Oso::query_rule`, or any method
+/// built on them.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Decision {
+    /// When the query was made.
+    #[serde(rename = "timestamp_ms", serialize_with = "unix_millis")]
+    pub timestamp: SystemTime,
+    /// The rule queried, e.g. `allow`.
+    pub rule: String,
+    /// The arguments to the rule. For `allow` these are the actor, action
+    /// and resource.
+    pub args: Vec<String>,
+    /// Whether the query had a result.
+    pub allowed: bool,
+    /// The error the query failed with, if any.
+    pub error: Option<String>,
+    /// How long the query took to reach its decision.
+    #[serde(rename = "elapsed_us", serialize_with = "micros")]
+    pub elapsed: Duration,
+    /// Where the rule that matched is defined.
+    pub source: Option<RuleSource>,
+    /// Whether the decision came from the decision cache.
+    pub cached: bool,
+}
+
+/// The location of a rule, or another part of a policy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleSource {
+    pub filename: Option<String>,
+    /// One-based line number.
+    pub line: usize,
+    /// One-based column number.
+    pub column: usize,
+}
+
+impl RuleSource {
+    pub(crate) fn of(rule: &Rule) -> Option<Self> {
+        rule.parsed_context().map(Self::from_context)
+    }
+
+    pub(crate) fn of_term(term: &Term) -> Option<Self> {
+        term.parsed_context().map(Self::from_context)
+    }
+
+    fn from_context(context: &Context) -> Self {
+        let (line, column) = context.position();
+        Self {
+            filename: context.source.filename.clone(),
+            line: line + 1,
+            column: column + 1,
+        }
+    }
+}
+
+fn unix_millis<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    serializer.serialize_u64(millis as u64)
+}
+
+fn micros<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_micros() as u64)
+}
+
+/// Receives every decision made by an `Oso`, set with `Oso::set_audit_sink`.
+///
+/// `record` is called on the thread that made the query, before the
+/// decision is returned, so it should be quick.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, decision: &Decision);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&Decision) + Send + Sync,
+{
+    fn record(&self, decision: &Decision) {
+        self(decision)
+    }
+}
+
+/// Writes each decision to `W` as one line of JSON.
+/// # Examples
+/// ```ignore
+/// let log = OpenOptions::new().append(true).create(true).open("audit.jsonl")?;
+/// oso.set_audit_sink(JsonLinesSink::new(log));
+/// ```
+pub struct JsonLinesSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+
+    /// Take back the writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<W: Write + Send> AuditSink for JsonLinesSink<W> {
+    fn record(&self, decision: &Decision) {
+        let mut line = serde_json::to_vec(decision).expect("decisions are always serializable");
+        line.push(b'\n');
+        let mut writer = self
+            .writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            tracing::error!("failed to write audit log: {}", e);
+        }
+    }
+}
+
+/// The pending decision of a query, recorded when it first has an answer.
+pub(crate) struct Audit {
+    sink: Arc<dyn AuditSink>,
+    timestamp: SystemTime,
+    start: Instant,
+    rule: String,
+    args: Vec<String>,
+}
+
+impl Audit {
+    pub(crate) fn new(sink: Arc<dyn AuditSink>, rule: &str, args: Vec<String>) -> Self {
+        Self {
+            sink,
+            timestamp: SystemTime::now(),
+            start: Instant::now(),
+            rule: rule.to_owned(),
+            args,
+        }
+    }
+
+    pub(crate) fn record(self, allowed: bool, error: Option<String>, source: Option<RuleSource>) {
+        self.finish(allowed, error, source, false)
+    }
+
+    pub(crate) fn record_cached(self, allowed: bool, source: Option<RuleSource>) {
+        self.finish(allowed, None, source, true)
+    }
+
+    fn finish(
+        self,
+        allowed: bool,
+        error: Option<String>,
+        source: Option<RuleSource>,
+        cached: bool,
+    ) {
+        self.sink.record(&Decision {
+            timestamp: self.timestamp,
+            rule: self.rule,
+            args: self.args,
+            allowed,
+            error,
+            elapsed: self.start.elapsed(),
+            source,
+            cached,
+        })
+    }
+}
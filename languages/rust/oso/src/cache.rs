@@ -0,0 +1,137 @@
+//! Memoize `Oso::is_allowed` decisions.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::audit::RuleSource;
+use crate::host::Host;
+use crate::PolarValue;
+
+/// Sweep expired decisions once the cache grows past this many entries.
+const MIN_SWEEP_SIZE: usize = 1024;
+
+/// One argument of a cached decision.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+enum KeyPart {
+    Integer(i64),
+    /// The bits of the float, so that it can be hashed.
+    Float(u64),
+    String(String),
+    Boolean(bool),
+    /// An instance of a class with a cache key, see `ClassBuilder::set_cache_key`.
+    Instance {
+        class: String,
+        key: String,
+    },
+}
+
+impl KeyPart {
+    fn new(value: &PolarValue, host: &Host) -> Option<Self> {
+        match value {
+            PolarValue::Integer(i) => Some(Self::Integer(*i)),
+            PolarValue::Float(f) => Some(Self::Float(f.to_bits())),
+            PolarValue::String(s) => Some(Self::String(s.clone())),
+            PolarValue::Boolean(b) => Some(Self::Boolean(*b)),
+            PolarValue::Instance(instance) => {
+                let class = instance.class(host).ok()?;
+                let key = class.cache_key(host, instance)?;
+                Some(Self::Instance {
+                    class: class.name.clone(),
+                    key,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The arguments of a decision, if they can all be cached.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub(crate) struct DecisionKey(Vec<KeyPart>);
+
+impl DecisionKey {
+    pub(crate) fn new(args: &[&PolarValue], host: &Host) -> Option<Self> {
+        args.iter()
+            .map(|arg| KeyPart::new(arg, host))
+            .collect::<Option<_>>()
+            .map(Self)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct CachedDecision {
+    pub allowed: bool,
+    /// The rule that allowed the decision.
+    pub source: Option<RuleSource>,
+}
+
+struct Entries {
+    decisions: HashMap<DecisionKey, (CachedDecision, Instant)>,
+    /// The size at which to next sweep out expired decisions.
+    sweep_at: usize,
+}
+
+/// Decisions made in the last `ttl`.
+pub(crate) struct DecisionCache {
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl DecisionCache {
+    pub(crate) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(Entries {
+                decisions: HashMap::new(),
+                sweep_at: MIN_SWEEP_SIZE,
+            }),
+        }
+    }
+
+    pub(crate) fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(crate) fn get(&self, key: &DecisionKey) -> Option<CachedDecision> {
+        let mut entries = self.entries();
+        match entries.decisions.get(key) {
+            Some((decision, expires)) if *expires > Instant::now() => Some(decision.clone()),
+            Some(_) => {
+                entries.decisions.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, key: DecisionKey, decision: CachedDecision) {
+        let now = Instant::now();
+        let mut entries = self.entries();
+        if entries.decisions.len() >= entries.sweep_at {
+            entries.decisions.retain(|_, (_, expires)| *expires > now);
+            entries.sweep_at = MIN_SWEEP_SIZE.max(2 * entries.decisions.len());
+        }
+        entries.decisions.insert(key, (decision, now + self.ttl));
+    }
+
+    /// Forget every decision.
+    pub(crate) fn clear(&self) {
+        self.entries().decisions.clear()
+    }
+
+    /// Forget the decisions with `value` as an argument. Values that have no
+    /// cache key are never cached.
+    pub(crate) fn invalidate(&self, value: &PolarValue, host: &Host) {
+        if let Some(part) = KeyPart::new(value, host) {
+            self.entries()
+                .decisions
+                .retain(|key, _| !key.0.contains(&part));
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Evaluate filters against application instances in memory.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use polar_core::filter::{Comparison, Condition, Datum, Projection, Relation as FilterRelation};
+
+use super::Filter;
+use crate::{PolarValue, ToPolar};
+
+/// The instances bound to each class in the filter, for one combination of related instances.
+type Row = HashMap<String, PolarValue>;
+
+impl Filter {
+    /// Check whether `resource`, an instance of the filter's root class, passes the filter.
+    ///
+    /// Fields are read through the attribute getters registered on each class.
+    /// A relation is followed through the attribute getter with the same name
+    /// as the relation, which returns the related instance, or a list of them
+    /// for a relation to many instances. Application instances are compared
+    /// with the equality check registered on their class.
+    pub fn matches<R: ToPolar>(&self, resource: R) -> crate::Result<bool> {
+        let mut row = Row::new();
+        row.insert(self.root().to_owned(), resource.to_polar());
+        self.matches_row(row, self.relations())
+    }
+
+    /// Keep the resources that pass the filter.
+    /// # Examples
+    /// ```ignore
+    /// let repos = oso.authorized_resources(user, "read", "Repository", |filter| {
+    ///     filter.apply(repos.iter().cloned())
+    /// })?;
+    /// ```
+    pub fn apply<I, R>(&self, resources: I) -> crate::Result<Vec<R>>
+    where
+        I: IntoIterator<Item = R>,
+        R: ToPolar + Clone,
+    {
+        let mut matching = vec![];
+        for resource in resources {
+            if self.matches(resource.clone())? {
+                matching.push(resource);
+            }
+        }
+        Ok(matching)
+    }
+
+    /// Join `relations` onto `row` one at a time, then check the conditions
+    /// against every complete row. A relation without related instances
+    /// binds `nil`, like a SQL `LEFT JOIN`, so the conditions that don't use
+    /// it are still checked.
+    fn matches_row(&self, row: Row, relations: &[FilterRelation]) -> crate::Result<bool> {
+        let (FilterRelation(from, field, to), rest) = match relations.split_first() {
+            Some(split) => split,
+            None => return self.check_conditions(&row),
+        };
+        let source = row
+            .get(from)
+            .expect("relations are ordered from the root of the filter");
+        let mut related = match option(source) {
+            Some(None) => vec![],
+            _ => match self.get_attr(source, field)? {
+                PolarValue::List(values) => values,
+                value => match option(&value) {
+                    Some(None) => vec![],
+                    Some(Some(value)) => vec![value.clone()],
+                    None => vec![value],
+                },
+            },
+        };
+        if related.is_empty() {
+            related.push(None::<PolarValue>.to_polar());
+        }
+        for value in related {
+            let mut row = row.clone();
+            row.insert(to.clone(), value);
+            if self.matches_row(row, rest)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn check_conditions(&self, row: &Row) -> crate::Result<bool> {
+        for conjunction in self.conditions() {
+            let mut all = true;
+            for condition in conjunction {
+                if !self.check_condition(row, condition)? {
+                    all = false;
+                    break;
+                }
+            }
+            if all {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn check_condition(&self, row: &Row, condition: &Condition) -> crate::Result<bool> {
+        use Comparison::*;
+        let Condition(left, op, right) = condition;
+        // Fields of a relation without related instances match nothing.
+        if is_missing_field(row, left) || is_missing_field(row, right) {
+            return Ok(false);
+        }
+        let (left, right) = (self.datum(row, left)?, self.datum(row, right)?);
+        match op {
+            Eq => self.equal(&left, &right),
+            Neq => self.equal(&left, &right).map(|eq| !eq),
+            In => self.contains(&right, &left),
+            Nin => self.contains(&right, &left).map(|contains| !contains),
+            Lt | Leq | Gt | Geq => {
+                let ordering = compare(&left, &right).ok_or_else(|| {
+                    crate::OsoError::UnsupportedOperation {
+                        operation: op.to_string(),
+                        type_name: format!("{:?}", left),
+                    }
+                })?;
+                Ok(match op {
+                    Lt => ordering == Ordering::Less,
+                    Leq => ordering != Ordering::Greater,
+                    Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                })
+            }
+        }
+    }
+
+    fn datum(&self, row: &Row, datum: &Datum) -> crate::Result<PolarValue> {
+        match datum {
+            Datum::Immediate(value) => self.to_polar_value(value),
+            Datum::Field(Projection(class_tag, field)) => {
+                let instance = match row.get(class_tag) {
+                    Some(instance) => instance,
+                    None => return lazy_error!("No instance of {} in filter row.", class_tag),
+                };
+                match field {
+                    Some(field) => self.get_attr(instance, field),
+                    None => Ok(instance.clone()),
+                }
+            }
+        }
+    }
+
+    fn contains(&self, collection: &PolarValue, item: &PolarValue) -> crate::Result<bool> {
+        match collection {
+            PolarValue::List(items) => {
+                for other in items {
+                    if self.equal(item, other)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            _ => Err(crate::OsoError::UnsupportedOperation {
+                operation: "in".to_owned(),
+                type_name: format!("{:?}", collection),
+            }),
+        }
+    }
+
+    fn equal(&self, left: &PolarValue, right: &PolarValue) -> crate::Result<bool> {
+        match (left, right) {
+            (PolarValue::Integer(i), PolarValue::Float(f))
+            | (PolarValue::Float(f), PolarValue::Integer(i)) => Ok(*i as f64 == *f),
+            (PolarValue::Instance(_), _) | (_, PolarValue::Instance(_)) => {
+                match (option(left), option(right)) {
+                    (Some(None), Some(None)) => Ok(true),
+                    (Some(None), _) | (_, Some(None)) => Ok(false),
+                    (Some(Some(left)), _) => self.equal(left, right),
+                    (_, Some(Some(right))) => self.equal(left, right),
+                    (None, None) => match (left, right) {
+                        (PolarValue::Instance(l), PolarValue::Instance(r)) => {
+                            l.equals(r, &self.host)
+                        }
+                        _ => Ok(false),
+                    },
+                }
+            }
+            (PolarValue::List(l), PolarValue::List(r)) => {
+                if l.len() != r.len() {
+                    return Ok(false);
+                }
+                for (l, r) in l.iter().zip(r) {
+                    if !self.equal(l, r)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            _ => Ok(left == right),
+        }
+    }
+}
+
+/// Whether `datum` is a field of an instance that `row` binds to `nil`.
+fn is_missing_field(row: &Row, datum: &Datum) -> bool {
+    match datum {
+        Datum::Field(Projection(class_tag, Some(_))) => {
+            matches!(row.get(class_tag).map(option), Some(Some(None)))
+        }
+        _ => false,
+    }
+}
+
+/// If `value` is an `Option` (e.g. `nil`), return its contents.
+fn option(value: &PolarValue) -> Option<Option<&PolarValue>> {
+    match value {
+        PolarValue::Instance(instance) => instance
+            .downcast::<Option<PolarValue>>(None)
+            .ok()
+            .map(Option::as_ref),
+        _ => None,
+    }
+}
+
+fn compare(left: &PolarValue, right: &PolarValue) -> Option<Ordering> {
+    match (left, right) {
+        (PolarValue::Integer(l), PolarValue::Integer(r)) => Some(l.cmp(r)),
+        (PolarValue::Float(l), PolarValue::Float(r)) => l.partial_cmp(r),
+        (PolarValue::Integer(l), PolarValue::Float(r)) => (*l as f64).partial_cmp(r),
+        (PolarValue::Float(l), PolarValue::Integer(r)) => l.partial_cmp(&(*r as f64)),
+        (PolarValue::String(l), PolarValue::String(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
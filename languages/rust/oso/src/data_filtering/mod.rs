@@ -0,0 +1,233 @@
+//! Data filtering: turn a policy into a filter over a collection of resources.
+//!
+//! Classes declare the fields and relations that may appear in a filter
+//! with `ClassBuilder::add_field` and `ClassBuilder::add_relation`.
+//! `Oso::authorized_query` then runs a partial query for
+//! `allow(actor, action, resource)` and returns a [`Filter`] describing
+//! which resources of a given class the actor may access. Pass the filter
+//! to an [`Adapter`] to query a data source, or use [`Filter::apply`] to
+//! filter instances already in memory.
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use polar_core::data_filtering::{Type, Types};
+use polar_core::terms::{Term, Value};
+
+use crate::errors::TypeError;
+use crate::host::Host;
+use crate::PolarValue;
+
+mod memory;
+mod sql;
+
+pub use sql::{SqlAdapter, SqlQuery, SqlValue};
+
+pub use polar_core::filter::Relation as FilterRelation;
+pub use polar_core::filter::{Comparison, Condition, Datum, Projection};
+
+/// Whether a relation points at one or many instances of the other type.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum RelationKind {
+    One,
+    Many,
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RelationKind::One => write!(f, "one"),
+            RelationKind::Many => write!(f, "many"),
+        }
+    }
+}
+
+/// A relation between two classes registered with Oso.
+///
+/// `my_field` on this class holds the same value as `other_field` on
+/// `other_type`. For example, a `Repository` with an `org_id` field
+/// relates to one `Organization` through its `id` field:
+///
+/// ```
+/// # use oso::Relation;
+/// let org = Relation::one("Organization", "org_id", "id");
+/// ```
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub other_type: String,
+    pub my_field: String,
+    pub other_field: String,
+}
+
+impl Relation {
+    pub fn new(kind: RelationKind, other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self {
+            kind,
+            other_type: other_type.to_owned(),
+            my_field: my_field.to_owned(),
+            other_field: other_field.to_owned(),
+        }
+    }
+
+    /// A relation to a single instance of `other_type`.
+    pub fn one(other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self::new(RelationKind::One, other_type, my_field, other_field)
+    }
+
+    /// A relation to any number of instances of `other_type`.
+    pub fn many(other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self::new(RelationKind::Many, other_type, my_field, other_field)
+    }
+}
+
+/// A field declared on a class for data filtering.
+#[derive(Clone, Debug)]
+pub(crate) enum Field {
+    /// A plain value, an instance of the class named `class_tag`.
+    Base {
+        class_tag: String,
+    },
+    Relation(Relation),
+}
+
+impl Field {
+    /// The name of the class this field holds.
+    pub(crate) fn class_tag(&self) -> &str {
+        match self {
+            Field::Base { class_tag } => class_tag,
+            Field::Relation(relation) => &relation.other_type,
+        }
+    }
+
+    fn to_type(&self) -> Type {
+        match self {
+            Field::Base { class_tag } => Type::Base {
+                class_tag: class_tag.clone(),
+            },
+            Field::Relation(relation) => Type::Relation {
+                kind: relation.kind.to_string(),
+                other_class_tag: relation.other_type.clone(),
+                my_field: relation.my_field.clone(),
+                other_field: relation.other_field.clone(),
+            },
+        }
+    }
+}
+
+/// Convert the fields declared on every registered class to what the core expects.
+pub(crate) fn serialize_types(host: &Host) -> Types {
+    host.classes()
+        .filter(|class| !class.fields.is_empty())
+        .map(|class| {
+            let fields = class
+                .fields
+                .iter()
+                .map(|(name, field)| (name.to_string(), field.to_type()))
+                .collect();
+            (class.name.clone(), fields)
+        })
+        .collect()
+}
+
+/// A filter over the resources of one class, produced by `Oso::authorized_query`.
+///
+/// The filter is rooted at the resource class and may join through any
+/// relations reached by the policy. Its conditions are in disjunctive normal
+/// form: a resource passes the filter if every condition in at least one of
+/// the sets holds.
+///
+/// Immediate values in conditions may refer to application instances (e.g.
+/// the actor); use [`Filter::to_polar_value`] to recover them.
+#[derive(Clone)]
+pub struct Filter {
+    inner: polar_core::filter::Filter,
+    host: Host,
+}
+
+impl Filter {
+    pub(crate) fn new(inner: polar_core::filter::Filter, host: Host) -> Self {
+        Self { inner, host }
+    }
+
+    /// The name of the class being filtered.
+    pub fn root(&self) -> &str {
+        self.inner.root()
+    }
+
+    /// Relations joined from the root class. The source of each relation is
+    /// either the root or the target of an earlier relation.
+    pub fn relations(&self) -> &[FilterRelation] {
+        self.inner.relations()
+    }
+
+    /// Conditions in disjunctive normal form.
+    pub fn conditions(&self) -> &[HashSet<Condition>] {
+        self.inner.conditions()
+    }
+
+    /// Convert an immediate value from a condition into a `PolarValue`.
+    pub fn to_polar_value(&self, value: &Value) -> crate::Result<PolarValue> {
+        PolarValue::from_term(&Term::new_temporary(value.clone()), &self.host)
+    }
+
+    /// Look up the attribute `name` on an application instance from a condition.
+    pub fn get_attr(&self, value: &PolarValue, name: &str) -> crate::Result<PolarValue> {
+        match value {
+            PolarValue::Instance(instance) => instance.get_attr(name, &self.host),
+            _ => Err(TypeError::expected("Instance").user()),
+        }
+    }
+
+    /// The registered class name of an application instance from a condition.
+    pub fn class_name(&self, value: &PolarValue) -> crate::Result<String> {
+        match value {
+            PolarValue::Instance(instance) => Ok(instance.class(&self.host)?.name.clone()),
+            _ => Err(TypeError::expected("Instance").user()),
+        }
+    }
+
+    /// Look up the relation declared as `field` on the class `class_tag`.
+    pub fn relation(&self, class_tag: &str, field: &str) -> crate::Result<&Relation> {
+        match self.host.get_class(class_tag)?.fields.get(field) {
+            Some(Field::Relation(relation)) => Ok(relation),
+            _ => lazy_error!("No relation {} declared on class {}.", field, class_tag),
+        }
+    }
+
+    /// Return the underlying `polar_core` filter.
+    pub fn into_inner(self) -> polar_core::filter::Filter {
+        self.inner
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.inner)
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.inner)
+    }
+}
+
+/// Turns a [`Filter`] into a query against a data source.
+///
+/// Use an adapter with `Oso::authorized_resources` to fetch the resources a
+/// filter matches:
+///
+/// ```ignore
+/// let repos = oso.authorized_resources(user, "read", "Repository", |filter| {
+///     let query = adapter.build_query(filter)?;
+///     run(query)
+/// })?;
+/// ```
+pub trait Adapter {
+    type Query;
+
+    fn build_query(&self, filter: &Filter) -> crate::Result<Self::Query>;
+}
+
+/// Fields declared on a class, by name.
+pub(crate) type Fields = HashMap<&'static str, Field>;
@@ -0,0 +1,320 @@
+//! A reference `Adapter` that renders filters to parameterized SQL.
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use polar_core::filter::{Comparison, Condition, Datum, Projection, Relation as FilterRelation};
+
+use super::{Adapter, Filter};
+use crate::PolarValue;
+
+/// A parameter bound to a `?` placeholder in a `SqlQuery`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(String),
+}
+
+/// A SQL statement with positional `?` placeholders, and the values to bind to them in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqlQuery {
+    pub sql: String,
+    pub params: Vec<SqlValue>,
+}
+
+/// Where instances of a class are stored.
+#[derive(Clone, Debug)]
+struct Table {
+    name: String,
+    primary_key: String,
+    columns: HashMap<String, String>,
+}
+
+impl Table {
+    fn new(class_tag: &str) -> Self {
+        Self {
+            name: class_tag.to_owned(),
+            primary_key: "id".to_owned(),
+            columns: HashMap::new(),
+        }
+    }
+
+    fn column<'a>(&'a self, field: &'a str) -> &'a str {
+        self.columns.get(field).map_or(field, String::as_str)
+    }
+}
+
+/// Renders a `Filter` to a `SELECT` statement over the root class's table.
+///
+/// Each class is stored in a table named after the class, with a column for
+/// each field named after the field and an `id` primary key, unless
+/// configured otherwise:
+///
+/// ```
+/// # use oso::data_filtering::SqlAdapter;
+/// let adapter = SqlAdapter::new()
+///     .table("Repository", "repos")
+///     .column("Repository", "org_id", "organization_id")
+///     .primary_key("Repository", "name");
+/// ```
+///
+/// Relations become `LEFT JOIN`s on the relation's `my_field` and
+/// `other_field` columns, so a row without a related row can still match
+/// conditions that don't use the relation. Each joined table is aliased by
+/// the path of relations to it, like `"repos_org"`, so a table can be joined
+/// more than once. A class reached through more than one relation is an
+/// error, since the conditions can't say which one they refer to.
+/// Application instances in conditions are compared by their primary key
+/// field.
+#[derive(Clone, Debug, Default)]
+pub struct SqlAdapter {
+    tables: HashMap<String, Table>,
+}
+
+impl SqlAdapter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store instances of the class `class_tag` in `table`.
+    pub fn table(mut self, class_tag: &str, table: &str) -> Self {
+        self.table_mut(class_tag).name = table.to_owned();
+        self
+    }
+
+    /// Store the field `field` of the class `class_tag` in `column`.
+    pub fn column(mut self, class_tag: &str, field: &str, column: &str) -> Self {
+        self.table_mut(class_tag)
+            .columns
+            .insert(field.to_owned(), column.to_owned());
+        self
+    }
+
+    /// Identify instances of the class `class_tag` by the field `field`.
+    pub fn primary_key(mut self, class_tag: &str, field: &str) -> Self {
+        self.table_mut(class_tag).primary_key = field.to_owned();
+        self
+    }
+
+    fn table_mut(&mut self, class_tag: &str) -> &mut Table {
+        self.tables
+            .entry(class_tag.to_owned())
+            .or_insert_with(|| Table::new(class_tag))
+    }
+
+    fn table_for(&self, class_tag: &str) -> Table {
+        self.tables
+            .get(class_tag)
+            .cloned()
+            .unwrap_or_else(|| Table::new(class_tag))
+    }
+}
+
+impl Adapter for SqlAdapter {
+    type Query = SqlQuery;
+
+    fn build_query(&self, filter: &Filter) -> crate::Result<SqlQuery> {
+        QueryBuilder::new(self, filter)?.build()
+    }
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// A fragment of SQL along with the parameters its placeholders refer to.
+type Fragment = (String, Vec<SqlValue>);
+
+struct QueryBuilder<'a> {
+    adapter: &'a SqlAdapter,
+    filter: &'a Filter,
+    /// The name each class's table is selected as.
+    aliases: HashMap<String, String>,
+}
+
+impl<'a> QueryBuilder<'a> {
+    fn new(adapter: &'a SqlAdapter, filter: &'a Filter) -> crate::Result<Self> {
+        let root = filter.root();
+        let mut aliases = HashMap::new();
+        aliases.insert(root.to_owned(), adapter.table_for(root).name);
+        for FilterRelation(from, field, to) in filter.relations() {
+            if aliases.contains_key(to) {
+                return lazy_error!(
+                    "Can't join {} more than once in a SQL filter, through {}.{}.",
+                    to,
+                    from,
+                    field
+                );
+            }
+            let alias = format!("{}_{}", aliases[from], field);
+            aliases.insert(to.clone(), alias);
+        }
+        Ok(Self {
+            adapter,
+            filter,
+            aliases,
+        })
+    }
+
+    fn build(&self) -> crate::Result<SqlQuery> {
+        let root = quote(&self.aliases[self.filter.root()]);
+        let mut sql = format!("SELECT DISTINCT {}.* FROM {}", root, root);
+        for relation in self.filter.relations() {
+            write!(sql, " {}", self.join(relation)?).unwrap();
+        }
+
+        let mut params = vec![];
+        let mut disjuncts = vec![];
+        for conjunction in self.filter.conditions() {
+            let mut conjuncts = conjunction
+                .iter()
+                .map(|condition| self.condition(condition))
+                .collect::<crate::Result<Vec<Fragment>>>()?;
+            // Conditions are unordered; sort them so the output is stable.
+            conjuncts.sort_by_cached_key(|(sql, params)| (sql.clone(), format!("{:?}", params)));
+            let sql = if conjuncts.is_empty() {
+                "1 = 1".to_owned()
+            } else {
+                conjuncts
+                    .iter()
+                    .map(|(sql, _)| sql.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            };
+            disjuncts.push(format!("({})", sql));
+            params.extend(conjuncts.into_iter().flat_map(|(_, params)| params));
+        }
+        if !disjuncts.is_empty() {
+            write!(sql, " WHERE {}", disjuncts.join(" OR ")).unwrap();
+        }
+
+        Ok(SqlQuery { sql, params })
+    }
+
+    fn join(&self, FilterRelation(from, field, to): &FilterRelation) -> crate::Result<String> {
+        let relation = self.filter.relation(from, field)?;
+        let (from_alias, to_alias) = (quote(&self.aliases[from]), quote(&self.aliases[to]));
+        let (from, to) = (self.adapter.table_for(from), self.adapter.table_for(to));
+        Ok(format!(
+            "LEFT JOIN {} AS {} ON {}.{} = {}.{}",
+            quote(&to.name),
+            to_alias,
+            from_alias,
+            quote(from.column(&relation.my_field)),
+            to_alias,
+            quote(to.column(&relation.other_field)),
+        ))
+    }
+
+    fn column(&self, Projection(class_tag, field): &Projection) -> String {
+        let table = self.adapter.table_for(class_tag);
+        let field = field.as_deref().unwrap_or(&table.primary_key);
+        let alias = self.aliases.get(class_tag).unwrap_or(&table.name);
+        format!("{}.{}", quote(alias), quote(table.column(field)))
+    }
+
+    fn condition(&self, condition: &Condition) -> crate::Result<Fragment> {
+        use Comparison::*;
+        let Condition(left, op, right) = condition;
+        let (mut left, mut op, mut right) = (left, *op, right);
+        // Render `1 = repo.id` as `repo.id = 1`.
+        if matches!((left, right), (Datum::Immediate(_), Datum::Field(_))) {
+            let flipped = match op {
+                Lt => Some(Gt),
+                Leq => Some(Geq),
+                Gt => Some(Lt),
+                Geq => Some(Leq),
+                Eq | Neq => Some(op),
+                In | Nin => None,
+            };
+            if let Some(flipped) = flipped {
+                std::mem::swap(&mut left, &mut right);
+                op = flipped;
+            }
+        }
+        match op {
+            Eq | Neq => {
+                let (left, right) = (self.scalar(left)?, self.scalar(right)?);
+                let (sql, params) = match (left, right) {
+                    ((sql, params), (_, null)) | ((_, null), (sql, params))
+                        if null == [SqlValue::Null] =>
+                    {
+                        let test = if op == Eq { "IS NULL" } else { "IS NOT NULL" };
+                        (format!("{} {}", sql, test), params)
+                    }
+                    ((l, mut lp), (r, rp)) => {
+                        lp.extend(rp);
+                        (
+                            format!("{} {} {}", l, if op == Eq { "=" } else { "<>" }, r),
+                            lp,
+                        )
+                    }
+                };
+                Ok((sql, params))
+            }
+            In | Nin => {
+                let (sql, mut params) = self.scalar(left)?;
+                let values = match right {
+                    Datum::Immediate(value) => match self.filter.to_polar_value(value)? {
+                        PolarValue::List(values) => values
+                            .iter()
+                            .map(|value| self.value(value))
+                            .collect::<crate::Result<Vec<_>>>()?,
+                        _ => return unsupported(condition),
+                    },
+                    _ => return unsupported(condition),
+                };
+                if values.is_empty() {
+                    let sql = if op == In { "1 = 0" } else { "1 = 1" };
+                    return Ok((sql.to_owned(), vec![]));
+                }
+                let placeholders = vec!["?"; values.len()].join(", ");
+                let op = if op == In { "IN" } else { "NOT IN" };
+                params.extend(values);
+                Ok((format!("{} {} ({})", sql, op, placeholders), params))
+            }
+            Lt | Leq | Gt | Geq => {
+                let ((l, mut params), (r, rp)) = (self.scalar(left)?, self.scalar(right)?);
+                params.extend(rp);
+                Ok((format!("{} {} {}", l, op, r), params))
+            }
+        }
+    }
+
+    fn scalar(&self, datum: &Datum) -> crate::Result<Fragment> {
+        match datum {
+            Datum::Field(projection) => Ok((self.column(projection), vec![])),
+            Datum::Immediate(value) => {
+                let value = self.value(&self.filter.to_polar_value(value)?)?;
+                Ok(("?".to_owned(), vec![value]))
+            }
+        }
+    }
+
+    fn value(&self, value: &PolarValue) -> crate::Result<SqlValue> {
+        match value {
+            PolarValue::Integer(i) => Ok(SqlValue::Integer(*i)),
+            PolarValue::Float(f) => Ok(SqlValue::Float(*f)),
+            PolarValue::String(s) => Ok(SqlValue::Text(s.clone())),
+            PolarValue::Boolean(b) => Ok(SqlValue::Boolean(*b)),
+            PolarValue::Instance(instance) => {
+                if let Ok(option) = instance.downcast::<Option<PolarValue>>(None) {
+                    return match option {
+                        Some(value) => self.value(value),
+                        None => Ok(SqlValue::Null),
+                    };
+                }
+                // Compare application instances by their primary key.
+                let table = self.adapter.table_for(&self.filter.class_name(value)?);
+                self.value(&self.filter.get_attr(value, &table.primary_key)?)
+            }
+            _ => lazy_error!("Unsupported value in SQL filter: {:?}", value),
+        }
+    }
+}
+
+fn unsupported<T>(condition: &Condition) -> crate::Result<T> {
+    lazy_error!("Unsupported condition in SQL filter: {}", condition)
+}
@@ -58,6 +58,16 @@ pub enum OsoError {
     #[error("Tried to find an instance that doesn't exist -- internal error")]
     MissingInstanceError,
 
+    /// The actor is not allowed to read the resource. Most of the time, this
+    /// should be handled by returning a 404 HTTP error to the client.
+    #[error("Oso NotFoundError -- The current user does not have permission to read the given resource. You should handle this error by returning a 404 error to the client.")]
+    NotFound,
+
+    /// The actor is not allowed to perform the action. Most of the time, this
+    /// should be handled by returning a 403 HTTP error to the client.
+    #[error("Oso ForbiddenError -- The requested action was not allowed for the given resource. You should handle this error by returning a 403 error to the client.")]
+    Forbidden,
+
     /// TODO: replace all these with proper variants
     #[error("{message}")]
     Custom { message: String },
@@ -3,12 +3,14 @@
 use std::any::TypeId;
 use std::collections::HashMap;
 use std::fmt;
+use std::future::Future;
 use std::sync::Arc;
 
+use crate::data_filtering::{Field, Fields, Relation};
 use crate::errors::{InvalidCallError, OsoError};
 
 use super::class_method::{
-    AttributeGetter, ClassMethod, Constructor, InstanceMethod, RegisterHook,
+    AsyncMethod, AttributeGetter, ClassMethod, Constructor, InstanceMethod, RegisterHook,
 };
 use super::from_polar::FromPolarList;
 use super::method::{Function, Method};
@@ -20,10 +22,12 @@ type Attributes = HashMap<&'static str, AttributeGetter>;
 type RegisterHooks = Vec<RegisterHook>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
+type AsyncMethods = HashMap<&'static str, AsyncMethod>;
 
 type EqualityMethod = Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync>;
 type IteratorMethod =
     Arc<dyn Fn(&Host, &Instance) -> crate::Result<crate::host::PolarIterator> + Send + Sync>;
+type CacheKeyMethod = Arc<dyn Fn(&Host, &Instance) -> Option<String> + Send + Sync>;
 
 fn equality_not_supported() -> EqualityMethod {
     let eq = move |host: &Host, lhs: &Instance, _: &Instance| -> crate::Result<bool> {
@@ -60,6 +64,10 @@ pub struct Class {
     instance_methods: InstanceMethods,
     /// Class methods on `T`
     class_methods: ClassMethods,
+    /// Attribute getters on `T` that return futures
+    async_attributes: AsyncMethods,
+    /// Instance methods on `T` that return futures
+    async_methods: AsyncMethods,
 
     /// A function that accepts arguments of this class and compares them for equality.
     /// Limitation: Only works on comparisons of the same type.
@@ -67,6 +75,12 @@ pub struct Class {
 
     into_iter: IteratorMethod,
 
+    /// Identifies instances in the decision cache, if they may be cached.
+    cache_key: Option<CacheKeyMethod>,
+
+    /// Fields and relations available to data filtering
+    pub(crate) fields: Fields,
+
     // Hooks to be called on the class once it's been registered with host.
     pub register_hooks: RegisterHooks,
 }
@@ -111,6 +125,20 @@ impl Class {
         }
     }
 
+    /// Look up an async attribute getter, or an async method if `args` are given.
+    pub(crate) fn get_async_method(&self, name: &str, has_args: bool) -> Option<AsyncMethod> {
+        if has_args {
+            self.async_methods.get(name).cloned()
+        } else {
+            self.async_attributes.get(name).cloned()
+        }
+    }
+
+    /// The key of `instance` in the decision cache, if it has one.
+    pub(crate) fn cache_key(&self, host: &Host, instance: &Instance) -> Option<String> {
+        self.cache_key.as_ref().and_then(|f| f(host, instance))
+    }
+
     fn equals(&self, host: &Host, lhs: &Instance, rhs: &Instance) -> crate::Result<bool> {
         // equality checking is currently only supported for exactly matching types
         // TODO: support multiple dispatch for equality
@@ -144,8 +172,12 @@ where
                 attributes: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
                 class_methods: ClassMethods::new(),
+                async_attributes: AsyncMethods::new(),
+                async_methods: AsyncMethods::new(),
                 equality_check: equality_not_supported(),
                 into_iter: iterator_not_supported(),
+                cache_key: None,
+                fields: Fields::new(),
                 type_id: TypeId::of::<T>(),
                 register_hooks: RegisterHooks::new(),
             },
@@ -202,6 +234,22 @@ where
         self
     }
 
+    /// Set a function that identifies instances in the decision cache, e.g.
+    /// by their id. Decisions about instances of classes without a cache key
+    /// are never cached. See `Oso::with_decision_cache`.
+    pub fn set_cache_key<F, K>(mut self, f: F) -> Self
+    where
+        F: Fn(&T) -> K + Send + Sync + 'static,
+        K: ToString,
+    {
+        self.class.cache_key = Some(Arc::new(move |host, instance| {
+            let instance = instance.downcast(Some(host)).ok()?;
+            Some((f)(instance).to_string())
+        }));
+
+        self
+    }
+
     /// Set a method to convert instances into iterators
     pub fn set_into_iter<F, I, V>(mut self, f: F) -> Self
     where
@@ -250,6 +298,41 @@ where
         self
     }
 
+    /// Add an attribute getter that returns a future, for statements like `foo.bar`.
+    /// Queries that look up the attribute must be run with `Query::next_result_async`.
+    /// `class.add_async_attribute_getter("bar", |instance| fetch_bar(instance.id))`
+    pub fn add_async_attribute_getter<F, Fut>(mut self, name: &'static str, f: F) -> Self
+    where
+        F: Fn(&T) -> Fut + Send + Sync + 'static,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+        T: 'static,
+    {
+        self.class
+            .async_attributes
+            .insert(name, AsyncMethod::new(f));
+        self
+    }
+
+    /// Declare a field for data filtering, holding instances of the class `class_tag`.
+    /// `class.add_field("id", "Integer")`
+    pub fn add_field(mut self, name: &'static str, class_tag: &str) -> Self {
+        self.class.fields.insert(
+            name,
+            Field::Base {
+                class_tag: class_tag.to_owned(),
+            },
+        );
+        self
+    }
+
+    /// Declare a relation to another class for data filtering.
+    /// `class.add_relation("org", Relation::one("Organization", "org_id", "id"))`
+    pub fn add_relation(mut self, name: &'static str, relation: Relation) -> Self {
+        self.class.fields.insert(name, Field::Relation(relation));
+        self
+    }
+
     /// Set the name of the polar class.
     pub fn name(mut self, name: &str) -> Self {
         self.class.name = name.to_string();
@@ -301,6 +384,20 @@ where
         self
     }
 
+    /// Add a method that returns a future, for polar method calls like `foo.fetch(1)`.
+    /// Queries that call the method must be run with `Query::next_result_async`.
+    pub fn add_async_method<F, Args, Fut>(mut self, name: &'static str, f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Method<T, Args, Result = Fut>,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+        T: 'static,
+    {
+        self.class.async_methods.insert(name, AsyncMethod::new(f));
+        self
+    }
+
     /// A method that's called on the type instead of an instance.
     /// eg `Foo.pi`
     pub fn add_class_method<F, Args, R>(mut self, name: &'static str, f: F) -> Self
@@ -380,7 +477,7 @@ impl Instance {
     }
 
     /// Lookup an attribute on the instance via the registered `Class`
-    pub fn get_attr(&self, name: &str, host: &mut Host) -> crate::Result<PolarValue> {
+    pub fn get_attr(&self, name: &str, host: &Host) -> crate::Result<PolarValue> {
         tracing::trace!({ method = %name }, "get_attr");
         let attr = self
             .class(host)
@@ -1,4 +1,6 @@
 //! Wrapper structs for the generic `Function` and `Method` traits
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use crate::host::from_polar::FromPolarList;
@@ -16,6 +18,11 @@ type TypeErasedFunction<R> = Arc<dyn Fn(Vec<PolarValue>) -> crate::Result<R> + S
 type TypeErasedMethod<R> =
     Arc<dyn Fn(&Instance, Vec<PolarValue>, &mut Host) -> crate::Result<R> + Send + Sync>;
 
+/// The pending result of an async host call.
+pub type HostFuture = Pin<Box<dyn Future<Output = crate::Result<PolarValue>>>>;
+type TypeErasedAsyncMethod =
+    Arc<dyn Fn(&Instance, Vec<PolarValue>, &Host) -> crate::Result<HostFuture> + Send + Sync>;
+
 type RegisterMethod = Arc<dyn Fn(&mut crate::Oso) -> crate::Result<()> + Send + Sync + 'static>;
 
 #[derive(Clone)]
@@ -55,7 +62,7 @@ impl Constructor {
 }
 
 type AttributeGetterMethod =
-    Arc<dyn Fn(&Instance, &mut Host) -> crate::Result<PolarValue> + Send + Sync>;
+    Arc<dyn Fn(&Instance, &Host) -> crate::Result<PolarValue> + Send + Sync>;
 
 #[derive(Clone)]
 pub struct AttributeGetter(AttributeGetterMethod);
@@ -67,7 +74,7 @@ impl AttributeGetter {
         F: Fn(&T) -> R + Send + Sync + 'static,
         R: ToPolarResult,
     {
-        Self(Arc::new(move |receiver, host: &mut Host| {
+        Self(Arc::new(move |receiver, host: &Host| {
             let receiver = receiver
                 .downcast(Some(host))
                 .map_err(|e| e.invariant().into());
@@ -75,7 +82,7 @@ impl AttributeGetter {
         }))
     }
 
-    pub fn invoke(&self, receiver: &Instance, host: &mut Host) -> crate::Result<PolarValue> {
+    pub fn invoke(&self, receiver: &Instance, host: &Host) -> crate::Result<PolarValue> {
         self.0(receiver, host)
     }
 }
@@ -156,6 +163,45 @@ impl InstanceMethod {
     }
 }
 
+/// An instance method or attribute getter that returns a future.
+#[derive(Clone)]
+pub struct AsyncMethod(TypeErasedAsyncMethod);
+
+impl AsyncMethod {
+    pub fn new<T, F, Args, Fut>(f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Method<T, Args, Result = Fut>,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+        T: 'static,
+    {
+        Self(Arc::new(
+            move |receiver: &Instance, args: Vec<PolarValue>, host: &Host| {
+                let receiver = receiver
+                    .downcast(Some(host))
+                    .map_err(|e| e.invariant().into());
+
+                let args = Args::from_polar_list(&args);
+
+                join(receiver, args).map(|(receiver, args)| {
+                    let future = f.invoke(receiver, args);
+                    Box::pin(async move { future.await.to_polar_result() }) as HostFuture
+                })
+            },
+        ))
+    }
+
+    pub fn invoke(
+        &self,
+        receiver: &Instance,
+        args: Vec<PolarValue>,
+        host: &Host,
+    ) -> crate::Result<HostFuture> {
+        self.0(receiver, args, host)
+    }
+}
+
 #[derive(Clone)]
 pub struct ClassMethod(TypeErasedFunction<PolarValue>);
 
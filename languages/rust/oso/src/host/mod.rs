@@ -12,6 +12,7 @@ mod to_polar;
 mod value;
 
 pub use class::{Class, ClassBuilder, Instance};
+pub(crate) use class_method::HostFuture;
 pub use from_polar::{FromPolar, FromPolarList};
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
@@ -47,6 +48,9 @@ pub struct Host {
     /// class name it is registered as
     class_names: HashMap<std::any::TypeId, String>,
 
+    /// Map from class names to the instance IDs of the registered classes
+    class_ids: HashMap<String, u64>,
+
     pub accept_expression: bool,
 }
 
@@ -54,6 +58,7 @@ impl Host {
     pub fn new(polar: Arc<Polar>) -> Self {
         let mut host = Self {
             class_names: HashMap::new(),
+            class_ids: HashMap::new(),
             classes: HashMap::new(),
             instances: HashMap::new(),
             accept_expression: false,
@@ -66,6 +71,14 @@ impl Host {
         host
     }
 
+    /// A copy of this host that registers classes and instances with `polar` instead.
+    pub(crate) fn fork(&self, polar: Arc<Polar>) -> Self {
+        Self {
+            polar,
+            ..self.clone()
+        }
+    }
+
     pub fn get_class(&self, name: &str) -> crate::Result<&Class> {
         self.classes
             .get(name)
@@ -83,6 +96,10 @@ impl Host {
             .and_then(|name| self.get_class(name))
     }
 
+    pub fn classes(&self) -> impl Iterator<Item = &Class> {
+        self.classes.values()
+    }
+
     pub fn get_class_mut(&mut self, name: &str) -> crate::Result<&mut Class> {
         self.classes
             .get_mut(name)
@@ -112,12 +129,27 @@ impl Host {
         }
     }
 
+    /// Set the instance ID of the registered class `name`. Instances of the
+    /// class are passed to Polar with it, so that the core can check their
+    /// class without asking the host.
+    pub(crate) fn set_class_id(&mut self, name: &str, id: Option<u64>) {
+        match id {
+            Some(id) => self.class_ids.insert(name.to_owned(), id),
+            None => self.class_ids.remove(name),
+        };
+    }
+
+    pub(crate) fn class_id(&self, name: &str) -> Option<u64> {
+        self.class_ids.get(name).copied()
+    }
+
     /// Register an MRO list for every registered class.
-    /// Since inheritance is not supported, all lists are empty.
+    /// Since inheritance is not supported, each list holds only the class itself.
     pub fn register_mros(&self) -> crate::Result<()> {
         for name in self.classes.keys() {
             if name != "oso::host::Class" {
-                self.polar.register_mro(Symbol(name.clone()), vec![])?;
+                let mro = self.class_id(name).into_iter().collect();
+                self.polar.register_mro(Symbol(name.clone()), mro)?;
             }
         }
         Ok(())
@@ -185,6 +217,24 @@ impl Host {
         Ok(res)
     }
 
+    /// Starting from the class `base_tag`, follow the declared fields in `path`
+    /// and check whether the result is the class `class_tag`.
+    pub fn isa_with_path(
+        &self,
+        base_tag: &str,
+        path: &[String],
+        class_tag: &str,
+    ) -> crate::Result<bool> {
+        let mut tag = base_tag;
+        for field in path {
+            tag = match self.get_class(tag)?.fields.get(field.as_str()) {
+                Some(field) => field.class_tag(),
+                None => return lazy_error!("No field {} declared on class {}.", field, tag),
+            };
+        }
+        Ok(tag == class_tag)
+    }
+
     pub fn is_subspecializer(&self, _id: u64, _left_tag: &str, _right_tag: &str) -> bool {
         // Rust has no notion of inheritance, so there are no subspecializers.
         false
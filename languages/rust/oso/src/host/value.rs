@@ -1,7 +1,7 @@
 use polar_core::terms::*;
 use std::collections::hash_map::HashMap;
 
-use crate::host::{Host, Instance};
+use crate::host::{Class, Host, Instance};
 
 /// An enum of the possible value types that can be
 /// sent to/from Polar.
@@ -107,13 +107,21 @@ This may mean you performed an operation in your policy over an unbound variable
                 Value::Dictionary(dict)
             }
             PolarValue::Instance(instance) => {
-                let id = host.cache_instance(instance.clone(), None);
+                // A registered class is passed with its own ID as both its
+                // instance and class ID.
+                let class_id = instance
+                    .downcast::<Class>(None)
+                    .ok()
+                    .and_then(|class| host.class_id(&class.name));
+                let id = host.cache_instance(instance.clone(), class_id);
+                let name = instance.name(host).to_owned();
+                let class_id = class_id.or_else(|| host.class_id(&name));
                 Value::ExternalInstance(ExternalInstance {
                     constructor: None,
                     instance_id: id,
-                    repr: Some(std::any::type_name::<Self>().to_owned()),
-                    class_repr: Some(std::any::type_name::<Self>().to_owned()),
-                    class_id: None,
+                    repr: Some(name.clone()),
+                    class_repr: Some(name),
+                    class_id,
                 })
             }
             PolarValue::List(l) => {
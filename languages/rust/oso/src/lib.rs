@@ -74,17 +74,30 @@
 #[macro_use]
 pub mod macros;
 
+pub mod audit;
 pub(crate) mod builtins;
+mod cache;
+pub mod data_filtering;
 pub mod errors;
 mod extras;
 mod host;
 mod oso;
+mod policy_test;
 mod query;
+mod shared;
+mod watch;
 
-pub use crate::oso::{Action, Oso};
+pub use crate::oso::{Action, Explanation, Oso};
+pub use audit::{AuditSink, Decision, JsonLinesSink, RuleSource};
+pub use data_filtering::{Adapter, Filter, Relation};
 pub use errors::{OsoError, Result};
 pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
-pub use query::{Query, ResultSet};
+pub use polar_core::explain::ExplainedRule;
+pub use polar_core::query::{CancellationToken, QueryLimits};
+pub use policy_test::{AssertionFailure, PolicyTestResult};
+pub use query::{Query, QueryStream, ResultSet};
+pub use shared::SharedOso;
+pub use watch::{PolicyWatcher, ReloadEvent};
 
 use polar_core::polar::Polar;
 
@@ -1,23 +1,77 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
+use polar_core::diagnostic::Diagnostic;
+use polar_core::error::{OperationalError, PolarError};
+use polar_core::explain::ExplainedRule;
+use polar_core::query::QueryLimits;
 use polar_core::sources::Source;
-use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::terms::{
+    Call, Dictionary, InstanceLiteral, Operation, Operator, Pattern, Symbol, Term, Value,
+};
 
 use std::collections::HashSet;
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Read;
 use std::sync::Arc;
+use std::time::Duration;
 
+use serde::Serialize;
+
+use crate::audit::{Audit, AuditSink};
+use crate::cache::{CachedDecision, DecisionCache, DecisionKey};
+use crate::data_filtering::{serialize_types, Filter};
 use crate::host::Host;
+use crate::policy_test::{AssertionFailure, PolicyTestResult};
 use crate::query::Query;
 use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
 
+/// Why an `allow` query succeeded or failed, from `Oso::explain_allowed`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Explanation {
+    pub allowed: bool,
+    /// The `allow` rules that were tried, in order.
+    pub rules: Vec<ExplainedRule>,
+}
+
+impl Explanation {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("explanations are always serializable")
+    }
+}
+
+/// Read policy files, which must end in `.polar`.
+pub(crate) fn read_sources<P: AsRef<std::path::Path>>(
+    filenames: Vec<P>,
+) -> crate::Result<Vec<Source>> {
+    let mut sources = Vec::with_capacity(filenames.len());
+
+    for file in filenames {
+        let file = file.as_ref();
+        let filename = file.to_string_lossy().into_owned();
+        if !file.extension().map_or(false, |ext| ext == "polar") {
+            return Err(crate::OsoError::IncorrectFileType { filename });
+        }
+        let mut f = File::open(&file)?;
+        let mut src = String::new();
+        f.read_to_string(&mut src)?;
+        sources.push(Source::new_with_name(filename, src));
+    }
+
+    Ok(sources)
+}
+
 /// Oso is the main struct you interact with. It is an instance of the Oso authorization library
 /// and contains the polar language knowledge base and query engine.
 #[derive(Clone)]
 pub struct Oso {
     inner: Arc<polar_core::polar::Polar>,
     host: Host,
+    /// The action used by `Oso::authorize` to distinguish `NotFound` from `Forbidden`.
+    read_action: PolarValue,
+    audit_sink: Option<Arc<dyn AuditSink>>,
+    /// Limits for new queries, if not the defaults.
+    limits: Option<QueryLimits>,
+    decision_cache: Option<Arc<DecisionCache>>,
 }
 
 impl Default for Oso {
@@ -52,7 +106,14 @@ impl Oso {
         let inner = Arc::new(polar_core::polar::Polar::new());
         let host = Host::new(inner.clone());
 
-        let mut oso = Self { inner, host };
+        let mut oso = Self {
+            inner,
+            host,
+            read_action: PolarValue::String("read".to_owned()),
+            audit_sink: None,
+            limits: None,
+            decision_cache: None,
+        };
 
         for class in crate::builtins::classes() {
             oso.register_class(class)
@@ -63,6 +124,23 @@ impl Oso {
         oso
     }
 
+    /// A copy of this instance with the same classes and constants but no rules.
+    /// Loading rules into the copy does not affect queries made on `self`.
+    pub(crate) fn fork(&self) -> Self {
+        let inner = Arc::new(self.inner.fork());
+        Self {
+            host: self.host.fork(inner.clone()),
+            inner,
+            read_action: self.read_action.clone(),
+            audit_sink: self.audit_sink.clone(),
+            limits: self.limits,
+            decision_cache: self
+                .decision_cache
+                .as_ref()
+                .map(|cache| Arc::new(DecisionCache::new(cache.ttl()))),
+        }
+    }
+
     /// High level interface for authorization decisions. Makes an allow query with the given actor, action and resource and returns true or false.
     pub fn is_allowed<Actor, Action, Resource>(
         &self,
@@ -75,12 +153,251 @@ impl Oso {
         Action: ToPolar,
         Resource: ToPolar,
     {
-        let mut query = self.query_rule("allow", (actor, action, resource)).unwrap();
-        match query.next() {
-            Some(Ok(_)) => Ok(true),
-            Some(Err(e)) => Err(e),
-            None => Ok(false),
+        let args = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        let cache = match &self.decision_cache {
+            Some(cache) => cache,
+            None => return self.query_rule_once("allow", args),
+        };
+        let key = match DecisionKey::new(&[&args.0, &args.1, &args.2], &self.host) {
+            Some(key) => key,
+            None => return self.query_rule_once("allow", args),
+        };
+        if let Some(decision) = cache.get(&key) {
+            if let Some(sink) = &self.audit_sink {
+                let mut host = self.host.clone();
+                let args = [args.0, args.1, args.2]
+                    .iter()
+                    .map(|arg| arg.to_term(&mut host).to_string())
+                    .collect();
+                Audit::new(sink.clone(), "allow", args)
+                    .record_cached(decision.allowed, decision.source);
+            }
+            return Ok(decision.allowed);
+        }
+
+        let mut query = self.query_rule("allow", args)?;
+        let allowed = match query.next() {
+            Some(Ok(_)) => true,
+            Some(Err(e)) => return Err(e),
+            None => false,
+        };
+        let source = if allowed {
+            query.matched_rule_source()
+        } else {
+            None
+        };
+        cache.insert(key, CachedDecision { allowed, source });
+        Ok(allowed)
+    }
+
+    /// Cache the decisions made by `is_allowed` for `ttl`.
+    ///
+    /// Decisions are cached only if every argument is a string, number,
+    /// boolean, or an instance of a class with a cache key set by
+    /// `ClassBuilder::set_cache_key`. The cache is flushed whenever rules or
+    /// constants change. Use `invalidate_decisions` and
+    /// `invalidate_decisions_for` when the application data that decisions
+    /// depend on changes.
+    /// # Examples
+    /// ```ignore
+    /// oso.register_class(User::get_polar_class_builder().set_cache_key(|u: &User| u.id).build())?;
+    /// let oso = oso.with_decision_cache(Duration::from_secs(30));
+    /// ```
+    pub fn with_decision_cache(mut self, ttl: Duration) -> Self {
+        self.decision_cache = Some(Arc::new(DecisionCache::new(ttl)));
+        self
+    }
+
+    /// Forget every cached decision.
+    pub fn invalidate_decisions(&self) {
+        if let Some(cache) = &self.decision_cache {
+            cache.clear()
+        }
+    }
+
+    /// Forget the cached decisions that `value` was an argument to, e.g.
+    /// after changing a user's roles.
+    pub fn invalidate_decisions_for<V: ToPolar>(&self, value: V) {
+        if let Some(cache) = &self.decision_cache {
+            cache.invalidate(&value.to_polar(), &self.host)
+        }
+    }
+
+    /// Explain why `actor` is or isn't allowed to perform `action` on `resource`.
+    ///
+    /// Returns the `allow` rules that were tried, the rules they called, and
+    /// whether each one matched. Serialize it with `Explanation::to_json`.
+    pub fn explain_allowed<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<Explanation>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let mut query = self.query_rule("allow", (actor, action, resource))?;
+        query.set_explain();
+        let allowed = match query.next() {
+            Some(Ok(_)) => true,
+            Some(Err(e)) => return Err(e),
+            None => false,
+        };
+        Ok(Explanation {
+            allowed,
+            rules: query.explanation(),
+        })
+    }
+
+    /// Ensure that `actor` is allowed to perform `action` on `resource`.
+    ///
+    /// Returns `Ok(())` if an `allow` rule permits the action. Otherwise, returns
+    /// `OsoError::NotFound` if the actor cannot read the resource either, and
+    /// `OsoError::Forbidden` if they can read it but not perform the action.
+    /// # Examples
+    /// ```ignore
+    /// match oso.authorize(user, "delete", repo) {
+    ///     Err(OsoError::NotFound) => /* 404 */,
+    ///     Err(OsoError::Forbidden) => /* 403 */,
+    ///     ...
+    /// }
+    /// ```
+    pub fn authorize<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let (actor, action, resource) = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        if self.query_rule_once("allow", (actor.clone(), action.clone(), resource.clone()))? {
+            return Ok(());
+        }
+
+        if action == self.read_action
+            || !self.query_rule_once("allow", (actor, self.read_action.clone(), resource))?
+        {
+            return Err(OsoError::NotFound);
+        }
+        Err(OsoError::Forbidden)
+    }
+
+    /// Ensure that `actor` is allowed to send `request` to the server.
+    ///
+    /// Checks the `allow_request` rule of the policy and returns
+    /// `OsoError::Forbidden` if the request is not allowed.
+    pub fn authorize_request<Actor, Request>(
+        &self,
+        actor: Actor,
+        request: Request,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Request: ToPolar,
+    {
+        if self.query_rule_once("allow_request", (actor, request))? {
+            Ok(())
+        } else {
+            Err(OsoError::Forbidden)
+        }
+    }
+
+    /// Ensure that `actor` is allowed to perform `action` on the field `field`
+    /// of `resource`.
+    ///
+    /// Checks the `allow_field` rule of the policy and returns
+    /// `OsoError::Forbidden` if the action is not allowed.
+    pub fn authorize_field<Actor, Action, Resource, Field>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+        field: Field,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+        Field: ToPolar,
+    {
+        if self.query_rule_once("allow_field", (actor, action, resource, field))? {
+            Ok(())
+        } else {
+            Err(OsoError::Forbidden)
+        }
+    }
+
+    /// Get the fields of `resource` on which `actor` is allowed to perform `action`.
+    /// Returns a [std::collections::HashSet] of fields, typed according the return value.
+    ///
+    /// If an `allow_field` rule allows _any_ field, the set contains `Action::Any`
+    /// when typed as `Action`, and conversion fails when typed as e.g. `String`.
+    /// # Examples
+    /// ```ignore
+    /// let fields: HashSet<String> = oso.authorized_fields(actor, "read", resource)?;
+    /// ```
+    pub fn authorized_fields<Actor, Action, Resource, T>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<HashSet<T>>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+        T: FromPolar + Eq + Hash,
+    {
+        let query = self.query_rule(
+            "allow_field",
+            (
+                actor,
+                action,
+                resource,
+                PolarValue::Variable("field".to_owned()),
+            ),
+        )?;
+
+        let mut set = HashSet::new();
+        for result in query {
+            if let Some(field) = result?.get("field") {
+                set.insert(T::from_polar(field)?);
+            }
         }
+        Ok(set)
+    }
+
+    /// Set the action used by `Oso::authorize` to decide between returning
+    /// `OsoError::NotFound` and `OsoError::Forbidden`. Defaults to `"read"`.
+    pub fn set_read_action<A: ToPolar>(&mut self, action: A) {
+        self.read_action = action.to_polar();
+    }
+
+    /// Apply `limits` to every query made by this instance, instead of the
+    /// default limits. Use `Query::set_limits` to change the limits of one query.
+    /// # Examples
+    /// ```ignore
+    /// let oso = Oso::new().with_limits(QueryLimits {
+    ///     timeout_ms: 100,
+    ///     ..QueryLimits::default()
+    /// });
+    /// ```
+    pub fn with_limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Record every decision made by `is_allowed`, `query_rule`, and the
+    /// methods built on them with `sink`, e.g. a `JsonLinesSink`. Data
+    /// filtering queries aren't decisions, so they aren't recorded.
+    pub fn set_audit_sink<S: AuditSink + 'static>(&mut self, sink: S) {
+        self.audit_sink = Some(Arc::new(sink));
     }
 
     /// Get the actions actor is allowed to take on resource.
@@ -129,8 +446,89 @@ impl Oso {
         Ok(set)
     }
 
+    /// Build a filter over the resources of class `resource_class` that `actor`
+    /// is allowed to perform `action` on.
+    ///
+    /// Runs a partial query for `allow(actor, action, resource)` with `resource`
+    /// constrained to `resource_class`. The fields and relations of each class
+    /// that appear in the policy must be declared with `ClassBuilder::add_field`
+    /// and `ClassBuilder::add_relation`.
+    /// # Examples
+    /// ```ignore
+    /// let filter = oso.authorized_query(user, "read", "Repository")?;
+    /// ```
+    pub fn authorized_query<Actor, Action>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource_class: &str,
+    ) -> crate::Result<Filter>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+    {
+        self.host.get_class(resource_class)?;
+
+        let mut host = self.host.clone();
+        host.accept_expression = true;
+        let resource = PolarValue::Variable("resource".to_owned());
+        // Data filtering isn't a decision, so it isn't audited.
+        let mut query = self.new_rule_query("allow", (actor, action, resource), host, false);
+
+        let isa = Term::new_from_ffi(Value::Expression(Operation {
+            operator: Operator::Isa,
+            args: vec![
+                Term::new_from_ffi(Value::Variable(Symbol::new("resource"))),
+                Term::new_from_ffi(Value::Pattern(Pattern::Instance(InstanceLiteral {
+                    tag: Symbol::new(resource_class),
+                    fields: Dictionary::new(),
+                }))),
+            ],
+        }));
+        let constraint = Term::new_from_ffi(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![isa],
+        }));
+        query.bind("resource", constraint)?;
+
+        let mut results = vec![];
+        for result in &mut query {
+            results.push(result?.into_event());
+        }
+
+        let host = query.host().clone();
+        let types = serialize_types(&host);
+        let filter = self
+            .inner
+            .build_data_filter(types, results, "resource", resource_class)?;
+        Ok(Filter::new(filter, host))
+    }
+
+    /// Fetch the resources of class `resource_class` that `actor` is allowed to
+    /// perform `action` on.
+    ///
+    /// `fetch` receives the filter built by `Oso::authorized_query` and is
+    /// responsible for loading the matching resources, e.g. by rendering the
+    /// filter to a database query.
+    pub fn authorized_resources<Actor, Action, R, F>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource_class: &str,
+        fetch: F,
+    ) -> crate::Result<Vec<R>>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        F: FnOnce(&Filter) -> crate::Result<Vec<R>>,
+    {
+        let filter = self.authorized_query(actor, action, resource_class)?;
+        fetch(&filter)
+    }
+
     /// Clear out all files and rules that have been loaded.
     pub fn clear_rules(&mut self) -> crate::Result<()> {
+        self.invalidate_decisions();
         self.inner.clear_rules();
         check_messages!(self.inner);
         Ok(())
@@ -150,8 +548,31 @@ impl Oso {
         Ok(())
     }
 
+    /// Load `sources`, collecting every error and warning instead of stopping
+    /// at the first error. Returns the errors and the warnings.
+    pub(crate) fn diagnostic_load(&mut self, sources: Vec<Source>) -> (Vec<OsoError>, Vec<String>) {
+        self.invalidate_decisions();
+        if let Err(e) = self.host.register_mros() {
+            return (vec![e], vec![]);
+        }
+        let (mut errors, mut warnings) = (vec![], vec![]);
+        for diagnostic in self.inner.diagnostic_load(sources) {
+            match diagnostic {
+                Diagnostic::Error(e) => errors.push(e.into()),
+                Diagnostic::Warning(w) => warnings.push(w.to_string()),
+            }
+        }
+        if errors.is_empty() {
+            if let Err(e) = self.check_inline_queries() {
+                errors.push(e);
+            }
+        }
+        (errors, warnings)
+    }
+
     // Register MROs, load Polar code, and check inline queries.
     fn load_sources(&mut self, sources: Vec<Source>) -> crate::Result<()> {
+        self.invalidate_decisions();
         self.host.register_mros()?;
         self.inner.load(sources)?;
         self.check_inline_queries()
@@ -175,20 +596,7 @@ impl Oso {
             return Ok(());
         }
 
-        let mut sources = Vec::with_capacity(filenames.len());
-
-        for file in filenames {
-            let file = file.as_ref();
-            let filename = file.to_string_lossy().into_owned();
-            if !file.extension().map_or(false, |ext| ext == "polar") {
-                return Err(crate::OsoError::IncorrectFileType { filename });
-            }
-            let mut f = File::open(&file)?;
-            let mut src = String::new();
-            f.read_to_string(&mut src)?;
-            sources.push(Source::new_with_name(filename, src));
-        }
-
+        let sources = read_sources(filenames)?;
         self.load_sources(sources)
     }
 
@@ -202,14 +610,83 @@ impl Oso {
         self.load_sources(vec![Source::new(src)])
     }
 
+    /// Compile the loaded policy to JSON that can be loaded with
+    /// `load_compiled` without parsing the policy again.
+    /// # Examples
+    /// ```ignore
+    /// oso.load_files(vec!["policy.polar"])?;
+    /// std::fs::write("policy.json", oso.compile())?;
+    /// ```
+    pub fn compile(&self) -> String {
+        serde_json::to_string(&self.inner.compile()).expect("policies are always serializable")
+    }
+
+    /// Load a policy compiled by `compile`. Classes must be registered
+    /// before loading, as with `load_files`, and errors in compiled rules
+    /// don't point to their source files.
+    /// # Examples
+    /// ```ignore
+    /// oso.load_compiled(&std::fs::read_to_string("policy.json")?)?;
+    /// ```
+    pub fn load_compiled(&mut self, compiled: &str) -> crate::Result<()> {
+        let compiled = serde_json::from_str(compiled).map_err(|e| {
+            PolarError::from(OperationalError::Serialization { msg: e.to_string() })
+        })?;
+        self.invalidate_decisions();
+        self.host.register_mros()?;
+        self.inner.load_compiled(compiled)?;
+        self.check_inline_queries()
+    }
+
+    /// Run the `test` blocks in the loaded policy, in the order they were
+    /// loaded. Each assertion is a query made with the fixtures of its test.
+    /// # Examples
+    /// ```ignore
+    /// for result in oso.run_tests() {
+    ///     println!("{}", result);
+    /// }
+    /// ```
+    pub fn run_tests(&self) -> Vec<PolicyTestResult> {
+        self.inner
+            .policy_tests()
+            .iter()
+            .map(|test| {
+                let mut result = PolicyTestResult::new(test);
+                for assertion in &test.assertions {
+                    let mut query = self.inner.new_query_from_term(test.query(assertion), false);
+                    if let Some(limits) = self.limits {
+                        query.set_limits(limits);
+                    }
+                    let error = match Query::new(query, self.host.clone()).next() {
+                        Some(Ok(_)) => continue,
+                        // Drop the stack trace and source context, since the
+                        // failure has the location of the assertion.
+                        Some(Err(OsoError::Polar(e))) => {
+                            e.0.to_string().lines().last().map(str::to_owned)
+                        }
+                        Some(Err(e)) => Some(e.to_string()),
+                        None => None,
+                    };
+                    result
+                        .failures
+                        .push(AssertionFailure::new(assertion, error));
+                }
+                result
+            })
+            .collect()
+    }
+
     /// Query the knowledge base. This can be an allow query or any other polar expression.
     /// # Examples
     /// ```ignore
     /// oso.query("x = 1 or x = 2");
     /// ```
     pub fn query(&self, s: &str) -> crate::Result<Query> {
-        let query = self.inner.new_query(s, false)?;
+        let mut query = self.inner.new_query(s, false)?;
         check_messages!(self.inner);
+        if let Some(limits) = self.limits {
+            query.set_limits(limits);
+        }
         let query = Query::new(query, self.host.clone());
         Ok(query)
     }
@@ -222,22 +699,55 @@ impl Oso {
     /// ```
     #[must_use = "Query that is not consumed does nothing."]
     pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
-        let mut query_host = self.host.clone();
-        let args = args
+        Ok(self.new_rule_query(name, args, self.host.clone(), true))
+    }
+
+    /// A query for the rule `name`, whose result is recorded in the audit
+    /// sink if `audited`.
+    fn new_rule_query(
+        &self,
+        name: &str,
+        args: impl ToPolarList,
+        mut query_host: Host,
+        audited: bool,
+    ) -> Query {
+        let args: Vec<Term> = args
             .to_polar_list()
             .iter()
             .map(|value| value.to_term(&mut query_host))
             .collect();
+        let audit = self.audit_sink.as_ref().filter(|_| audited).map(|sink| {
+            let args = args.iter().map(Term::to_string).collect();
+            Audit::new(sink.clone(), name, args)
+        });
         let query_value = Value::Call(Call {
             name: Symbol(name.to_string()),
             args,
             kwargs: None,
         });
         let query_term = Term::new_from_ffi(query_value);
-        let query = self.inner.new_query_from_term(query_term, false);
+        let mut query = self.inner.new_query_from_term(query_term, false);
         check_messages!(self.inner);
-        let query = Query::new(query, query_host);
-        Ok(query)
+        if let Some(limits) = self.limits {
+            query.set_limits(limits);
+        }
+        let mut query = Query::new(query, query_host);
+        query.audit = audit;
+        query
+    }
+
+    /// Query the rule `name` with `args` and return whether it has at least one result.
+    /// # Examples
+    /// ```ignore
+    /// assert!(oso.query_rule_once("is_admin", (User{name: "steve"},))?);
+    /// ```
+    pub fn query_rule_once(&self, name: &str, args: impl ToPolarList) -> crate::Result<bool> {
+        let mut query = self.query_rule(name, args)?;
+        match query.next() {
+            Some(Ok(_)) => Ok(true),
+            Some(Err(e)) => Err(e),
+            None => Ok(false),
+        }
     }
 
     /// Register a rust type as a Polar class.
@@ -249,7 +759,16 @@ impl Oso {
         for hook in &class.register_hooks {
             hook.call(self)?;
         }
-        self.register_constant(class, &class_name)
+        // Instances of the class carry its ID, so that the core can match
+        // them against specializers without asking the host.
+        let id = self.inner.get_external_id();
+        self.host.set_class_id(&class_name, Some(id));
+        if let Err(e) = self.register_constant(class, &class_name) {
+            self.host.set_class_id(&class_name, None);
+            return Err(e);
+        }
+        self.inner.register_mro(Symbol(class_name), vec![id])?;
+        Ok(())
     }
 
     /// Register a rust type as a Polar constant.
@@ -259,6 +778,7 @@ impl Oso {
         value: V,
         name: &str,
     ) -> crate::Result<()> {
+        self.invalidate_decisions();
         self.inner.register_constant(
             Symbol(name.to_string()),
             value.to_polar().to_term(&mut self.host),
@@ -0,0 +1,83 @@
+//! Run the `test` blocks in a policy.
+use std::fmt;
+
+use polar_core::policy_test::{Assertion, PolicyTest};
+use serde::Serialize;
+
+use crate::audit::RuleSource;
+
+/// The result of a `test` block, from `Oso::run_tests`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PolicyTestResult {
+    pub name: String,
+    /// Where the test is defined.
+    pub source: Option<RuleSource>,
+    /// How many assertions the test made.
+    pub assertions: usize,
+    /// The assertions that failed. The test passed if there are none.
+    pub failures: Vec<AssertionFailure>,
+}
+
+impl PolicyTestResult {
+    pub(crate) fn new(test: &PolicyTest) -> Self {
+        Self {
+            name: test.name().to_owned(),
+            source: RuleSource::of_term(&test.name),
+            assertions: test.assertions.len(),
+            failures: vec![],
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// An assertion in a `test` block that failed.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AssertionFailure {
+    /// The assertion, e.g. `assert allow(alice, "read", repo)`.
+    pub assertion: String,
+    /// Where the assertion is.
+    pub source: Option<RuleSource>,
+    /// The error the query failed with, if it didn't just have the wrong
+    /// number of results.
+    pub error: Option<String>,
+}
+
+impl AssertionFailure {
+    pub(crate) fn new(assertion: &Assertion, error: Option<String>) -> Self {
+        Self {
+            assertion: format!("{} {}", assertion.keyword(), assertion.term()),
+            source: RuleSource::of_term(assertion.term()),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for RuleSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(filename) = &self.filename {
+            write!(f, "{}:", filename)?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl fmt::Display for PolicyTestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let status = if self.passed() { "PASS" } else { "FAIL" };
+        write!(f, "{} {}", status, self.name)?;
+        for failure in &self.failures {
+            write!(f, "\n    ")?;
+            if let Some(source) = &failure.source {
+                write!(f, "{}: ", source)?;
+            }
+            write!(f, "{}", failure.assertion)?;
+            if let Some(error) = &failure.error {
+                write!(f, " ({})", error)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,16 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
+use crate::audit::{Audit, RuleSource};
 use crate::errors::OsoError;
-use crate::host::{Host, Instance, PolarIterator};
+use crate::host::{Host, HostFuture, Instance, PolarIterator};
 use crate::{FromPolar, PolarValue};
 
 use polar_core::events::*;
+use polar_core::explain::ExplainedRule;
+use polar_core::query::{CancellationToken, QueryLimits};
 use polar_core::terms::*;
 
 impl Iterator for Query {
@@ -19,13 +24,19 @@ pub struct Query {
     inner: polar_core::query::Query,
     /// Stores a map from call_id to the iterator the call iterates through
     iterators: HashMap<u64, PolarIterator>,
+    /// The async host call the query is waiting on, if any
+    pending: Option<(u64, HostFuture)>,
     host: Host,
+    /// The decision to record once the query has an answer
+    pub(crate) audit: Option<Audit>,
 }
 
 impl Query {
     pub fn new(inner: polar_core::query::Query, host: Host) -> Self {
         Self {
             iterators: HashMap::new(),
+            pending: None,
+            audit: None,
             inner,
             host,
         }
@@ -35,20 +46,131 @@ impl Query {
         self.inner.source_info()
     }
 
+    /// Replace the limits on this query. Exceeding a limit fails the query
+    /// with an `OsoError::Polar` runtime error.
+    pub fn set_limits(&mut self, limits: QueryLimits) {
+        self.inner.set_limits(limits)
+    }
+
+    pub fn limits(&self) -> QueryLimits {
+        self.inner.limits()
+    }
+
+    /// A token that cancels the query, which can be sent to other threads.
+    /// Once cancelled, the query fails with a `QueryCancelled` runtime error.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.inner.cancellation_token()
+    }
+
+    /// Where the rule that matched for the most recent result is defined.
+    pub(crate) fn matched_rule_source(&self) -> Option<RuleSource> {
+        self.inner
+            .matched_rule()
+            .and_then(|rule| RuleSource::of(&rule))
+    }
+
+    /// Bind the variable `name` to `value` before running the query.
+    pub(crate) fn bind(&mut self, name: &str, value: Term) -> crate::Result<()> {
+        Ok(self.inner.bind(Symbol(name.to_string()), value)?)
+    }
+
+    pub(crate) fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Record the rules the query tries, for `explanation`.
+    pub(crate) fn set_explain(&mut self) {
+        self.inner.set_explain(true)
+    }
+
+    /// The rules tried so far.
+    pub(crate) fn explanation(&self) -> Vec<ExplainedRule> {
+        self.inner.explanation().unwrap_or_default()
+    }
+
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
+        // A dropped `next_result_async` future can leave a host call pending.
+        if self.pending.is_some() {
+            return Some(waiting_on_async_call());
+        }
+        match self.poll_next_result(None) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Some(waiting_on_async_call()),
+        }
+    }
+
+    /// Like `next_result`, but awaits methods and attribute getters registered
+    /// with `ClassBuilder::add_async_method` and `ClassBuilder::add_async_attribute_getter`.
+    pub async fn next_result_async(&mut self) -> Option<crate::Result<ResultSet>> {
+        std::future::poll_fn(|cx| self.poll_next_result(Some(cx))).await
+    }
+
+    /// Turn the query into a `Stream` of results, for use with async host calls.
+    pub fn into_stream(self) -> QueryStream {
+        QueryStream(self)
+    }
+
+    fn poll_next_result(
+        &mut self,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<Option<crate::Result<ResultSet>>> {
+        let result = self.poll_query(cx);
+        if let (Poll::Ready(result), Some(audit)) = (&result, self.audit.take()) {
+            match result {
+                Some(Ok(_)) => audit.record(true, None, self.matched_rule_source()),
+                Some(Err(e)) => audit.record(false, Some(e.to_string()), None),
+                None => audit.record(false, None, None),
+            }
+        }
+        result
+    }
+
+    /// Run the query until the next result. Async host calls are only
+    /// supported when polled with a `cx`.
+    fn poll_query(
+        &mut self,
+        mut cx: Option<&mut Context<'_>>,
+    ) -> Poll<Option<crate::Result<ResultSet>>> {
         loop {
-            let event = self.inner.next()?;
+            if let Some((call_id, future)) = self.pending.as_mut() {
+                let cx = match cx.as_deref_mut() {
+                    Some(cx) => cx,
+                    None => return Poll::Ready(Some(waiting_on_async_call())),
+                };
+                let result = match future.as_mut().poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                };
+                let call_id = *call_id;
+                self.pending = None;
+                let result = match result {
+                    Ok(value) => self.call_result(call_id, value),
+                    Err(e) => self.call_result_none(call_id).and(Err(e)),
+                };
+                if let Err(e) = self.handle_call_error(result) {
+                    return Poll::Ready(Some(Err(e)));
+                }
+                continue;
+            }
+
+            let event = match self.inner.next() {
+                Some(event) => event,
+                None => return Poll::Ready(None),
+            };
             check_messages!(self.inner);
             if let Err(e) = event {
-                return Some(Err(e.into()));
+                return Poll::Ready(Some(Err(e.into())));
             }
             let event = event.unwrap();
             tracing::debug!(event=?event);
             let result = match event {
                 QueryEvent::None => Ok(()),
-                QueryEvent::Done { .. } => return None,
+                QueryEvent::Done { .. } => return Poll::Ready(None),
                 QueryEvent::Result { bindings, .. } => {
-                    return Some(ResultSet::from_bindings(bindings, self.host.clone()));
+                    return Poll::Ready(Some(ResultSet::from_bindings(
+                        bindings,
+                        self.host.clone(),
+                    )));
                 }
                 QueryEvent::MakeExternal {
                     instance_id,
@@ -63,7 +185,14 @@ impl Query {
                     attribute,
                     args,
                     kwargs,
-                } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
+                } => self.handle_external_call(
+                    call_id,
+                    instance,
+                    attribute,
+                    args,
+                    kwargs,
+                    cx.is_some(),
+                ),
                 QueryEvent::ExternalOp {
                     call_id,
                     operator,
@@ -85,31 +214,41 @@ impl Query {
                     left_class_tag,
                     right_class_tag,
                 ),
+                QueryEvent::ExternalIsaWithPath {
+                    call_id,
+                    base_tag,
+                    path,
+                    class_tag,
+                } => self.handle_external_isa_with_path(call_id, base_tag, path, class_tag),
                 QueryEvent::Debug { message } => self.handle_debug(message),
                 QueryEvent::ExternalIsSubclass {
                     call_id,
                     left_class_tag,
                     right_class_tag,
                 } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
-                event => unimplemented!("Unhandled event {:?}", event),
+                event => lazy_error!("Unhandled event {:?}", event),
             };
 
-            match result {
-                // Only call errors get passed back.
-                Err(call_error @ OsoError::InvalidCallError { .. }) => {
-                    tracing::error!("application invalid call error {}", call_error);
-                    if let Err(e) = self.application_error(call_error) {
-                        return Some(Err(e));
-                    }
-                }
-                // All others get returned.
-                Err(err) => return Some(Err(err)),
-                // Continue on ok
-                Ok(_) => {}
+            if let Err(e) = self.handle_call_error(result) {
+                return Poll::Ready(Some(Err(e)));
             }
         }
     }
 
+    fn handle_call_error(&mut self, result: crate::Result<()>) -> crate::Result<()> {
+        match result {
+            // Only call errors get passed back.
+            Err(call_error @ OsoError::InvalidCallError { .. }) => {
+                tracing::error!("application invalid call error {}", call_error);
+                self.application_error(call_error)
+            }
+            // All others get returned.
+            Err(err) => Err(err),
+            // Continue on ok
+            Ok(_) => Ok(()),
+        }
+    }
+
     fn question_result(&mut self, call_id: u64, result: bool) -> crate::Result<()> {
         Ok(self.inner.question_result(call_id, result)?)
     }
@@ -180,12 +319,42 @@ impl Query {
         name: Symbol,
         args: Option<Vec<Term>>,
         kwargs: Option<BTreeMap<Symbol, Term>>,
+        is_async: bool,
     ) -> crate::Result<()> {
         if kwargs.is_some() {
             return lazy_error!("Invalid call error: kwargs not supported in Rust.");
         }
         tracing::trace!(call_id, name = %name, args = ?args, "call");
         let instance = Instance::from_polar(PolarValue::from_term(&instance, &self.host)?)?;
+        let async_method = instance
+            .class(&self.host)
+            .ok()
+            .and_then(|class| class.get_async_method(&name.0, args.is_some()));
+        if let Some(method) = async_method {
+            let future = if is_async {
+                args.unwrap_or_default()
+                    .iter()
+                    .map(|v| PolarValue::from_term(v, &self.host))
+                    .collect::<crate::Result<Vec<PolarValue>>>()
+                    .and_then(|args| method.invoke(&instance, args, &self.host))
+            } else {
+                lazy_error!(
+                    "{} on {} is async; use Query::next_result_async to run this query.",
+                    name,
+                    instance.name(&self.host)
+                )
+            };
+            return match future {
+                Ok(future) => {
+                    self.pending = Some((call_id, future));
+                    Ok(())
+                }
+                Err(e) => {
+                    self.call_result_none(call_id)?;
+                    Err(e)
+                }
+            };
+        }
         let result = if let Some(args) = args {
             let args = args
                 .iter()
@@ -193,7 +362,7 @@ impl Query {
                 .collect::<crate::Result<Vec<PolarValue>>>()?;
             instance.call(&name.0, args, &mut self.host)
         } else {
-            instance.get_attr(&name.0, &mut self.host)
+            instance.get_attr(&name.0, &self.host)
         };
         match result {
             Ok(t) => self.call_result(call_id, t),
@@ -236,6 +405,26 @@ impl Query {
         Ok(())
     }
 
+    fn handle_external_isa_with_path(
+        &mut self,
+        call_id: u64,
+        base_tag: Symbol,
+        path: Vec<Term>,
+        class_tag: Symbol,
+    ) -> crate::Result<()> {
+        tracing::debug!(base = %base_tag, path = ?path, class = %class_tag, "isa_with_path");
+        let path = path
+            .iter()
+            .map(|field| match field.value() {
+                Value::String(field) => Ok(field.clone()),
+                _ => lazy_error!("invalid field in isa path: {}", field),
+            })
+            .collect::<crate::Result<Vec<String>>>()?;
+        let res = self.host.isa_with_path(&base_tag.0, &path, &class_tag.0)?;
+        self.question_result(call_id, res)?;
+        Ok(())
+    }
+
     fn handle_external_is_subspecializer(
         &mut self,
         call_id: u64,
@@ -269,6 +458,21 @@ impl Query {
     }
 }
 
+fn waiting_on_async_call<T>() -> crate::Result<T> {
+    lazy_error!("query is waiting on an async host call; use next_result_async")
+}
+
+/// A `Stream` over the results of a query, which awaits async host calls.
+/// Created by `Query::into_stream`.
+pub struct QueryStream(Query);
+
+impl futures_core::Stream for QueryStream {
+    type Item = crate::Result<ResultSet>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().0.poll_next_result(Some(cx))
+    }
+}
+
 #[derive(Clone)]
 pub struct ResultSet {
     bindings: polar_core::kb::Bindings,
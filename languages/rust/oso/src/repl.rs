@@ -1,6 +1,12 @@
 //! Code for making interactive Oso queries from a REPL.
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use polar_core::diagnostic::Diagnostic;
+use polar_core::error::{ErrorKind, ValidationError};
+use polar_core::formatter::format_policy;
+use polar_core::polar::Polar;
+use polar_core::sources::Source;
+use polar_core::warning::ValidationWarning;
 use rustyline::error::ReadlineError;
 use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::Editor;
@@ -8,8 +14,15 @@ use rustyline_derive::{Completer, Helper, Highlighter, Hinter};
 
 use oso::Oso;
 
+use std::collections::BTreeSet;
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
+
+fn files_arg() -> Arg<'static, 'static> {
+    Arg::with_name("FILES")
+        .multiple(true)
+        .help("Specify one or more .polar files to load")
+}
 
 /// Build the App for handling command line parameters
 fn build_app() -> App<'static, 'static> {
@@ -17,11 +30,186 @@ fn build_app() -> App<'static, 'static> {
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about("Oso REPL. See https://docs.osohq.com/rust/reference/tooling/repl.html")
-        .arg(
-            Arg::with_name("FILES")
-                .multiple(true)
-                .help("Specify one or more .polar files to load"),
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .arg(files_arg())
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Report the errors and warnings in FILES. Exits with status 1 if there are errors.")
+                .arg(files_arg().required(true))
+                .arg(
+                    Arg::with_name("deny-warnings")
+                        .long("deny-warnings")
+                        .help("Exit with status 1 if there are warnings"),
+                )
+                .arg(
+                    Arg::with_name("allow-unregistered")
+                        .long("allow-unregistered")
+                        .help("Report classes the application might register as notes instead of errors and warnings"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run the inline queries and test blocks in FILES. Exits with status 1 if any fail. Policies that use classes registered by an application can't be tested without it, and also exit with status 1.")
+                .arg(files_arg().required(true))
+                .arg(
+                    Arg::with_name("allow-skip")
+                        .long("allow-skip")
+                        .help("Exit with status 0 when the tests are skipped because the policy uses classes registered by an application"),
+                ),
         )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Format FILES in place")
+                .arg(files_arg().required(true))
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Don't write the files. Exit with status 1 if any need formatting"),
+                ),
+        )
+}
+
+fn files<'a>(matches: &'a ArgMatches) -> Vec<&'a str> {
+    matches
+        .values_of("FILES")
+        .map(|files| files.collect())
+        .unwrap_or_default()
+}
+
+fn read_sources(files: &[&str]) -> anyhow::Result<Vec<Source>> {
+    files
+        .iter()
+        .map(|file| Ok(Source::new_with_name(*file, fs::read_to_string(file)?)))
+        .collect()
+}
+
+/// Whether `diagnostic` depends on the classes and constants registered by
+/// an application, which the CLI can't know about. A singleton variable
+/// that starts with a capital letter is probably an unregistered class.
+fn depends_on_app(diagnostic: &Diagnostic) -> bool {
+    match diagnostic {
+        Diagnostic::Error(e) => match &e.0 {
+            ErrorKind::Validation(ValidationError::UnregisteredClass { .. }) => true,
+            ErrorKind::Validation(ValidationError::SingletonVariable { term }) => {
+                term.to_string().starts_with(|c: char| c.is_uppercase())
+            }
+            _ => false,
+        },
+        Diagnostic::Warning(w) => matches!(w.0, ValidationWarning::UnknownSpecializer { .. }),
+    }
+}
+
+/// Print the errors and warnings in `files`, returning whether the check
+/// passed. With `allow_unregistered`, diagnostics that might be resolved by
+/// classes the application registers are printed as notes, which don't
+/// fail the check.
+fn check(files: &[&str], deny_warnings: bool, allow_unregistered: bool) -> anyhow::Result<bool> {
+    let diagnostics = Polar::new().diagnostic_load(read_sources(files)?);
+    let (mut errors, mut warnings, mut notes) = (0, 0, 0);
+    for diagnostic in &diagnostics {
+        if allow_unregistered && depends_on_app(diagnostic) {
+            notes += 1;
+            println!("note: {}\n", diagnostic);
+        } else if diagnostic.is_error() {
+            errors += 1;
+            println!("error: {}\n", diagnostic);
+        } else {
+            warnings += 1;
+            println!("warning: {}\n", diagnostic);
+        }
+    }
+    if allow_unregistered {
+        println!("{} errors, {} warnings, {} notes", errors, warnings, notes);
+    } else {
+        println!("{} errors, {} warnings", errors, warnings);
+    }
+    Ok(errors == 0 && (warnings == 0 || !deny_warnings))
+}
+
+/// The names of the classes and constants that `diagnostics` show the
+/// policy needs the application to register.
+fn app_names(diagnostics: &[Diagnostic]) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+    for diagnostic in diagnostics.iter().filter(|d| depends_on_app(d)) {
+        let name = match diagnostic {
+            Diagnostic::Error(e) => match &e.0 {
+                ErrorKind::Validation(
+                    ValidationError::UnregisteredClass { term }
+                    | ValidationError::SingletonVariable { term },
+                ) => term.to_string(),
+                _ => continue,
+            },
+            Diagnostic::Warning(w) => match &w.0 {
+                ValidationWarning::UnknownSpecializer { sym, .. } => sym.0.clone(),
+                _ => continue,
+            },
+        };
+        names.insert(name);
+    }
+    names
+}
+
+/// Load `files`, which runs their inline queries, then print the result of
+/// each test, returning whether they all passed. Policies that depend on
+/// classes registered by an application can't be loaded without it, so
+/// their tests are skipped, which only passes with `allow_skip`.
+fn test(files: &[&str], allow_skip: bool) -> anyhow::Result<bool> {
+    let polar = Polar::new();
+    let diagnostics = polar.diagnostic_load(read_sources(files)?);
+    let names = app_names(&diagnostics);
+    if !names.is_empty() {
+        let errors: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.is_error() && !depends_on_app(d))
+            .collect();
+        for error in &errors {
+            println!("error: {}\n", error);
+        }
+        if !errors.is_empty() {
+            return Ok(false);
+        }
+        let tests = polar.kb.read().unwrap().policy_tests.len();
+        println!(
+            "The policy uses names the application registers: {}.\n\
+             Run its tests from the application with `Oso::run_tests` instead.",
+            names.into_iter().collect::<Vec<_>>().join(", ")
+        );
+        println!("\n0 passed, 0 failed, {} skipped", tests);
+        return Ok(allow_skip);
+    }
+
+    let mut oso = Oso::new();
+    if let Err(e) = oso.load_files(files.to_vec()) {
+        println!("{}", e);
+        return Ok(false);
+    }
+    let results = oso.run_tests();
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    for result in &results {
+        println!("{}", result);
+    }
+    println!("\n{} passed, {} failed", results.len() - failed, failed);
+    Ok(failed == 0)
+}
+
+/// Format `files` in place, or with `check` list the files that need
+/// formatting. Returns whether every file was already formatted.
+fn fmt(files: &[&str], check: bool) -> anyhow::Result<bool> {
+    let mut formatted = true;
+    for source in read_sources(files)? {
+        let filename = source.filename.clone().unwrap_or_default();
+        let original = source.src.clone();
+        let output = format_policy(source)?;
+        if output != original {
+            formatted = false;
+            if check {
+                println!("{} needs formatting", filename);
+            } else {
+                fs::write(&filename, output)?;
+            }
+        }
+    }
+    Ok(formatted || !check)
 }
 
 /// Attempt to create a new temporary directory to store
@@ -110,13 +298,31 @@ impl Drop for Repl {
 
 pub fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
-    let mut repl = Repl::new();
-    let mut oso = Oso::new();
 
     let matches = build_app().get_matches();
-    if matches.is_present("FILES") {
-        oso.load_files(matches.values_of("FILES").unwrap().collect())?;
+    let passed = match matches.subcommand() {
+        ("check", Some(m)) => check(
+            &files(m),
+            m.is_present("deny-warnings"),
+            m.is_present("allow-unregistered"),
+        )?,
+        ("test", Some(m)) => test(&files(m), m.is_present("allow-skip"))?,
+        ("fmt", Some(m)) => fmt(&files(m), m.is_present("check"))?,
+        _ => return repl(files(&matches)),
+    };
+    if !passed {
+        std::process::exit(1);
     }
+    Ok(())
+}
+
+fn repl(files: Vec<&str>) -> anyhow::Result<()> {
+    let mut oso = Oso::new();
+    if !files.is_empty() {
+        oso.load_files(files)?;
+    }
+
+    let mut repl = Repl::new();
 
     loop {
         // get input
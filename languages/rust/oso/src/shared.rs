@@ -0,0 +1,133 @@
+//! Share one policy across threads and swap it out atomically.
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use arc_swap::ArcSwap;
+use polar_core::sources::Source;
+
+use crate::query::Query;
+use crate::watch::ReloadEvent;
+use crate::{Oso, ToPolar, ToPolarList};
+
+/// An `Oso` that can be shared across threads and reloaded while in use.
+///
+/// Queries run against a snapshot of the current policy without taking a
+/// lock. A reload builds the new policy on the side, on a copy of the
+/// registered classes and constants, and publishes it only once it has
+/// loaded successfully. Queries already running keep the policy they
+/// started with.
+///
+/// Register classes on the `Oso` before sharing it, or in [`SharedOso::reload`].
+/// # Examples
+/// ```ignore
+/// let oso = Arc::new(SharedOso::new(oso));
+/// // On worker threads:
+/// oso.is_allowed(user, "read", repo)?;
+/// // When the policy changes:
+/// oso.reload_files(vec!["policy.polar"])?;
+/// ```
+pub struct SharedOso {
+    current: ArcSwap<Oso>,
+    /// Serializes reloads, so that one can't discard another's changes.
+    reload_lock: Mutex<()>,
+}
+
+impl SharedOso {
+    pub fn new(oso: Oso) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(oso),
+            reload_lock: Mutex::new(()),
+        }
+    }
+
+    /// The current policy. The snapshot is unaffected by later reloads.
+    pub fn snapshot(&self) -> Arc<Oso> {
+        self.current.load_full()
+    }
+
+    /// See [`Oso::is_allowed`].
+    pub fn is_allowed<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<bool>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        self.current.load().is_allowed(actor, action, resource)
+    }
+
+    /// See [`Oso::authorize`].
+    pub fn authorize<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        self.current.load().authorize(actor, action, resource)
+    }
+
+    /// See [`Oso::query_rule`].
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
+        self.current.load().query_rule(name, args)
+    }
+
+    /// Replace the policy with one built by `f`.
+    ///
+    /// `f` is given an `Oso` with the current classes and constants but no
+    /// rules. If it returns an error, the current policy is kept.
+    pub fn reload<F>(&self, f: F) -> crate::Result<()>
+    where
+        F: FnOnce(&mut Oso) -> crate::Result<()>,
+    {
+        let _guard = self.lock_reloads();
+        let mut oso = self.current.load().fork();
+        f(&mut oso)?;
+        self.current.store(Arc::new(oso));
+        Ok(())
+    }
+
+    /// Replace the policy with `sources` if they load without errors.
+    pub(crate) fn reload_sources(&self, sources: Vec<Source>) -> ReloadEvent {
+        let _guard = self.lock_reloads();
+        let mut oso = self.current.load().fork();
+        let (errors, warnings) = oso.diagnostic_load(sources);
+        if errors.is_empty() {
+            self.current.store(Arc::new(oso));
+            ReloadEvent::Reloaded { warnings }
+        } else {
+            ReloadEvent::Rejected { errors, warnings }
+        }
+    }
+
+    fn lock_reloads(&self) -> MutexGuard<'_, ()> {
+        self.reload_lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Replace the policy with the rules in `src`.
+    pub fn reload_str(&self, src: &str) -> crate::Result<()> {
+        self.reload(|oso| oso.load_str(src))
+    }
+
+    /// Replace the policy with the rules in `filenames`.
+    pub fn reload_files<P: AsRef<Path>>(&self, filenames: Vec<P>) -> crate::Result<()> {
+        self.reload(|oso| oso.load_files(filenames))
+    }
+}
+
+impl From<Oso> for SharedOso {
+    fn from(oso: Oso) -> Self {
+        Self::new(oso)
+    }
+}
@@ -0,0 +1,120 @@
+//! Reload policy files when they change on disk.
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::oso::read_sources;
+use crate::{OsoError, SharedOso};
+
+/// The outcome of reloading changed policy files.
+#[derive(Debug)]
+pub enum ReloadEvent {
+    /// The new policy loaded and is now in use.
+    Reloaded { warnings: Vec<String> },
+    /// The new policy has errors, so the previous policy is still in use.
+    Rejected {
+        errors: Vec<OsoError>,
+        warnings: Vec<String>,
+    },
+}
+
+/// Watches policy files for changes, started by [`SharedOso::watch_files`].
+///
+/// The watcher stops when it is dropped.
+pub struct PolicyWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PolicyWatcher {
+    /// Stop watching and wait for any reload in progress to finish.
+    pub fn stop(self) {}
+}
+
+impl Drop for PolicyWatcher {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the watcher thread.
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// The contents of the watched files, or the error reading them.
+type Snapshot = Result<Vec<String>, String>;
+
+impl SharedOso {
+    /// Load the policy files `filenames`, then reload them whenever they change.
+    ///
+    /// The files are checked every `interval`. Changed files are validated
+    /// before the new policy is published; if they have errors, the previous
+    /// policy stays in use. The outcome of each reload, including its errors
+    /// and warnings, is passed to `on_reload`.
+    ///
+    /// Returns the first error if the files can't be loaded initially.
+    /// # Examples
+    /// ```ignore
+    /// let watcher = oso.watch_files(vec!["policy.polar"], Duration::from_secs(1), |event| {
+    ///     if let ReloadEvent::Rejected { errors, .. } = event {
+    ///         tracing::error!("policy rejected: {:?}", errors);
+    ///     }
+    /// })?;
+    /// ```
+    pub fn watch_files<P, F>(
+        self: &Arc<Self>,
+        filenames: Vec<P>,
+        interval: Duration,
+        on_reload: F,
+    ) -> crate::Result<PolicyWatcher>
+    where
+        P: AsRef<Path>,
+        F: Fn(ReloadEvent) + Send + 'static,
+    {
+        let filenames: Vec<PathBuf> = filenames.iter().map(|f| f.as_ref().to_owned()).collect();
+        let sources = read_sources(filenames.iter().collect())?;
+        let mut last: Snapshot = Ok(sources.iter().map(|s| s.src.clone()).collect());
+        match self.reload_sources(sources) {
+            ReloadEvent::Rejected { mut errors, .. } => return Err(errors.remove(0)),
+            event => on_reload(event),
+        }
+
+        let oso = Arc::downgrade(self);
+        let (stop, stopped) = mpsc::channel();
+        let handle = thread::spawn(move || loop {
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+            let oso = match Weak::upgrade(&oso) {
+                Some(oso) => oso,
+                None => return,
+            };
+
+            let sources = read_sources(filenames.iter().collect());
+            let snapshot: Snapshot = match &sources {
+                Ok(sources) => Ok(sources.iter().map(|s| s.src.clone()).collect()),
+                Err(e) => Err(e.to_string()),
+            };
+            if snapshot == last {
+                continue;
+            }
+            last = snapshot;
+
+            on_reload(match sources {
+                Ok(sources) => oso.reload_sources(sources),
+                Err(e) => ReloadEvent::Rejected {
+                    errors: vec![e],
+                    warnings: vec![],
+                },
+            });
+        });
+
+        Ok(PolicyWatcher {
+            stop: Some(stop),
+            handle: Some(handle),
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::executor::block_on;
+use futures::StreamExt;
+use oso::{Oso, OsoError, PolarClass};
+
+mod common;
+
+/// A future that is pending once before resolving, like a real I/O call.
+struct Delayed<T>(Option<T>, bool);
+
+impl<T: Unpin> Future for Delayed<T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if self.1 {
+            Poll::Ready(self.0.take().unwrap())
+        } else {
+            self.1 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+fn delayed<T>(value: T) -> Delayed<T> {
+    Delayed(Some(value), false)
+}
+
+#[derive(PolarClass, Clone, Debug)]
+struct Repo {
+    #[polar(attribute)]
+    id: i64,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("connection lost")]
+struct ConnectionLost;
+
+fn async_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(
+        Repo::get_polar_class_builder()
+            .add_async_attribute_getter("owner", |repo: &Repo| {
+                delayed(format!("owner-{}", repo.id))
+            })
+            .add_async_method("member", |repo: &Repo, name: String| {
+                let is_member = repo.id == 1 && name == "alice";
+                async move { is_member }
+            })
+            .add_async_attribute_getter("missing", |_: &Repo| async {
+                Err::<bool, _>(ConnectionLost)
+            })
+            .build(),
+    )
+    .unwrap();
+    oso.load_str(
+        r#"owns(name, repo: Repo) if repo.owner = name;
+           member(name, repo: Repo) if repo.member(name);
+           broken(repo: Repo) if repo.missing;"#,
+    )
+    .unwrap();
+    oso
+}
+
+#[test]
+fn test_async_attribute_getter() -> oso::Result<()> {
+    common::setup();
+    let oso = async_oso();
+    let mut query = oso.query_rule("owns", ("owner-1", Repo { id: 1 }))?;
+    assert!(block_on(query.next_result_async()).transpose()?.is_some());
+    assert!(block_on(query.next_result_async()).is_none());
+
+    let mut query = oso.query_rule("owns", ("owner-2", Repo { id: 1 }))?;
+    assert!(block_on(query.next_result_async()).is_none());
+    Ok(())
+}
+
+#[test]
+fn test_async_method() -> oso::Result<()> {
+    common::setup();
+    let oso = async_oso();
+    let query = oso.query_rule("member", ("alice", Repo { id: 1 }))?;
+    let results = block_on(query.into_stream().collect::<Vec<_>>());
+    assert_eq!(results.len(), 1);
+
+    let query = oso.query_rule("member", ("bob", Repo { id: 1 }))?;
+    assert!(block_on(query.into_stream().collect::<Vec<_>>()).is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_async_call_from_sync_query() -> oso::Result<()> {
+    common::setup();
+    let oso = async_oso();
+    let mut query = oso.query_rule("owns", ("owner-1", Repo { id: 1 }))?;
+    assert!(matches!(
+        query.next_result(),
+        Some(Err(OsoError::Custom { message })) if message.contains("next_result_async")
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_sync_result_during_async_call() -> oso::Result<()> {
+    common::setup();
+    let oso = async_oso();
+    let mut query = oso.query_rule("owns", ("owner-1", Repo { id: 1 }))?;
+    {
+        // Drop the future while the attribute getter is pending.
+        let mut future = Box::pin(query.next_result_async());
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+    }
+    assert!(matches!(
+        query.next_result(),
+        Some(Err(OsoError::Custom { message })) if message.contains("next_result_async")
+    ));
+    assert!(block_on(query.next_result_async()).transpose()?.is_some());
+    Ok(())
+}
+
+#[test]
+fn test_async_application_error() -> oso::Result<()> {
+    common::setup();
+    let oso = async_oso();
+    let mut query = oso.query_rule("broken", (Repo { id: 1 },))?;
+    assert!(matches!(
+        block_on(query.next_result_async()),
+        Some(Err(OsoError::ApplicationError { .. }))
+    ));
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::sync::{Arc, Mutex};
+
+use oso::{Decision, JsonLinesSink, Oso, PolarClass, RuleSource};
+
+mod common;
+
+#[derive(PolarClass, Clone, Debug)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+fn user(name: &str) -> User {
+    User {
+        name: name.to_owned(),
+    }
+}
+
+fn audited_oso() -> (Oso, Arc<Mutex<Vec<Decision>>>) {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.load_str(
+        r#"allow(user: User, "read", "repo") if user.name = "alice";
+           allow(_: User, "read", "readme");"#,
+    )
+    .unwrap();
+    let decisions = Arc::new(Mutex::new(vec![]));
+    let recorded = decisions.clone();
+    oso.set_audit_sink(move |decision: &Decision| recorded.lock().unwrap().push(decision.clone()));
+    (oso, decisions)
+}
+
+#[test]
+fn test_audit_is_allowed() -> oso::Result<()> {
+    common::setup();
+    let (oso, decisions) = audited_oso();
+    assert!(oso.is_allowed(user("alice"), "read", "repo")?);
+    assert!(!oso.is_allowed(user("bob"), "read", "repo")?);
+    assert!(oso.is_allowed(user("bob"), "read", "readme")?);
+
+    let decisions = decisions.lock().unwrap();
+    assert_eq!(decisions.len(), 3);
+    let allowed = &decisions[0];
+    assert_eq!(allowed.rule, "allow");
+    assert_eq!(
+        allowed.args,
+        vec!["User TYPE `User`", "\"read\"", "\"repo\""]
+    );
+    assert!(allowed.allowed);
+    assert!(allowed.error.is_none());
+    assert_eq!(
+        allowed.source,
+        Some(RuleSource {
+            filename: None,
+            line: 1,
+            column: 1
+        })
+    );
+
+    assert!(!decisions[1].allowed);
+    assert!(decisions[1].source.is_none());
+    assert_eq!(decisions[2].source.as_ref().unwrap().line, 2);
+    Ok(())
+}
+
+#[test]
+fn test_audit_query_rule() -> oso::Result<()> {
+    common::setup();
+    let (oso, decisions) = audited_oso();
+
+    // A query is recorded once, at its first answer.
+    let mut query = oso.query_rule("allow", (user("alice"), "read", "repo"))?;
+    assert!(decisions.lock().unwrap().is_empty());
+    assert!(query.next().is_some());
+    assert!(query.next().is_none());
+    assert_eq!(decisions.lock().unwrap().len(), 1);
+
+    // Errors are recorded as denials.
+    assert!(oso.query_rule_once("missing", (1,)).is_err());
+    let decisions = decisions.lock().unwrap();
+    assert!(!decisions[1].allowed);
+    assert!(decisions[1].error.as_ref().unwrap().contains("missing"));
+    Ok(())
+}
+
+#[test]
+fn test_audit_skips_authorized_query() -> oso::Result<()> {
+    common::setup();
+    let (mut oso, decisions) = audited_oso();
+    oso.clear_rules()?;
+    oso.load_str(r#"allow(_: User, "read", user: User) if user.name = "alice";"#)?;
+
+    let filter = oso.authorized_query(user("bob"), "read", "User")?;
+    assert_eq!(filter.conditions().len(), 1);
+    assert!(decisions.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_json_lines_sink() -> oso::Result<()> {
+    common::setup();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    let mut oso = Oso::new();
+    oso.load_str(r#"allow("alice", "read", "repo");"#)?;
+    oso.set_audit_sink(JsonLinesSink::new(std::fs::File::create(&path).unwrap()));
+    assert!(oso.is_allowed("alice", "read", "repo")?);
+    assert!(!oso.is_allowed("bob", "read", "repo")?);
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["rule"], "allow");
+    assert_eq!(lines[0]["args"][0], "\"alice\"");
+    assert_eq!(lines[0]["allowed"], true);
+    assert_eq!(lines[0]["source"]["line"], 1);
+    assert!(lines[0]["timestamp_ms"].as_u64().unwrap() > 0);
+    assert!(lines[0]["elapsed_us"].is_u64());
+    assert_eq!(lines[1]["allowed"], false);
+    assert!(lines[1]["source"].is_null());
+    Ok(())
+}
@@ -0,0 +1,159 @@
+// The `oso` binary needs the `cli` feature, which `make rust-test` enables.
+#![cfg(feature = "cli")]
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Run the `oso` binary in `dir`, returning whether it exited successfully
+/// and what it printed.
+fn oso(dir: &Path, args: &[&str]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_oso"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    (output.status.success(), stdout)
+}
+
+fn policy(src: &str) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("policy.polar"), src).unwrap();
+    dir
+}
+
+#[test]
+fn test_check() {
+    let dir = policy("allow(actor, \"read\", _resource) if actor = \"alice\";");
+    let (passed, stdout) = oso(dir.path(), &["check", "policy.polar"]);
+    assert!(passed, "{}", stdout);
+    assert_eq!(stdout, "0 errors, 0 warnings\n");
+
+    let dir = policy("allow(actor, action, resource) if x;");
+    let (passed, stdout) = oso(dir.path(), &["check", "policy.polar"]);
+    assert!(!passed);
+    assert!(stdout.contains("Singleton variable x"), "{}", stdout);
+
+    let dir = policy("allow(x, y, z) if x = y and y = z or x;");
+    assert!(oso(dir.path(), &["check", "policy.polar"]).0);
+    let (passed, stdout) = oso(dir.path(), &["check", "--deny-warnings", "policy.polar"]);
+    assert!(!passed);
+    assert!(stdout.ends_with("0 errors, 1 warnings\n"), "{}", stdout);
+}
+
+#[test]
+fn test_check_unregistered_classes() {
+    // A misspelled specializer is reported.
+    let dir = policy("allow(_: Usr, \"read\", _resource);");
+    let (passed, stdout) = oso(dir.path(), &["check", "policy.polar"]);
+    assert!(passed);
+    assert!(
+        stdout.starts_with("warning: Unknown specializer Usr"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("0 errors, 1 warnings\n"), "{}", stdout);
+    assert!(!oso(dir.path(), &["check", "--deny-warnings", "policy.polar"]).0);
+
+    let dir = policy("actor User {}\nallow(_actor, \"read\", _resource);");
+    let (passed, stdout) = oso(dir.path(), &["check", "policy.polar"]);
+    assert!(!passed);
+    assert!(
+        stdout.starts_with("error: Unregistered class: User"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.ends_with("1 errors, 1 warnings\n"), "{}", stdout);
+
+    // Classes the application might register are still printed, as notes.
+    let (passed, stdout) = oso(
+        dir.path(),
+        &["check", "--allow-unregistered", "policy.polar"],
+    );
+    assert!(passed, "{}", stdout);
+    assert!(
+        stdout.starts_with("note: Unregistered class: User"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.ends_with("0 errors, 1 warnings, 1 notes\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn test_test() {
+    let dir = policy(
+        r#"allow(actor, "read", _resource) if actor = "alice";
+test "alice can read" {
+  assert allow("alice", "read", "repo");
+}"#,
+    );
+    let (passed, stdout) = oso(dir.path(), &["test", "policy.polar"]);
+    assert!(passed, "{}", stdout);
+    assert!(stdout.ends_with("1 passed, 0 failed\n"), "{}", stdout);
+
+    let dir = policy(
+        r#"allow(actor, "read", _resource) if actor = "alice";
+test "bob can read" {
+  assert allow("bob", "read", "repo");
+}"#,
+    );
+    let (passed, stdout) = oso(dir.path(), &["test", "policy.polar"]);
+    assert!(!passed);
+    assert!(stdout.starts_with("FAIL bob can read"), "{}", stdout);
+    assert!(stdout.ends_with("0 passed, 1 failed\n"), "{}", stdout);
+}
+
+#[test]
+fn test_test_skips_policies_that_need_the_app() {
+    let dir = policy(
+        r#"actor User {}
+resource Repo {
+  permissions = ["read"];
+}
+allow(actor, action, resource) if has_permission(actor, action, resource);
+has_permission(_: User, "read", _: Repo);
+test "users can read" {
+  assert allow(new User(), "read", new Repo());
+}"#,
+    );
+    let (passed, stdout) = oso(dir.path(), &["test", "policy.polar"]);
+    assert!(!passed);
+    assert!(
+        stdout.starts_with("The policy uses names the application registers: Repo, User."),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.ends_with("0 passed, 0 failed, 1 skipped\n"),
+        "{}",
+        stdout
+    );
+    assert!(oso(dir.path(), &["test", "--allow-skip", "policy.polar"]).0);
+
+    // Errors that don't depend on the app still fail.
+    let dir = policy("allow(_: User, _action, _resource) if x;");
+    let (passed, stdout) = oso(dir.path(), &["test", "--allow-skip", "policy.polar"]);
+    assert!(!passed);
+    assert!(stdout.contains("Singleton variable x"), "{}", stdout);
+}
+
+#[test]
+fn test_fmt() {
+    let dir = policy("allow(actor, action, resource) if actor = action and action = resource;");
+    let (passed, stdout) = oso(dir.path(), &["fmt", "--check", "policy.polar"]);
+    assert!(!passed);
+    assert_eq!(stdout, "policy.polar needs formatting\n");
+
+    assert!(oso(dir.path(), &["fmt", "policy.polar"]).0);
+    let formatted = fs::read_to_string(dir.path().join("policy.polar")).unwrap();
+    assert_eq!(
+        formatted,
+        "allow(actor, action, resource) if\n  actor = action and\n  action = resource;\n"
+    );
+    assert!(oso(dir.path(), &["fmt", "--check", "policy.polar"]).0);
+}
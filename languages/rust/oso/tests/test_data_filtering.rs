@@ -0,0 +1,578 @@
+use oso::data_filtering::{
+    Comparison, Condition, Datum, FilterRelation, Projection, SqlAdapter, SqlQuery, SqlValue,
+};
+use oso::{Adapter, Oso, PolarClass, PolarValue, Relation};
+use polar_core::terms::{Numeric, Value};
+
+mod common;
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+    #[polar(attribute)]
+    org_id: i64,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Org {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Repo {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    org_id: i64,
+}
+
+/// A repo that others are forked from, stored alongside them.
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Upstream {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    org_id: i64,
+}
+
+/// The same data as `sqlite_db`, with no org stored as `org_id: 0`.
+fn orgs() -> Vec<Org> {
+    vec![
+        Org {
+            id: 1,
+            name: "public".to_owned(),
+        },
+        Org {
+            id: 2,
+            name: "private".to_owned(),
+        },
+    ]
+}
+
+fn repos() -> Vec<Repo> {
+    vec![
+        Repo { id: 1, org_id: 1 },
+        Repo { id: 2, org_id: 2 },
+        Repo { id: 3, org_id: 1 },
+        Repo { id: 4, org_id: 0 },
+    ]
+}
+
+fn data_filtering_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(
+        Org::get_polar_class_builder()
+            .with_equality_check()
+            .add_field("id", "Integer")
+            .add_field("name", "String")
+            .add_relation("repos", Relation::many("Repo", "id", "org_id"))
+            .add_attribute_getter("repos", |org: &Org| {
+                repos()
+                    .into_iter()
+                    .filter(|repo| repo.org_id == org.id)
+                    .collect::<Vec<_>>()
+            })
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Repo::get_polar_class_builder()
+            .add_field("id", "Integer")
+            .add_field("org_id", "Integer")
+            .add_relation("org", Relation::one("Org", "org_id", "id"))
+            .add_attribute_getter("org", |repo: &Repo| {
+                orgs().into_iter().find(|org| org.id == repo.org_id)
+            })
+            .add_relation("upstream", Relation::one("Upstream", "parent_id", "id"))
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Upstream::get_polar_class_builder()
+            .add_field("id", "Integer")
+            .add_field("org_id", "Integer")
+            .add_relation("org", Relation::one("Org", "org_id", "id"))
+            .build(),
+    )
+    .unwrap();
+    oso
+}
+
+fn field(class: &str, name: Option<&str>) -> Datum {
+    Datum::Field(Projection(class.to_owned(), name.map(str::to_owned)))
+}
+
+fn imm(value: Value) -> Datum {
+    Datum::Immediate(value)
+}
+
+fn int(i: i64) -> Value {
+    Value::Number(Numeric::Integer(i))
+}
+
+#[test]
+fn test_authorized_query_field_condition() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.org_id = user.org_id;"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let filter = oso.authorized_query(user, "read", "Repo")?;
+    assert_eq!(filter.root(), "Repo");
+    assert!(filter.relations().is_empty());
+    assert_eq!(filter.conditions().len(), 1);
+    let conditions = &filter.conditions()[0];
+    assert_eq!(conditions.len(), 1);
+    let Condition(left, op, right) = conditions.iter().next().unwrap();
+    assert_eq!(*op, Comparison::Eq);
+    let pair = (left.clone(), right.clone());
+    let expected = field("Repo", Some("org_id"));
+    assert!(
+        pair == (expected.clone(), imm(int(1))) || pair == (imm(int(1)), expected),
+        "{}",
+        filter
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_relation() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(_: User, "read", repo: Repo) if repo.org.name = "public";"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let filter = oso.authorized_query(user, "read", "Repo")?;
+    assert_eq!(
+        filter.relations(),
+        &[FilterRelation(
+            "Repo".to_owned(),
+            "org".to_owned(),
+            "Org".to_owned()
+        )]
+    );
+    assert_eq!(filter.relation("Repo", "org")?.other_field, "id");
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_denied() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", _: Repo) if user.name = "admin";"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let filter = oso.authorized_query(user, "read", "Repo")?;
+    // An unsatisfiable filter: `true = false`.
+    assert_eq!(
+        filter.conditions(),
+        &[[Condition(
+            imm(Value::Boolean(true)),
+            Comparison::Eq,
+            imm(Value::Boolean(false))
+        )]
+        .into_iter()
+        .collect()]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_instance_values() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.id = user;"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let filter = oso.authorized_query(user.clone(), "read", "Repo")?;
+    let Condition(left, _, right) = filter.conditions()[0].iter().next().unwrap();
+    let value = match (left, right) {
+        (Datum::Immediate(value), _) | (_, Datum::Immediate(value)) => value,
+        _ => panic!("expected an immediate value: {}", filter),
+    };
+    match filter.to_polar_value(value)? {
+        PolarValue::Instance(instance) => {
+            assert_eq!(instance.downcast::<User>(None).unwrap(), &user)
+        }
+        v => panic!("expected an instance: {:?}", v),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_authorized_resources() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.org_id = user.org_id;"#)?;
+
+    let repos = [
+        Repo { id: 1, org_id: 1 },
+        Repo { id: 2, org_id: 2 },
+        Repo { id: 3, org_id: 1 },
+    ];
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let allowed = oso.authorized_resources(user, "read", "Repo", |filter| {
+        let org_id = filter.conditions()[0]
+            .iter()
+            .find_map(|Condition(left, _, right)| match (left, right) {
+                (Datum::Immediate(v), _) | (_, Datum::Immediate(v)) => Some(v.clone()),
+                _ => None,
+            })
+            .unwrap();
+        Ok(repos
+            .iter()
+            .filter(|repo| int(repo.org_id) == org_id)
+            .cloned()
+            .collect())
+    })?;
+    assert_eq!(allowed, vec![repos[0].clone(), repos[2].clone()]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_unregistered_class() {
+    common::setup();
+    let oso = data_filtering_oso();
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert!(matches!(
+        oso.authorized_query(user, "read", "Issue"),
+        Err(oso::OsoError::MissingClassError { .. })
+    ));
+}
+
+fn sqlite_db() -> rusqlite::Connection {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.execute_batch(
+        r#"
+        CREATE TABLE orgs (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+        CREATE TABLE repos (
+            id INTEGER PRIMARY KEY,
+            org_id INTEGER REFERENCES orgs(id),
+            parent_id INTEGER REFERENCES repos(id)
+        );
+        INSERT INTO orgs VALUES (1, 'public'), (2, 'private');
+        INSERT INTO repos VALUES (1, 1, NULL), (2, 2, NULL), (3, 1, 2), (4, NULL, NULL);
+        "#,
+    )
+    .unwrap();
+    conn
+}
+
+fn sql_adapter() -> SqlAdapter {
+    SqlAdapter::new()
+        .table("Org", "orgs")
+        .table("Repo", "repos")
+        .table("Upstream", "repos")
+}
+
+fn fetch_repos(conn: &rusqlite::Connection, query: SqlQuery) -> oso::Result<Vec<Repo>> {
+    let params = query.params.iter().map(|param| match param {
+        SqlValue::Null => rusqlite::types::Value::Null,
+        SqlValue::Boolean(b) => rusqlite::types::Value::Integer(*b as i64),
+        SqlValue::Integer(i) => rusqlite::types::Value::Integer(*i),
+        SqlValue::Float(f) => rusqlite::types::Value::Real(*f),
+        SqlValue::Text(s) => rusqlite::types::Value::Text(s.clone()),
+    });
+    let mut stmt = conn.prepare(&query.sql).unwrap();
+    let mut repos = stmt
+        .query_map(rusqlite::params_from_iter(params), |row| {
+            Ok(Repo {
+                id: row.get("id")?,
+                org_id: row.get::<_, Option<i64>>("org_id")?.unwrap_or(0),
+            })
+        })
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    repos.sort_by_key(|repo| repo.id);
+    Ok(repos)
+}
+
+fn sql_repo_ids(oso: &Oso, actor: impl oso::ToPolar) -> oso::Result<Vec<i64>> {
+    let conn = sqlite_db();
+    let adapter = sql_adapter();
+    let repos = oso.authorized_resources(actor, "read", "Repo", |filter| {
+        fetch_repos(&conn, adapter.build_query(filter)?)
+    })?;
+    Ok(repos.into_iter().map(|repo| repo.id).collect())
+}
+
+#[test]
+fn test_sql_adapter_field_condition() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.org_id = user.org_id;"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let query =
+        sql_adapter().build_query(&oso.authorized_query(user.clone(), "read", "Repo")?)?;
+    assert_eq!(
+        query,
+        SqlQuery {
+            sql: r#"SELECT DISTINCT "repos".* FROM "repos" WHERE ("repos"."org_id" = ?)"#
+                .to_owned(),
+            params: vec![SqlValue::Integer(1)],
+        }
+    );
+    assert_eq!(sql_repo_ids(&oso, user)?, vec![1, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_relation() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(_: User, "read", repo: Repo) if repo.org.name = "private";"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let query =
+        sql_adapter().build_query(&oso.authorized_query(user.clone(), "read", "Repo")?)?;
+    assert_eq!(
+        query.sql,
+        r#"SELECT DISTINCT "repos".* FROM "repos" LEFT JOIN "orgs" AS "repos_org" ON "repos"."org_id" = "repos_org"."id" WHERE ("repos_org"."name" = ?)"#
+    );
+    assert_eq!(sql_repo_ids(&oso, user)?, vec![2]);
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_relation_in_disjunction() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    // Repo 4 has no org, so it only matches the second rule.
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.org.name = "private";
+           allow(_: User, "read", repo: Repo) if repo.org_id = nil;"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(sql_repo_ids(&oso, user)?, vec![2, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_joins_table_twice() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.upstream.org_id = 2;
+           allow(_: User, "read", repo: Repo) if repo.id = 1;"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let query =
+        sql_adapter().build_query(&oso.authorized_query(user.clone(), "read", "Repo")?)?;
+    assert!(
+        query.sql.starts_with(
+            r#"SELECT DISTINCT "repos".* FROM "repos" LEFT JOIN "repos" AS "repos_upstream" ON "repos"."parent_id" = "repos_upstream"."id" WHERE "#
+        ),
+        "{}",
+        query.sql
+    );
+    assert_eq!(sql_repo_ids(&oso, user.clone())?, vec![1, 3]);
+
+    // The conditions can't say which relation to `Org` they refer to.
+    oso.clear_rules()?;
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.org.name = "private";
+           allow(_: User, "read", repo: Repo) if repo.upstream.org.name = "private";"#,
+    )?;
+    assert!(sql_adapter()
+        .build_query(&oso.authorized_query(user, "read", "Repo")?)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_instance_condition() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(org: Org, "read", repo: Repo) if repo.org = org;"#)?;
+
+    let org = Org {
+        id: 2,
+        name: "private".to_owned(),
+    };
+    assert_eq!(sql_repo_ids(&oso, org)?, vec![2]);
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_disjunctions_and_lists() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.id in [2, 3];
+           allow(_: User, "read", repo: Repo) if repo.org_id = nil;
+           allow(_: User{name: "admin"}, "read", _: Repo);"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(sql_repo_ids(&oso, user)?, vec![2, 3, 4]);
+
+    let admin = User {
+        name: "admin".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(sql_repo_ids(&oso, admin)?, vec![1, 2, 3, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_sql_adapter_comparisons() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.id > 1 and repo.id <= 3 and repo.org_id != 2;"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(sql_repo_ids(&oso, user)?, vec![3]);
+    Ok(())
+}
+
+fn memory_repo_ids(oso: &Oso, actor: impl oso::ToPolar) -> oso::Result<Vec<i64>> {
+    let repos = oso.authorized_resources(actor, "read", "Repo", |filter| filter.apply(repos()))?;
+    Ok(repos.into_iter().map(|repo| repo.id).collect())
+}
+
+#[test]
+fn test_filter_apply_field_condition() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(user: User, "read", repo: Repo) if repo.org_id = user.org_id;"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(memory_repo_ids(&oso, user)?, vec![1, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_filter_apply_relation() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(_: User, "read", repo: Repo) if repo.org.name = "private";"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(memory_repo_ids(&oso, user)?, vec![2]);
+
+    oso.clear_rules()?;
+    oso.load_str(r#"allow(org: Org, "read", repo: Repo) if repo.org = org;"#)?;
+    let org = Org {
+        id: 1,
+        name: "public".to_owned(),
+    };
+    assert_eq!(memory_repo_ids(&oso, org)?, vec![1, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_filter_apply_missing_relation_in_disjunction() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    // Repo 4 has no org, so it only matches the second rule.
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.org.name = "private";
+           allow(_: User, "read", repo: Repo) if repo.id = 4;"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(memory_repo_ids(&oso, user)?, vec![2, 4]);
+    Ok(())
+}
+
+#[test]
+fn test_filter_apply_many_relation() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(r#"allow(_: User, "read", org: Org) if repo in org.repos and repo.id = 2;"#)?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    let orgs = oso.authorized_resources(user, "read", "Org", |filter| filter.apply(orgs()))?;
+    assert_eq!(
+        orgs.into_iter().map(|org| org.id).collect::<Vec<_>>(),
+        vec![2]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_filter_apply_disjunctions_and_comparisons() -> oso::Result<()> {
+    common::setup();
+    let mut oso = data_filtering_oso();
+    oso.load_str(
+        r#"allow(_: User, "read", repo: Repo) if repo.id in [1, 2];
+           allow(_: User, "read", repo: Repo) if repo.id > 3 and repo.org_id != 1;
+           allow(_: User{name: "admin"}, "read", _: Repo);"#,
+    )?;
+
+    let user = User {
+        name: "sam".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(memory_repo_ids(&oso, user.clone())?, vec![1, 2, 4]);
+
+    let filter = oso.authorized_query(user, "read", "Repo")?;
+    assert!(filter.matches(Repo { id: 2, org_id: 1 })?);
+    assert!(!filter.matches(Repo { id: 3, org_id: 1 })?);
+
+    let admin = User {
+        name: "admin".to_owned(),
+        org_id: 1,
+    };
+    assert_eq!(memory_repo_ids(&oso, admin)?, vec![1, 2, 3, 4]);
+    Ok(())
+}
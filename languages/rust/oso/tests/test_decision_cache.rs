@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use oso::{Decision, Oso, PolarClass};
+
+mod common;
+
+#[derive(Clone, Debug)]
+struct User {
+    id: i64,
+    /// Counts lookups of `role`, i.e. how often the policy was evaluated.
+    lookups: Arc<AtomicUsize>,
+}
+
+impl PolarClass for User {
+    fn get_polar_class_builder() -> oso::ClassBuilder<User> {
+        oso::Class::builder()
+            .add_attribute_getter("role", |user: &User| {
+                user.lookups.fetch_add(1, Ordering::SeqCst);
+                if user.id == 1 {
+                    "admin"
+                } else {
+                    "guest"
+                }
+            })
+            .set_cache_key(|user: &User| user.id)
+    }
+}
+
+#[derive(PolarClass, Clone, Debug)]
+struct Uncached;
+
+fn cached_oso(ttl: Duration) -> Oso {
+    let mut oso = Oso::new().with_decision_cache(ttl);
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Uncached::get_polar_class()).unwrap();
+    oso.load_str(r#"allow(user: User, "read", _) if user.role = "admin";"#)
+        .unwrap();
+    oso
+}
+
+fn user(id: i64) -> (User, Arc<AtomicUsize>) {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    (
+        User {
+            id,
+            lookups: lookups.clone(),
+        },
+        lookups,
+    )
+}
+
+#[test]
+fn test_cache_hits() -> oso::Result<()> {
+    common::setup();
+    let oso = cached_oso(Duration::from_secs(60));
+    let (admin, lookups) = user(1);
+    for _ in 0..10 {
+        assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+    // Different arguments are different decisions.
+    assert!(oso.is_allowed(admin.clone(), "read", 1)?);
+    assert!(oso.is_allowed(admin, "read", 1)?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    let (guest, lookups) = user(2);
+    assert!(!oso.is_allowed(guest.clone(), "read", "repo")?);
+    assert!(!oso.is_allowed(guest, "read", "repo")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 1);
+
+    // Classes without a cache key are never cached.
+    assert!(!oso.is_allowed(Uncached, "read", "repo")?);
+    assert!(!oso.is_allowed(Uncached, "read", "repo")?);
+    Ok(())
+}
+
+#[test]
+fn test_cache_expires() -> oso::Result<()> {
+    common::setup();
+    let oso = cached_oso(Duration::from_millis(10));
+    let (admin, lookups) = user(1);
+    assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(oso.is_allowed(admin, "read", "repo")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[test]
+fn test_cache_invalidation() -> oso::Result<()> {
+    common::setup();
+    let mut oso = cached_oso(Duration::from_secs(60));
+    let (admin, lookups) = user(1);
+    let (guest, guest_lookups) = user(2);
+    assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    assert!(!oso.is_allowed(guest.clone(), "read", "repo")?);
+
+    oso.invalidate_decisions_for(admin.clone());
+    assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    assert!(!oso.is_allowed(guest.clone(), "read", "repo")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    assert_eq!(guest_lookups.load(Ordering::SeqCst), 1);
+
+    oso.invalidate_decisions();
+    assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 3);
+
+    // Changing the policy flushes the cache.
+    oso.clear_rules()?;
+    oso.load_str(r#"allow(_: User, "write", _);"#)?;
+    assert!(!oso.is_allowed(admin.clone(), "read", "repo")?);
+    oso.clear_rules()?;
+    oso.load_str(r#"allow(user: User, "read", _) if user.role = "admin";"#)?;
+    assert!(oso.is_allowed(admin, "read", "repo")?);
+    assert_eq!(lookups.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+#[test]
+fn test_cache_hits_are_audited() -> oso::Result<()> {
+    common::setup();
+    let mut oso = cached_oso(Duration::from_secs(60));
+    let decisions = Arc::new(Mutex::new(vec![]));
+    let recorded = decisions.clone();
+    oso.set_audit_sink(move |decision: &Decision| recorded.lock().unwrap().push(decision.clone()));
+
+    let (admin, _) = user(1);
+    assert!(oso.is_allowed(admin.clone(), "read", "repo")?);
+    assert!(oso.is_allowed(admin, "read", "repo")?);
+    let decisions = decisions.lock().unwrap();
+    assert_eq!(decisions.len(), 2);
+    assert!(!decisions[0].cached);
+    assert!(decisions[1].cached);
+    assert_eq!(decisions[0].args, decisions[1].args);
+    assert_eq!(decisions[0].source, decisions[1].source);
+    assert!(decisions[1].source.is_some());
+    Ok(())
+}
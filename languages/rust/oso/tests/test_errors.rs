@@ -638,3 +638,74 @@ fn test_operator_unimplemented() -> oso::Result<()> {
 // This would raise a type error (if we did one-sided external unification,
 // but we want the matches to just fail.  This wouldn't be caught by the
 // current application error implementation.
+
+fn runtime_error(result: Option<oso::Result<oso::ResultSet>>) -> RuntimeError {
+    match result {
+        Some(Err(OsoError::Polar(PolarError(ErrorKind::Runtime(e))))) => e,
+        result => panic!("expected a runtime error, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_query_limits() -> oso::Result<()> {
+    common::setup();
+    let limits = oso::QueryLimits {
+        timeout_ms: 0,
+        max_goals: Some(1000),
+        ..oso::QueryLimits::default()
+    };
+    let mut oso = Oso::new().with_limits(limits);
+    oso.load_str(
+        "count(0);
+         count(n) if n > 0 and count(n - 1);
+         missing(xs) if x in xs and x = -1;",
+    )?;
+
+    assert!(oso.query_rule_once("count", (10,))?);
+    assert!(matches!(
+        runtime_error(oso.query_rule("count", (1000,))?.next()),
+        RuntimeError::GoalLimitExceeded { limit: 1000 }
+    ));
+
+    // The limits of one query can be changed.
+    let mut query = oso.query_rule("count", (1000,))?;
+    assert_eq!(query.limits(), limits);
+    query.set_limits(oso::QueryLimits {
+        max_stack_depth: 100,
+        ..limits
+    });
+    assert!(matches!(
+        runtime_error(query.next()),
+        RuntimeError::StackOverflow { .. }
+    ));
+
+    let numbers: Vec<i64> = (0..100_000).collect();
+    let mut query = oso.query_rule("missing", (numbers,))?;
+    query.set_limits(oso::QueryLimits {
+        timeout_ms: 1,
+        ..oso::QueryLimits::default()
+    });
+    assert!(matches!(
+        runtime_error(query.next()),
+        RuntimeError::QueryTimeout { timeout: 1, .. }
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_cancel_query() -> oso::Result<()> {
+    common::setup();
+    let oso = Oso::new();
+    let mut query = oso.query("x in [1, 2, 3]")?;
+    assert!(query.next().unwrap().is_ok());
+
+    // Cancel the query between results, from another thread.
+    let token = query.cancellation_token();
+    std::thread::spawn(move || token.cancel()).join().unwrap();
+    assert!(matches!(
+        runtime_error(query.next()),
+        RuntimeError::QueryCancelled
+    ));
+    Ok(())
+}
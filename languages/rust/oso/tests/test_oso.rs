@@ -1,4 +1,4 @@
-use oso::{Action, Oso, PolarClass};
+use oso::{Action, Oso, OsoError, PolarClass};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
@@ -222,3 +222,271 @@ fn test_get_allowed_actions() -> oso::Result<()> {
 
     Ok(())
 }
+
+fn authorize_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+    oso.load_str(
+        r#"allow(_: User{name: "sally"}, action, _: Widget) if action in ["read", "update"];
+           allow(_: User{name: "fred"}, "read", _: Widget{id: 1});
+           allow_request(_: User{name: "sally"}, request) if request = "/widgets";
+           allow_field(_: User{name: "sally"}, "read", _: Widget, field) if field in ["id", "name"];
+           allow_field(_: User{name: "fred"}, "read", _: Widget, _field);"#,
+    )
+    .unwrap();
+    oso
+}
+
+#[test]
+fn test_authorize() -> oso::Result<()> {
+    common::setup();
+    let oso = authorize_oso();
+
+    let sally = User::new(String::from("sally"));
+    let fred = User::new(String::from("fred"));
+
+    oso.authorize(sally.clone(), "read", Widget::new(1))?;
+    oso.authorize(sally, "update", Widget::new(1))?;
+    oso.authorize(fred.clone(), "read", Widget::new(1))?;
+
+    // fred can read widget 1, so updating it is forbidden.
+    assert!(matches!(
+        oso.authorize(fred.clone(), "update", Widget::new(1)),
+        Err(OsoError::Forbidden)
+    ));
+    // fred cannot read widget 2, so it is not found.
+    assert!(matches!(
+        oso.authorize(fred.clone(), "update", Widget::new(2)),
+        Err(OsoError::NotFound)
+    ));
+    assert!(matches!(
+        oso.authorize(fred, "read", Widget::new(2)),
+        Err(OsoError::NotFound)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_custom_read_action() -> oso::Result<()> {
+    common::setup();
+    let mut oso = authorize_oso();
+    oso.set_read_action("update");
+
+    let fred = User::new(String::from("fred"));
+    // fred cannot perform the read action ("update") on widget 1.
+    assert!(matches!(
+        oso.authorize(fred, "delete", Widget::new(1)),
+        Err(OsoError::NotFound)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_request() -> oso::Result<()> {
+    common::setup();
+    let oso = authorize_oso();
+
+    oso.authorize_request(User::new(String::from("sally")), "/widgets")?;
+    assert!(matches!(
+        oso.authorize_request(User::new(String::from("sally")), "/users"),
+        Err(OsoError::Forbidden)
+    ));
+    assert!(matches!(
+        oso.authorize_request(User::new(String::from("fred")), "/widgets"),
+        Err(OsoError::Forbidden)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_field() -> oso::Result<()> {
+    common::setup();
+    let oso = authorize_oso();
+
+    let sally = User::new(String::from("sally"));
+    oso.authorize_field(sally.clone(), "read", Widget::new(1), "id")?;
+    assert!(matches!(
+        oso.authorize_field(sally.clone(), "read", Widget::new(1), "secret"),
+        Err(OsoError::Forbidden)
+    ));
+    assert!(matches!(
+        oso.authorize_field(sally, "update", Widget::new(1), "id"),
+        Err(OsoError::Forbidden)
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_authorized_fields() -> oso::Result<()> {
+    common::setup();
+    let oso = authorize_oso();
+
+    let sally = User::new(String::from("sally"));
+    let fields: HashSet<String> = oso.authorized_fields(sally.clone(), "read", Widget::new(1))?;
+    assert_eq!(
+        fields,
+        vec!["id".to_string(), "name".to_string()]
+            .into_iter()
+            .collect()
+    );
+    let fields: HashSet<String> = oso.authorized_fields(sally, "update", Widget::new(1))?;
+    assert!(fields.is_empty());
+
+    // fred may read any field.
+    let fred = User::new(String::from("fred"));
+    let fields: HashSet<Action> = oso.authorized_fields(fred, "read", Widget::new(1))?;
+    assert_eq!(fields, vec![Action::Any].into_iter().collect());
+
+    Ok(())
+}
+
+#[test]
+fn test_explain_allowed() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class())?;
+    oso.register_class(Widget::get_polar_class())?;
+    oso.load_str(
+        r#"allow(user: User, "read", _: Widget) if is_admin(user);
+           allow(_: User, "read", widget: Widget) if widget.id = 1;
+           is_admin(user: User) if user.name = "sally";"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    let explanation = oso.explain_allowed(sally, "read", Widget::new(2))?;
+    assert!(explanation.allowed);
+    let allow = &explanation.rules[0];
+    assert_eq!(allow.rule, r#"allow(user: User{}, "read", _1: Widget{})"#);
+    assert!(allow.matched);
+    assert_eq!(allow.bindings["user"], "User TYPE `User`");
+    assert_eq!(allow.children[0].rule, "is_admin(user: User{})");
+    assert!(allow.children[0].matched);
+
+    // When denied, every rule tried is reported with the condition that failed.
+    let fred = User::new(String::from("fred"));
+    let explanation = oso.explain_allowed(fred, "read", Widget::new(2))?;
+    assert!(!explanation.allowed);
+    assert_eq!(explanation.rules.len(), 2);
+    assert!(explanation.rules.iter().all(|rule| !rule.matched));
+    let is_admin = &explanation.rules[0].children[0];
+    assert!(is_admin.failed_at.as_ref().unwrap().contains(r#""sally""#));
+    let by_id = &explanation.rules[1];
+    assert_eq!(
+        by_id.failed_at.as_deref(),
+        Some("Widget TYPE `Widget`.id = 1")
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&explanation.to_json()).unwrap();
+    assert_eq!(json["allowed"], false);
+    assert_eq!(json["rules"][1]["failed_at"], "Widget TYPE `Widget`.id = 1");
+
+    Ok(())
+}
+
+#[test]
+fn test_compiled_policy() -> oso::Result<()> {
+    common::setup();
+    let policy = r#"
+        allow(actor, action, resource) if has_permission(actor, action, resource);
+        actor User {}
+        resource Widget {
+          permissions = ["read", "edit"];
+          roles = ["viewer"];
+          "read" if "viewer";
+        }
+        has_role(user: User, "viewer", _: Widget) if user.name = "sally";
+        allow(_: User, "edit", widget: Widget) if widget.id = 1;"#;
+
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class())?;
+    oso.register_class(Widget::get_polar_class())?;
+    oso.load_str(policy)?;
+    let compiled = oso.compile();
+
+    // Classes are registered before loading, as with sources.
+    let mut other = Oso::new();
+    other.register_class(User::get_polar_class())?;
+    other.register_class(Widget::get_polar_class())?;
+    other.load_compiled(&compiled)?;
+    let sally = User::new(String::from("sally"));
+    let fred = User::new(String::from("fred"));
+    assert!(other.is_allowed(sally.clone(), "read", Widget::new(2))?);
+    assert!(!other.is_allowed(fred.clone(), "read", Widget::new(2))?);
+    assert!(other.is_allowed(fred, "edit", Widget::new(1))?);
+    assert!(!other.is_allowed(sally, "edit", Widget::new(2))?);
+
+    let mut unregistered = Oso::new();
+    unregistered.register_class(User::get_polar_class())?;
+    assert!(matches!(
+        unregistered.load_compiled(&compiled),
+        Err(OsoError::Polar(_))
+    ));
+    assert!(matches!(
+        Oso::new().load_compiled("{}"),
+        Err(OsoError::Polar(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_policy_tests() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .set_constructor(User::new)
+            .build(),
+    )?;
+    oso.register_class(
+        Widget::get_polar_class_builder()
+            .set_constructor(Widget::new)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"allow(user: User, "read", widget: Widget) if
+             user.name = "sally" or widget.id = 1;
+
+           test "sally reads everything" {
+             fixture sally = new User("sally");
+             assert allow(sally, "read", new Widget(2));
+             assert_not allow(sally, "write", new Widget(2));
+           }
+
+           test "fred reads widget 1" {
+             fixture fred = new User("fred");
+             assert allow(fred, "read", new Widget(1));
+             assert allow(fred, "read", new Widget(2));
+             assert fred.missing = 1;
+           }"#,
+    )?;
+
+    let results = oso.run_tests();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].passed());
+    assert_eq!(results[0].name, "sally reads everything");
+    assert_eq!(results[0].assertions, 2);
+    assert_eq!(results[0].source.as_ref().map(|s| s.line), Some(4));
+
+    let failures = &results[1].failures;
+    assert_eq!(failures.len(), 2);
+    assert_eq!(
+        failures[0].assertion,
+        r#"assert allow(fred, "read", new Widget(2))"#
+    );
+    assert_eq!(failures[0].source.as_ref().map(|s| s.line), Some(13));
+    assert!(failures[0].error.is_none());
+    assert!(failures[1].error.is_some());
+    assert_eq!(
+        results[1].to_string(),
+        r#"FAIL fred reads widget 1
+    13:21: assert allow(fred, "read", new Widget(2))
+    14:21: assert fred.missing = 1 (Application error: Attribute missing not found on type User.)"#
+    );
+    Ok(())
+}
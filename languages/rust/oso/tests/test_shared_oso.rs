@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use oso::{Oso, PolarClass, SharedOso};
+
+mod common;
+
+static_assertions::assert_impl_all!(SharedOso: Send, Sync);
+
+#[derive(PolarClass, Clone, Debug)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+fn user(name: &str) -> User {
+    User {
+        name: name.to_owned(),
+    }
+}
+
+/// Version `n` of the policy allows the user named `user-n`.
+fn policy(version: i64) -> String {
+    format!(
+        r#"version({v});
+           allow(user: User, "read", _) if user.name = "user-{v}";"#,
+        v = version
+    )
+}
+
+fn shared_oso() -> SharedOso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.load_str(&policy(0)).unwrap();
+    SharedOso::new(oso)
+}
+
+fn version(oso: &Oso) -> oso::Result<Vec<i64>> {
+    oso.query_rule("version", (oso::PolarValue::Variable("v".to_owned()),))?
+        .map(|result| result?.get_typed("v"))
+        .collect()
+}
+
+#[test]
+fn test_reload_replaces_policy() -> oso::Result<()> {
+    common::setup();
+    let oso = shared_oso();
+    assert!(oso.is_allowed(user("user-0"), "read", "repo")?);
+
+    oso.reload_str(&policy(1))?;
+    assert!(!oso.is_allowed(user("user-0"), "read", "repo")?);
+    assert!(oso.is_allowed(user("user-1"), "read", "repo")?);
+    assert_eq!(version(&oso.snapshot())?, vec![1]);
+    Ok(())
+}
+
+#[test]
+fn test_failed_reload_keeps_policy() -> oso::Result<()> {
+    common::setup();
+    let oso = shared_oso();
+    assert!(oso.reload_str("allow(x, y, z) if").is_err());
+    assert!(oso
+        .reload(|oso| {
+            oso.load_str(&policy(1))?;
+            oso.load_str("?= false;")
+        })
+        .is_err());
+    assert_eq!(version(&oso.snapshot())?, vec![0]);
+    assert!(oso.is_allowed(user("user-0"), "read", "repo")?);
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_unaffected_by_reload() -> oso::Result<()> {
+    common::setup();
+    let oso = shared_oso();
+    let snapshot = oso.snapshot();
+    let mut query = oso.query_rule("version", (oso::PolarValue::Variable("v".to_owned()),))?;
+
+    oso.reload_str(&policy(1))?;
+    assert_eq!(version(&snapshot)?, vec![0]);
+    assert_eq!(query.next().unwrap()?.get_typed::<i64>("v")?, 0);
+    assert!(query.next().is_none());
+    Ok(())
+}
+
+#[test]
+fn test_queries_during_reloads() {
+    common::setup();
+    let oso = Arc::new(shared_oso());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers = (0..8)
+        .map(|_| {
+            let (oso, done) = (oso.clone(), done.clone());
+            thread::spawn(move || {
+                let mut queries = 0;
+                while !done.load(Ordering::SeqCst) || queries < 100 {
+                    // Each snapshot has exactly one, complete policy.
+                    let snapshot = oso.snapshot();
+                    let versions = version(&snapshot).unwrap();
+                    assert_eq!(versions.len(), 1, "{:?}", versions);
+                    let name = format!("user-{}", versions[0]);
+                    assert!(snapshot.is_allowed(user(&name), "read", "repo").unwrap());
+
+                    // Queries directly on the shared instance never fail.
+                    oso.is_allowed(user("user-1"), "read", "repo").unwrap();
+                    queries += 1;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let writers = (0..2)
+        .map(|w| {
+            let oso = oso.clone();
+            thread::spawn(move || {
+                for i in 1..=50 {
+                    oso.reload_str(&policy(w * 100 + i)).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    let last = version(&oso.snapshot()).unwrap();
+    assert!(last == vec![50] || last == vec![150], "{:?}", last);
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+use oso::{Oso, OsoError, ReloadEvent, SharedOso};
+
+mod common;
+
+const INTERVAL: Duration = Duration::from_millis(10);
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn watched_policy(
+    src: &str,
+) -> (
+    tempfile::TempDir,
+    std::path::PathBuf,
+    Arc<SharedOso>,
+    oso::PolicyWatcher,
+    mpsc::Receiver<ReloadEvent>,
+) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("policy.polar");
+    fs::write(&path, src).unwrap();
+
+    let oso = Arc::new(SharedOso::new(Oso::new()));
+    let (tx, rx) = mpsc::channel();
+    let watcher = oso
+        .watch_files(vec![&path], INTERVAL, move |event| {
+            let _ = tx.send(event);
+        })
+        .unwrap();
+    (dir, path, oso, watcher, rx)
+}
+
+fn allowed(oso: &SharedOso, action: &str) -> bool {
+    oso.is_allowed("alice", action, "repo").unwrap()
+}
+
+#[test]
+fn test_watch_reloads_changed_files() {
+    common::setup();
+    let (_dir, path, oso, _watcher, events) = watched_policy(r#"allow("alice", "read", "repo");"#);
+    assert!(matches!(
+        events.recv_timeout(TIMEOUT).unwrap(),
+        ReloadEvent::Reloaded { .. }
+    ));
+    assert!(allowed(&oso, "read"));
+
+    fs::write(&path, r#"allow("alice", "write", "repo");"#).unwrap();
+    assert!(matches!(
+        events.recv_timeout(TIMEOUT).unwrap(),
+        ReloadEvent::Reloaded { .. }
+    ));
+    assert!(!allowed(&oso, "read"));
+    assert!(allowed(&oso, "write"));
+}
+
+#[test]
+fn test_watch_keeps_policy_with_errors() {
+    common::setup();
+    let (_dir, path, oso, _watcher, events) = watched_policy(r#"allow("alice", "read", "repo");"#);
+    events.recv_timeout(TIMEOUT).unwrap();
+
+    // A parse error and a call to an undefined rule.
+    fs::write(&path, r#"allow("alice", "write", "repo") if"#).unwrap();
+    match events.recv_timeout(TIMEOUT).unwrap() {
+        ReloadEvent::Rejected { errors, .. } => {
+            assert!(matches!(errors[0], OsoError::Polar(_)), "{:?}", errors)
+        }
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(allowed(&oso, "read"));
+
+    fs::write(&path, r#"allow(actor, "write", _) if is_alice(actor);"#).unwrap();
+    match events.recv_timeout(TIMEOUT).unwrap() {
+        ReloadEvent::Rejected { errors, .. } => assert_eq!(errors.len(), 1),
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(allowed(&oso, "read"));
+
+    // Removing the file is reported too.
+    fs::remove_file(&path).unwrap();
+    match events.recv_timeout(TIMEOUT).unwrap() {
+        ReloadEvent::Rejected { errors, .. } => {
+            assert!(matches!(errors[0], OsoError::Io(_)), "{:?}", errors)
+        }
+        event => panic!("unexpected event: {:?}", event),
+    }
+    assert!(allowed(&oso, "read"));
+}
+
+#[test]
+fn test_watch_reports_warnings() {
+    common::setup();
+    let (_dir, _path, _oso, _watcher, events) = watched_policy("f(1);");
+    match events.recv_timeout(TIMEOUT).unwrap() {
+        ReloadEvent::Reloaded { warnings } => assert!(!warnings.is_empty()),
+        event => panic!("unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn test_watch_initial_load_error() {
+    common::setup();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("policy.polar");
+    fs::write(&path, "allow(x, y, z) if").unwrap();
+
+    let oso = Arc::new(SharedOso::new(Oso::new()));
+    assert!(oso.watch_files(vec![&path], INTERVAL, |_| {}).is_err());
+}
+
+#[test]
+fn test_watch_stops_when_dropped() {
+    common::setup();
+    let (_dir, path, oso, watcher, events) = watched_policy(r#"allow("alice", "read", "repo");"#);
+    events.recv_timeout(TIMEOUT).unwrap();
+    watcher.stop();
+
+    fs::write(&path, r#"allow("alice", "write", "repo");"#).unwrap();
+    assert!(events.recv_timeout(INTERVAL * 10).is_err());
+    assert!(allowed(&oso, "read"));
+}
@@ -3,7 +3,7 @@
 
 use polar_core::error::PolarError;
 pub use polar_core::polar::Polar;
-pub use polar_core::query::Query;
+pub use polar_core::query::{CancellationToken, Query};
 use polar_core::{error, terms};
 
 use std::ffi::{c_void, CStr, CString};
@@ -110,6 +110,28 @@ pub extern "C" fn polar_load(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_compile(polar_ptr: *mut Polar) -> *mut CResult<c_char> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let compiled_json = serde_json::to_string(&polar.compile()).unwrap();
+        Ok(CString::new(compiled_json)
+            .expect("JSON should not contain any 0 bytes")
+            .into_raw())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn polar_load_compiled(
+    polar_ptr: *mut Polar,
+    compiled: *const c_char,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        from_json(compiled).and_then(|compiled| polar.load_compiled(compiled))
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> *mut CResult<c_void> {
     ffi_try!({
@@ -317,6 +339,23 @@ pub extern "C" fn polar_bind(
     })
 }
 
+/// Get a token that cancels the query. Unlike the query, the token may be
+/// used from any thread. Free it with `cancellation_token_free`.
+#[no_mangle]
+pub extern "C" fn polar_query_cancellation_token(query_ptr: *mut Query) -> *mut CancellationToken {
+    let query = unsafe { ffi_ref!(query_ptr) };
+    box_ptr!(query.cancellation_token())
+}
+
+/// Cancel the query the token belongs to. Its next event is a
+/// `QueryCancelled` runtime error.
+#[no_mangle]
+pub extern "C" fn polar_cancel_query(token_ptr: *mut CancellationToken) -> i32 {
+    let token = unsafe { ffi_ref!(token_ptr) };
+    token.cancel();
+    POLAR_SUCCESS
+}
+
 #[no_mangle]
 pub extern "C" fn polar_get_external_id(polar_ptr: *mut Polar) -> u64 {
     let polar = unsafe { ffi_ref!(polar_ptr) };
@@ -349,6 +388,14 @@ pub extern "C" fn query_free(query: *mut Query) -> i32 {
     POLAR_SUCCESS
 }
 
+/// Recovers the original boxed version of `token` so that
+/// it can be properly freed
+#[no_mangle]
+pub extern "C" fn cancellation_token_free(token: *mut CancellationToken) -> i32 {
+    std::mem::drop(unsafe { Box::from_raw(token) });
+    POLAR_SUCCESS
+}
+
 /// Recovers the original boxed version of `result` so that
 /// it can be properly freed
 #[no_mangle]
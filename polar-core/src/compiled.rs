@@ -0,0 +1,44 @@
+//! Loaded policies, serialized so that they can be loaded again without
+//! parsing, rewriting or validating their sources.
+
+use serde::{Deserialize, Serialize};
+
+use crate::policy_test::PolicyTest;
+use crate::rules::Rule;
+use crate::terms::{Symbol, Term};
+
+/// The version of the compiled policy format. Compiled policies of other
+/// versions are rejected, and must be compiled again from their sources.
+pub const COMPILED_POLICY_VERSION: u32 = 2;
+
+/// The contents of a knowledge base after loading a policy.
+///
+/// Resource blocks are kept as the rules and rule types they were rewritten
+/// into and the `Actor` and `Resource` unions they declared. Terms keep no
+/// source information, so errors about compiled rules and failed assertions
+/// in compiled tests can't point into the policy files. Inline queries are
+/// run when the policy is loaded from its sources, and aren't compiled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CompiledPolicy {
+    pub(crate) version: u32,
+    /// The version of `polar-core` that compiled the policy.
+    pub(crate) polar_version: String,
+    /// Rules, in the order they were loaded for each rule name.
+    pub(crate) rules: Vec<Rule>,
+    pub(crate) rule_types: Vec<Rule>,
+    pub(crate) actors: Vec<Term>,
+    pub(crate) resources: Vec<Term>,
+    pub(crate) tabled_rules: Vec<Symbol>,
+    /// `test` blocks, in the order they were loaded.
+    pub(crate) tests: Vec<PolicyTest>,
+    /// The registered classes and instances the rules and tests refer to,
+    /// which must be registered before the policy is loaded.
+    pub(crate) classes: Vec<Symbol>,
+    /// The other registered constants the rules and tests refer to, with
+    /// their values. They are registered when the policy is loaded, unless
+    /// the host has registered them already.
+    pub(crate) constants: Vec<(Symbol, Term)>,
+    /// The next generated symbol, so that symbols generated after loading
+    /// don't clash with those in the rules.
+    pub(crate) next_gensym: u64,
+}
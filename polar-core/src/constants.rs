@@ -1,7 +1,7 @@
 use crate::terms::{Symbol, Term};
 use std::collections::HashMap;
 
-#[derive(Default, Debug)]
+#[derive(Clone, Default, Debug)]
 pub(crate) struct Constants {
     // Symbol -> Term (populated by *all* constants)
     pub symbol_to_term: HashMap<Symbol, Term>,
@@ -34,6 +34,10 @@ impl Constants {
         self.class_symbol_to_id.get(symbol)
     }
 
+    pub(crate) fn class_symbols(&self) -> impl Iterator<Item = &Symbol> {
+        self.class_symbol_to_id.keys()
+    }
+
     pub(crate) fn get_symbol_for_class_id(&self, id: &u64) -> Option<&Symbol> {
         self.class_id_to_symbol.get(id)
     }
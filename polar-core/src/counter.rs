@@ -27,6 +27,16 @@ impl Counter {
         }
     }
 
+    /// The ID that `next` will return.
+    pub fn peek(&self) -> u64 {
+        self.next.load(Ordering::SeqCst)
+    }
+
+    /// Skip ahead so that `next` returns at least `next`.
+    pub fn fast_forward(&self, next: u64) {
+        self.next.fetch_max(next.min(MAX_ID), Ordering::SeqCst);
+    }
+
     /// Return a monotonically increasing integer ID.
     ///
     /// Wraps around at 52 bits of precision so that it can be safely
@@ -107,6 +107,8 @@ impl PolarError {
                 // These errors never have context.
                 StackOverflow { .. }
                 | QueryTimeout { .. }
+                | GoalLimitExceeded { .. }
+                | QueryCancelled
                 | IncompatibleBindings { .. }
                 | DataFilteringFieldMissing { .. }
                 | DataFilteringUnsupportedOp { .. }
@@ -118,6 +120,7 @@ impl PolarError {
             Validation(e) => match e {
                 // These errors track `term`, from which we calculate the context.
                 ResourceBlock { term, .. }
+                | PolicyTest { term, .. }
                 | SingletonVariable { term, .. }
                 | UndefinedRuleCall { term }
                 | DuplicateResourceBlockDeclaration {
@@ -345,6 +348,12 @@ pub enum RuntimeError {
         elapsed: u64,
         timeout: u64,
     },
+    /// The query ran more goals than `QueryLimits::max_goals` allows.
+    GoalLimitExceeded {
+        limit: u64,
+    },
+    /// The query was cancelled with its `CancellationToken`.
+    QueryCancelled,
     Application {
         msg: String,
         stack_trace: String,
@@ -400,6 +409,8 @@ impl fmt::Display for RuntimeError {
                 write!(f, "{}", msg)
             }
             Self::QueryTimeout { elapsed, timeout } => write!(f, "Query timeout: Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.", elapsed, timeout),
+            Self::GoalLimitExceeded { limit } => write!(f, "Goal limit exceeded: Query ran more than {} goals.", limit),
+            Self::QueryCancelled => write!(f, "Query cancelled"),
             Self::Application {
                 msg, stack_trace, ..
             } => {
@@ -560,6 +571,12 @@ pub enum ValidationError {
         /// Term<Symbol> where the error arose, tracked for lexical context.
         term: Term,
     },
+    /// A malformed `test` block.
+    PolicyTest {
+        /// Term where the error arose, tracked for lexical context.
+        term: Term,
+        msg: String,
+    },
     DuplicateResourceBlockDeclaration {
         /// Term<Symbol> where the error arose.
         resource: Term,
@@ -592,7 +609,7 @@ impl fmt::Display for ValidationError {
             Self::MissingRequiredRule { rule_type } => {
                 write!(f, "Missing implementation for required rule {}", rule_type)
             }
-            Self::ResourceBlock { msg, .. } => {
+            Self::ResourceBlock { msg, .. } | Self::PolicyTest { msg, .. } => {
                 write!(f, "{}", msg)
             }
             Self::SingletonVariable { term } => {
@@ -0,0 +1,183 @@
+//! Record the rules a query tries, to explain why it succeeded or failed.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::rules::Rule;
+use super::terms::{Term, Value};
+
+/// A rule that was tried while answering a query.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ExplainedRule {
+    /// The rule head, e.g. `allow(actor: User, "read", repo: Repo)`.
+    pub rule: String,
+    /// Where the rule is defined, if it was loaded from a policy.
+    pub source: Option<String>,
+    /// The call the rule was tried for, before the rule ran.
+    pub call: String,
+    /// Whether the rule body succeeded.
+    pub matched: bool,
+    /// The values of the rule's named parameters when it matched.
+    pub bindings: BTreeMap<String, String>,
+    /// The last condition in the body that failed, if any.
+    pub failed_at: Option<String>,
+    /// Rules tried for calls in the body.
+    pub children: Vec<ExplainedRule>,
+}
+
+#[derive(Clone, Debug)]
+struct Attempt {
+    parent: Option<usize>,
+    rule: Arc<Rule>,
+    call: Term,
+    matched: bool,
+    bindings: BTreeMap<String, String>,
+    failed_at: Option<Term>,
+}
+
+/// Every rule attempt in the order they were made. Unlike the trace, the log
+/// is not unwound on backtracking, so it keeps the rules that failed.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ExplainLog {
+    attempts: Vec<Attempt>,
+}
+
+impl ExplainLog {
+    /// Record an attempt to apply `rule` to `call`, returning its ID.
+    pub(crate) fn enter(&mut self, parent: Option<usize>, rule: Arc<Rule>, call: Term) -> usize {
+        self.attempts.push(Attempt {
+            parent,
+            rule,
+            call,
+            matched: false,
+            bindings: BTreeMap::new(),
+            failed_at: None,
+        });
+        self.attempts.len() - 1
+    }
+
+    pub(crate) fn call(&self, id: usize) -> &Term {
+        &self.attempts[id].call
+    }
+
+    /// Mark the attempt `id` as matched, with `call` dereferenced at the time
+    /// of the match. Returns the parent attempt.
+    pub(crate) fn matched(&mut self, id: usize, call: &Term) -> Option<usize> {
+        let attempt = &mut self.attempts[id];
+        attempt.matched = true;
+        if let Value::Call(call) = call.value() {
+            for (param, arg) in attempt.rule.params.iter().zip(&call.args) {
+                if let Value::Variable(name) = param.parameter.value() {
+                    if !name.is_temporary_var() {
+                        attempt.bindings.insert(name.0.clone(), arg.to_string());
+                    }
+                }
+            }
+        }
+        attempt.parent
+    }
+
+    /// Record that the condition `at` failed while the attempt `id` was running.
+    pub(crate) fn failed(&mut self, id: usize, at: Term) {
+        let attempt = &mut self.attempts[id];
+        if !attempt.matched {
+            attempt.failed_at = Some(at);
+        }
+    }
+
+    /// Build the tree of attempts under each top-level call.
+    pub(crate) fn explain(&self) -> Vec<ExplainedRule> {
+        let mut children: Vec<Vec<usize>> = vec![vec![]; self.attempts.len()];
+        let mut roots = vec![];
+        for (id, attempt) in self.attempts.iter().enumerate() {
+            match attempt.parent {
+                Some(parent) => children[parent].push(id),
+                None => roots.push(id),
+            }
+        }
+        roots
+            .into_iter()
+            .map(|id| self.explain_attempt(id, &children))
+            .collect()
+    }
+
+    fn explain_attempt(&self, id: usize, children: &[Vec<usize>]) -> ExplainedRule {
+        let attempt = &self.attempts[id];
+        ExplainedRule {
+            rule: attempt.rule.head_as_string(),
+            source: attempt
+                .rule
+                .parsed_context()
+                .map(|context| context.source_position().trim_start().to_owned()),
+            call: attempt.call.to_string(),
+            matched: attempt.matched,
+            bindings: attempt.bindings.clone(),
+            failed_at: attempt.failed_at.as_ref().map(Term::to_string),
+            children: children[id]
+                .iter()
+                .map(|&child| self.explain_attempt(child, children))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::QueryEvent;
+    use crate::polar::Polar;
+
+    fn explain(policy: &str, query: &str) -> (bool, Vec<ExplainedRule>) {
+        let polar = Polar::new();
+        polar.load_str(policy).unwrap();
+        let mut query = polar.new_query(query, false).unwrap();
+        query.set_explain(true);
+        let success = match query.next_event().unwrap() {
+            QueryEvent::Result { .. } => true,
+            QueryEvent::Done { .. } => false,
+            event => panic!("unexpected event: {:?}", event),
+        };
+        (success, query.explanation().unwrap())
+    }
+
+    #[test]
+    fn test_explain_success() {
+        let (success, rules) = explain(
+            "allow(actor, _action, _resource) if is_admin(actor);
+             is_admin(name) if name = \"sam\";",
+            "allow(\"sam\", \"read\", \"repo\")",
+        );
+        assert!(success);
+        assert_eq!(rules.len(), 1);
+        let allow = &rules[0];
+        assert_eq!(allow.rule, "allow(actor, _action, _resource)");
+        assert_eq!(allow.call, "allow(\"sam\", \"read\", \"repo\")");
+        assert!(allow.matched);
+        assert_eq!(allow.bindings["actor"], "\"sam\"");
+        assert!(!allow.bindings.contains_key("_action"));
+        assert_eq!(allow.source.as_deref(), Some("at line 1, column 1"));
+        assert_eq!(allow.children.len(), 1);
+        assert!(allow.children[0].matched);
+    }
+
+    #[test]
+    fn test_explain_failure() {
+        let (success, rules) = explain(
+            "allow(actor, _action, _resource) if is_admin(actor);
+             allow(_actor, \"read\", resource) if resource = \"public\";
+             is_admin(name) if name = \"sam\";",
+            "allow(\"leina\", \"read\", \"repo\")",
+        );
+        assert!(!success);
+        assert_eq!(rules.len(), 2);
+        assert!(rules.iter().all(|rule| !rule.matched));
+
+        // The failed rule is kept, along with the rules it called.
+        let is_admin = &rules[0].children[0];
+        assert_eq!(is_admin.call, "is_admin(\"leina\")");
+        assert!(!is_admin.matched);
+        assert_eq!(is_admin.failed_at.as_deref(), Some("\"leina\" = \"sam\""));
+        assert_eq!(rules[1].failed_at.as_deref(), Some("\"repo\" = \"public\""));
+    }
+}
@@ -47,11 +47,11 @@ pub struct Filter {
 /// from the `Foo` type to the `Bar` type, accessed using the `bar` field
 /// on `Foo`.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Relation(TypeName, FieldName, TypeName);
+pub struct Relation(pub TypeName, pub FieldName, pub TypeName);
 
 /// A constraint that must hold for a record in the data source.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Condition(Datum, Comparison, Datum);
+pub struct Condition(pub Datum, pub Comparison, pub Datum);
 
 /// The left or right side of a Condition.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
@@ -75,7 +75,7 @@ pub enum Comparison {
 
 /// An abstract "field reference" on a record from a named data source.
 #[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Projection(TypeName, Option<FieldName>);
+pub struct Projection(pub TypeName, pub Option<FieldName>);
 
 type TypeInfo = Map<TypeName, Map<FieldName, Type>>;
 type VarTypes = Map<PathVar, TypeName>;
@@ -130,6 +130,22 @@ impl PathVar {
 }
 
 impl Filter {
+    /// The name of the data type being filtered.
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Relations from the root type to other types, ordered so that the source
+    /// of every relation is either the root or the target of an earlier relation.
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    /// Conditions in disjunctive normal form.
+    pub fn conditions(&self) -> &[Set<Condition>] {
+        &self.conditions
+    }
+
     pub fn build(
         types: TypeInfo,
         partials: PartialResults,
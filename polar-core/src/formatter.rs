@@ -0,0 +1,585 @@
+//! A canonical, comment-preserving formatter for Polar files.
+//!
+//! Rule bodies go on the lines after `if`, one conjunct per line. Blocks put
+//! each statement on its own line, and lists and dictionaries that don't fit
+//! in [`MAX_WIDTH`] columns or that contain comments get one element per
+//! line. Comments and single blank lines between statements are kept.
+
+use super::error::PolarResult;
+use super::lexer::{Lexer, Token};
+use super::parser::parse_lines;
+use super::sources::Source;
+
+const INDENT: usize = 2;
+pub const MAX_WIDTH: usize = 80;
+
+/// Format a Polar file. Fails with the parse error if the file doesn't parse.
+pub fn format_policy(source: Source) -> PolarResult<String> {
+    let src = source.src.clone();
+    parse_lines(source)?;
+
+    let (tokens, comments) = tokenize(&src);
+    let mut formatter = Formatter {
+        tokens: &tokens,
+        out: String::with_capacity(src.len()),
+        line: String::new(),
+        line_indent: 0,
+        indent: 0,
+        open: 0,
+        prev: None,
+        prev2: None,
+    };
+    formatter.policy();
+    for (comment, blank) in comments {
+        formatter.comment(comment, blank);
+    }
+    formatter.newline();
+    Ok(formatter.out)
+}
+
+struct Tok<'a> {
+    token: Token,
+    text: &'a str,
+    /// Comments on their own lines before the token, with whether each
+    /// follows a blank line.
+    comments: Vec<(&'a str, bool)>,
+    /// Whether a blank line separates the token from what precedes it.
+    blank: bool,
+    /// A comment on the same line after the token.
+    trailing: Option<&'a str>,
+}
+
+/// The whitespace and comments between two tokens.
+struct Gap<'a> {
+    trailing: Option<&'a str>,
+    comments: Vec<(&'a str, bool)>,
+    blank: bool,
+}
+
+impl<'a> Gap<'a> {
+    fn new(text: &'a str, first: bool, last: bool) -> Self {
+        let mut lines: Vec<&str> = text.split('\n').map(str::trim).collect();
+        // The rest of the previous token's line.
+        let trailing = if first {
+            None
+        } else {
+            Some(lines.remove(0)).filter(|line| !line.is_empty())
+        };
+        // The start of the next token's line.
+        if !last {
+            lines.pop();
+        }
+        let mut gap = Self {
+            trailing,
+            comments: vec![],
+            blank: false,
+        };
+        for line in lines {
+            if line.is_empty() {
+                gap.blank = true;
+            } else {
+                gap.comments.push((line, gap.blank));
+                gap.blank = false;
+            }
+        }
+        gap
+    }
+}
+
+/// Lex `src`, which must be valid Polar, attaching comments to the tokens
+/// around them. Also returns the comments after the last token.
+fn tokenize(src: &str) -> (Vec<Tok<'_>>, Vec<(&str, bool)>) {
+    let mut tokens: Vec<Tok> = vec![];
+    let mut prev_end = 0;
+    for (start, token, end) in Lexer::new(src).filter_map(Result::ok) {
+        let gap = Gap::new(&src[prev_end..start], tokens.is_empty(), false);
+        if let Some(last) = tokens.last_mut() {
+            last.trailing = gap.trailing;
+        }
+        tokens.push(Tok {
+            token,
+            text: &src[start..end],
+            comments: gap.comments,
+            blank: gap.blank,
+            trailing: None,
+        });
+        prev_end = end;
+    }
+    let gap = Gap::new(&src[prev_end..], tokens.is_empty(), true);
+    if let Some(last) = tokens.last_mut() {
+        last.trailing = gap.trailing;
+    }
+
+    // Move the comment in `a # comment\n,` after the comma.
+    for i in 1..tokens.len() {
+        if matches!(tokens[i].token, Token::Comma | Token::SemiColon)
+            && tokens[i].comments.is_empty()
+            && tokens[i].trailing.is_none()
+        {
+            tokens[i].trailing = tokens[i - 1].trailing.take();
+        }
+    }
+    (tokens, gap.comments)
+}
+
+/// Whether `token` ends an operand, making a following `-` or `*` binary.
+fn ends_value(token: Option<&Token>) -> bool {
+    matches!(
+        token,
+        Some(
+            Token::Integer(_)
+                | Token::Float(_)
+                | Token::String(_)
+                | Token::Boolean(_)
+                | Token::Symbol(_)
+                | Token::RP
+                | Token::RB
+                | Token::RCB
+        )
+    )
+}
+
+/// Whether to put a space between `prev` and `next` on the same line.
+fn space_between(before: Option<&Token>, prev: &Token, next: &Token) -> bool {
+    match (prev, next) {
+        (
+            _,
+            Token::Comma | Token::SemiColon | Token::Colon | Token::Dot | Token::RP | Token::RB,
+        ) => false,
+        (_, Token::RCB) => false,
+        (Token::LP | Token::LB | Token::LCB | Token::Dot, _) => false,
+        (Token::Symbol(_) | Token::ForAll | Token::Print | Token::Debug, Token::LP) => false,
+        (Token::Symbol(_), Token::LCB) => false,
+        // Unary minus and rest variables.
+        (Token::Sub | Token::Mul, _) => ends_value(before),
+        _ => true,
+    }
+}
+
+struct Formatter<'a> {
+    tokens: &'a [Tok<'a>],
+    out: String,
+    /// The line being written and its indentation.
+    line: String,
+    line_indent: usize,
+    indent: usize,
+    /// The brackets open on the current line that aren't written one element
+    /// per line, each of which indents the lines that continue inside it.
+    open: usize,
+    /// The last two tokens written, for spacing.
+    prev: Option<&'a Token>,
+    prev2: Option<&'a Token>,
+}
+
+impl<'a> Formatter<'a> {
+    fn newline(&mut self) {
+        if !self.line.is_empty() {
+            self.out.push_str(&" ".repeat(self.line_indent));
+            self.out.push_str(self.line.trim_end());
+            self.out.push('\n');
+            self.line.clear();
+        }
+    }
+
+    /// Start a new line after a blank line, except at the start of the file
+    /// or of a block.
+    fn blank_line(&mut self) {
+        self.newline();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// The indentation of a line started now.
+    fn line_start(&self) -> usize {
+        self.indent + self.open * INDENT
+    }
+
+    fn comment(&mut self, comment: &str, blank: bool) {
+        if blank {
+            self.blank_line();
+        } else {
+            self.newline();
+        }
+        self.line_indent = self.line_start();
+        self.line.push_str(comment);
+        self.newline();
+    }
+
+    /// Write the comments before token `i`.
+    fn comments(&mut self, i: usize) {
+        let tokens = self.tokens;
+        for &(comment, blank) in &tokens[i].comments {
+            self.comment(comment, blank);
+        }
+    }
+
+    /// Write the text of token `i` and the comment after it.
+    fn text(&mut self, i: usize) {
+        let space = self
+            .prev
+            .map(|prev| space_between(self.prev2, prev, &self.tokens[i].token))
+            .unwrap_or(false);
+        self.write(i, space);
+    }
+
+    fn write(&mut self, i: usize, space: bool) {
+        let tok = &self.tokens[i];
+        if self.line.is_empty() {
+            self.line_indent = self.line_start();
+        } else if space {
+            self.line.push(' ');
+        }
+        self.line.push_str(tok.text);
+        self.prev2 = self.prev;
+        self.prev = Some(&tok.token);
+        if let Some(comment) = tok.trailing {
+            self.line.push(' ');
+            self.line.push_str(comment);
+            self.newline();
+        }
+    }
+
+    fn token(&mut self, i: usize) {
+        self.comments(i);
+        self.text(i);
+    }
+
+    /// Write the first token of a statement on a new line, keeping a blank
+    /// line before it.
+    fn first_token(&mut self, i: usize) {
+        self.comments(i);
+        if self.tokens[i].blank {
+            self.blank_line();
+        } else {
+            self.newline();
+        }
+        self.text(i);
+    }
+
+    fn policy(&mut self) {
+        let mut i = 0;
+        while i < self.tokens.len() {
+            i = if self.is_block(i) {
+                self.block(i)
+            } else {
+                self.statement(i, true)
+            };
+        }
+    }
+
+    /// Whether a resource, actor or test block starts at token `i`.
+    fn is_block(&self, i: usize) -> bool {
+        let token = |i: usize| self.tokens.get(i).map(|tok| &tok.token);
+        matches!(
+            (token(i), token(i + 1), token(i + 2)),
+            (
+                Some(Token::Symbol(_)),
+                Some(Token::Symbol(_) | Token::String(_)),
+                Some(Token::LCB)
+            )
+        )
+    }
+
+    /// Write the block starting at token `start`. Returns the index after it.
+    fn block(&mut self, start: usize) -> usize {
+        self.first_token(start);
+        self.token(start + 1);
+        self.comments(start + 2);
+        self.write(start + 2, true);
+        let mut i = start + 3;
+        let close = |tok: &Tok| matches!(tok.token, Token::RCB) && tok.comments.is_empty();
+        if close(&self.tokens[i]) {
+            self.text(i);
+            self.newline();
+            return i + 1;
+        }
+
+        self.newline();
+        self.indent += INDENT;
+        while !matches!(self.tokens[i].token, Token::RCB) {
+            i = self.statement(i, false);
+        }
+        self.newline();
+        self.comments(i);
+        self.indent -= INDENT;
+        self.text(i);
+        self.newline();
+        i + 1
+    }
+
+    /// Write the statement starting at token `start`, splitting the body if
+    /// it's a top-level rule. Returns the index after the closing `;`.
+    fn statement(&mut self, start: usize, rule: bool) -> usize {
+        let tokens = self.tokens;
+        // Whether each open bracket is written one element per line.
+        let mut stack: Vec<bool> = vec![];
+        let mut body = false;
+        let mut i = start;
+        while i < tokens.len() {
+            self.open = stack.iter().filter(|&&exploded| !exploded).count();
+            match tokens[i].token {
+                _ if i == start => self.first_token(i),
+                Token::SemiColon if stack.is_empty() => {
+                    self.token(i);
+                    self.newline();
+                    if body {
+                        self.indent -= INDENT;
+                    }
+                    return i + 1;
+                }
+                Token::If if rule && !body && stack.is_empty() => {
+                    self.token(i);
+                    self.newline();
+                    self.indent += INDENT;
+                    body = true;
+                }
+                Token::And | Token::Or if body && stack.is_empty() => {
+                    self.token(i);
+                    self.newline();
+                }
+                Token::RP | Token::RB | Token::RCB => {
+                    let exploded = stack.pop().unwrap_or(false);
+                    self.open = stack.iter().filter(|&&exploded| !exploded).count();
+                    if exploded {
+                        self.newline();
+                        self.comments(i);
+                        self.indent -= INDENT;
+                        self.text(i);
+                    } else {
+                        self.token(i);
+                    }
+                }
+                Token::Comma => match stack.last() {
+                    Some(true) => {
+                        self.token(i);
+                        self.newline();
+                    }
+                    // Drop trailing commas from lists written on one line.
+                    Some(false)
+                        if matches!(
+                            tokens.get(i + 1).map(|tok| &tok.token),
+                            Some(Token::RB | Token::RCB)
+                        ) => {}
+                    _ => self.token(i),
+                },
+                _ => self.token(i),
+            }
+            match tokens[i].token {
+                Token::LP => stack.push(false),
+                Token::LB | Token::LCB => {
+                    let exploded = self.explode(i);
+                    if exploded {
+                        self.newline();
+                        self.indent += INDENT;
+                    }
+                    stack.push(exploded);
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+        i
+    }
+
+    /// Whether to write the list or dictionary opened at token `open`, which
+    /// has just been written, one element per line.
+    fn explode(&self, open: usize) -> bool {
+        let tokens = self.tokens;
+        let mut depth = 0;
+        let mut close = open;
+        for (i, tok) in tokens.iter().enumerate().skip(open) {
+            match tok.token {
+                Token::LP | Token::LB | Token::LCB => depth += 1,
+                Token::RP | Token::RB | Token::RCB => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                close = i;
+                break;
+            }
+        }
+        if close == open + 1 {
+            return !tokens[close].comments.is_empty();
+        }
+        if tokens[open..close].iter().any(|tok| tok.trailing.is_some())
+            || tokens[open + 1..=close]
+                .iter()
+                .any(|tok| !tok.comments.is_empty())
+        {
+            return true;
+        }
+
+        let mut width = self.line_indent + self.line.chars().count();
+        let (mut prev2, mut prev) = (self.prev2, &tokens[open].token);
+        for i in open + 1..=close {
+            let tok = &tokens[i];
+            let trailing_comma = matches!(tok.token, Token::Comma)
+                && matches!(tokens[i + 1].token, Token::RB | Token::RCB);
+            if trailing_comma {
+                continue;
+            }
+            if space_between(prev2, prev, &tok.token) {
+                width += 1;
+            }
+            width += tok.text.chars().count();
+            prev2 = Some(prev);
+            prev = &tok.token;
+        }
+        width > MAX_WIDTH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(src: &str) -> String {
+        format_policy(Source::new(src)).unwrap()
+    }
+
+    /// The tokens of `src` without trailing commas, which the formatter may
+    /// add or drop.
+    fn tokens(src: &str) -> Vec<String> {
+        let tokens: Vec<_> = Lexer::new(src).map(|tok| tok.unwrap().1).collect();
+        tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, tok)| {
+                !(matches!(tok, Token::Comma)
+                    && matches!(tokens.get(i + 1), Some(Token::RB | Token::RCB)))
+            })
+            .map(|(_, tok)| format!("{:?}", tok))
+            .collect()
+    }
+
+    fn comments(src: &str) -> Vec<&str> {
+        src.lines()
+            .filter_map(|line| line.find('#').map(|i| line[i..].trim()))
+            .collect()
+    }
+
+    /// Format `src` and check that the result is stable and only moved
+    /// tokens and comments around.
+    fn check(src: &str) -> String {
+        let formatted = format(src);
+        assert_eq!(format(&formatted), formatted, "not idempotent");
+        assert_eq!(tokens(&formatted), tokens(src));
+        assert_eq!(comments(&formatted), comments(src));
+        formatted
+    }
+
+    #[test]
+    fn test_format_rules() {
+        let src = r#"
+# Allow  everything.
+
+
+allow(actor,action,resource)if has_permission(actor,action,resource) and
+ not   actor.banned  or actor matches User{ name:"root" }; # root
+
+f(x:Integer,[a,*rest],-1)if x=a-1 and x in [1,2,3,];   @table   f;
+?=f( 1 ,[1],-1 );
+"#;
+        assert_eq!(
+            check(src),
+            r#"# Allow  everything.
+
+allow(actor, action, resource) if
+  has_permission(actor, action, resource) and
+  not actor.banned or
+  actor matches User{name: "root"}; # root
+
+f(x: Integer, [a, *rest], -1) if
+  x = a - 1 and
+  x in [1, 2, 3];
+@table f;
+?= f(1, [1], -1);
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_blocks() {
+        let src = r#"actor User{}
+resource Repo {    # Repositories.
+permissions=["read","push","delete","create_issue","close_issue","reopen_issue"];
+  roles = [ "reader",
+    # Can push.
+    "writer" ];
+  relations = {parent: Org};
+
+
+  "read" if "reader";
+  # Members of the parent
+  # org can read.
+  "read" if "member" on "parent";
+}
+test "readers can read" {
+  fixture r = new Repo("r");
+  assert allow(new User("u"), "read", r);
+}
+# The end.
+"#;
+        assert_eq!(
+            check(src),
+            r#"actor User {}
+resource Repo { # Repositories.
+  permissions = [
+    "read",
+    "push",
+    "delete",
+    "create_issue",
+    "close_issue",
+    "reopen_issue"
+  ];
+  roles = [
+    "reader",
+    # Can push.
+    "writer"
+  ];
+  relations = {parent: Org};
+
+  "read" if "reader";
+  # Members of the parent
+  # org can read.
+  "read" if "member" on "parent";
+}
+test "readers can read" {
+  fixture r = new Repo("r");
+  assert allow(new User("u"), "read", r);
+}
+# The end.
+"#
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        assert_eq!(check(""), "");
+        assert_eq!(check("\n# only\n\n# comments"), "# only\n\n# comments\n");
+        assert_eq!(
+            check("f(a # first\n, b) if # body\n  a = b;"),
+            "f(a, # first\n  b) if # body\n  a = b;\n"
+        );
+        assert_eq!(
+            check("f(x) if g(x, [x, # x\n# y\nx], # z\ny);"),
+            "f(x) if\n  g(x, [\n      x, # x\n      # y\n      x\n    ], # z\n    y);\n"
+        );
+        assert_eq!(check("f(\"💯\", 💯)   if 💯;"), "f(\"💯\", 💯) if\n  💯;\n");
+    }
+
+    #[test]
+    fn test_format_policies() {
+        check(include_str!("../benches/benchmarks/roles_policy.polar"));
+        check(include_str!("../../test/test.polar"));
+        check(include_str!(
+            "../../languages/python/oso/tests/resource_blocks.polar"
+        ));
+    }
+
+    #[test]
+    fn test_format_parse_error() {
+        assert!(format_policy(Source::new("f(x) if;")).is_err());
+    }
+}
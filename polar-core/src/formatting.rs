@@ -285,6 +285,9 @@ mod display {
                 Goal::PopQuery { term } => write!(fmt, "PopQuery({})", term.to_polar()),
                 Goal::Query { term } => write!(fmt, "Query({})", term.to_polar()),
                 Goal::Run { .. } => write!(fmt, "Run(...)"),
+                Goal::TableAnswers { term, .. } => {
+                    write!(fmt, "TableAnswers({})", term.to_polar())
+                }
                 Goal::FilterRules {
                     applicable_rules,
                     unfiltered_rules,
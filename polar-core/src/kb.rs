@@ -3,14 +3,19 @@ use std::fmt::Write;
 use std::sync::Arc;
 
 pub use super::bindings::Bindings;
+use super::compiled::{CompiledPolicy, COMPILED_POLICY_VERSION};
 use super::constants::Constants;
 use super::counter::Counter;
 use super::diagnostic::Diagnostic;
-use super::error::{invalid_state, PolarError, PolarResult, RuntimeError, ValidationError};
+use super::error::{
+    invalid_state, OperationalError, PolarError, PolarResult, RuntimeError, ValidationError,
+};
+use super::policy_test::PolicyTest;
 use super::resource_block::{ResourceBlocks, ACTOR_UNION_NAME, RESOURCE_UNION_NAME};
 use super::rules::*;
 use super::terms::*;
-use super::validations::check_undefined_rule_calls;
+use super::validations::{check_rule_call_types, check_undefined_rule_calls};
+use super::visitor::{walk_rule, walk_term, Visitor};
 
 enum RuleParamMatch {
     True,
@@ -42,6 +47,10 @@ pub struct KnowledgeBase {
     /// For call IDs, instance IDs, symbols, etc.
     id_counter: Counter,
     pub inline_queries: Vec<Term>,
+    /// `test` blocks, in the order they were loaded.
+    pub policy_tests: Vec<PolicyTest>,
+    /// Rules whose answers are memoized, declared with `@table name;`.
+    tabled_rules: HashSet<Symbol>,
 
     /// Resource block bookkeeping.
     pub resource_blocks: ResourceBlocks,
@@ -92,6 +101,15 @@ impl KnowledgeBase {
         generic_rule.add_rule(Arc::new(rule));
     }
 
+    /// Memoize the answers to calls to the rules named `name`.
+    pub fn table_rule(&mut self, name: Symbol) {
+        self.tabled_rules.insert(name);
+    }
+
+    pub fn is_tabled(&self, name: &Symbol) -> bool {
+        self.tabled_rules.contains(name)
+    }
+
     pub fn validate_rules(&self) -> Vec<Diagnostic> {
         // Prior to #1310 these validations were not order dependent due to the
         // use of static default rule types.
@@ -106,6 +124,7 @@ impl KnowledgeBase {
         }
 
         diagnostics.append(&mut check_undefined_rule_calls(self));
+        diagnostics.append(&mut check_rule_call_types(self));
 
         diagnostics
     }
@@ -554,7 +573,6 @@ impl KnowledgeBase {
         &self.rules
     }
 
-    #[cfg(test)]
     pub fn get_rule_types(&self, name: &Symbol) -> Option<&Vec<Rule>> {
         self.rule_types.get(name)
     }
@@ -563,6 +581,150 @@ impl KnowledgeBase {
         self.rules.get(name)
     }
 
+    /// The loaded policy, to be loaded again with `load_compiled`.
+    pub fn compile(&self) -> CompiledPolicy {
+        struct ConstantVisitor<'kb> {
+            kb: &'kb KnowledgeBase,
+            constants: HashSet<Symbol>,
+        }
+
+        impl<'kb> Visitor for ConstantVisitor<'kb> {
+            fn visit_symbol(&mut self, s: &Symbol) {
+                if self.kb.is_constant(s) {
+                    self.constants.insert(s.clone());
+                }
+            }
+
+            fn visit_variable(&mut self, v: &Symbol) {
+                self.visit_symbol(v)
+            }
+        }
+
+        /// Finds host instances, which can't be compiled.
+        #[derive(Default)]
+        struct InstanceVisitor {
+            found: bool,
+        }
+
+        impl Visitor for InstanceVisitor {
+            fn visit_instance_id(&mut self, _: &u64) {
+                self.found = true;
+            }
+        }
+
+        let mut names = self.rules.keys().collect::<Vec<_>>();
+        names.sort();
+        let rules = names
+            .into_iter()
+            .flat_map(|name| {
+                let generic_rule = &self.rules[name];
+                let mut ids = generic_rule.rules.keys().collect::<Vec<_>>();
+                ids.sort();
+                ids.into_iter()
+                    .map(|id| generic_rule.rules[id].as_ref().clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let rule_types = self
+            .rule_types
+            .all()
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut visitor = ConstantVisitor {
+            kb: self,
+            constants: HashSet::new(),
+        };
+        for rule in rules.iter().chain(&rule_types) {
+            walk_rule(&mut visitor, rule);
+        }
+        for test in &self.policy_tests {
+            for term in test
+                .fixtures
+                .iter()
+                .chain(test.assertions.iter().map(|a| a.term()))
+            {
+                walk_term(&mut visitor, term);
+            }
+        }
+
+        let sorted = |terms: &HashSet<Term>| {
+            let mut terms = terms.iter().cloned().collect::<Vec<_>>();
+            terms.sort_by_key(|term| term.to_string());
+            terms
+        };
+        let mut tabled_rules = self.tabled_rules.iter().cloned().collect::<Vec<_>>();
+        tabled_rules.sort();
+        let mut referenced = visitor.constants.into_iter().collect::<Vec<_>>();
+        referenced.sort();
+        let (mut classes, mut constants) = (vec![], vec![]);
+        for name in referenced {
+            let value = self
+                .constants
+                .get(&name)
+                .expect("visited constants are registered");
+            let mut instances = InstanceVisitor::default();
+            walk_term(&mut instances, value);
+            if instances.found {
+                classes.push(name);
+            } else {
+                constants.push((name, value.clone()));
+            }
+        }
+
+        CompiledPolicy {
+            version: COMPILED_POLICY_VERSION,
+            polar_version: env!("CARGO_PKG_VERSION").to_owned(),
+            rules,
+            rule_types,
+            actors: sorted(&self.resource_blocks.actors),
+            resources: sorted(&self.resource_blocks.resources),
+            tabled_rules,
+            tests: self.policy_tests.clone(),
+            classes,
+            constants,
+            next_gensym: self.gensym_counter.peek(),
+        }
+    }
+
+    /// Load a policy compiled by `compile`, without validating it again.
+    pub fn load_compiled(&mut self, policy: CompiledPolicy) -> PolarResult<()> {
+        if policy.version != COMPILED_POLICY_VERSION {
+            return Err(OperationalError::Serialization {
+                msg: format!(
+                    "Compiled policy has version {}, but this version of Polar (v{}) loads version {}. Compile the policy again.",
+                    policy.version,
+                    env!("CARGO_PKG_VERSION"),
+                    COMPILED_POLICY_VERSION
+                ),
+            }
+            .into());
+        }
+        if let Some(class) = policy.classes.iter().find(|c| !self.is_constant(c)) {
+            return Err(ValidationError::UnregisteredClass {
+                term: Term::from(Value::Variable(class.clone())),
+            }
+            .into());
+        }
+
+        self.gensym_counter.fast_forward(policy.next_gensym);
+        for rule in policy.rules {
+            self.add_rule(rule);
+        }
+        self.rule_types.replace(policy.rule_types);
+        self.resource_blocks.actors = policy.actors.into_iter().collect();
+        self.resource_blocks.resources = policy.resources.into_iter().collect();
+        self.tabled_rules = policy.tabled_rules.into_iter().collect();
+        self.policy_tests = policy.tests;
+        for (name, value) in policy.constants {
+            if !self.is_constant(&name) {
+                self.constants.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_rule_type(&mut self, rule_type: Rule) {
         self.rule_types.add(rule_type);
     }
@@ -616,10 +778,49 @@ impl KnowledgeBase {
         self.constants.get_symbol_for_class_id(id)
     }
 
+    /// The names of registered classes, as opposed to other registered constants.
+    pub fn registered_class_names(&self) -> impl Iterator<Item = &Symbol> {
+        self.constants.class_symbols()
+    }
+
     pub(crate) fn get_class_id_for_symbol(&self, symbol: &Symbol) -> Option<&u64> {
         self.constants.get_class_id_for_symbol(symbol)
     }
 
+    /// The names of the classes in the MRO of `instance`'s class, and of the
+    /// `Actor` and `Resource` unions it belongs to, if it's an instance of a
+    /// registered class. Like the VM's in-core `isa` check, this trusts the
+    /// registered MRO.
+    pub fn instance_classes(&self, instance: &Term) -> Option<HashSet<Symbol>> {
+        let class_id = match instance.value() {
+            Value::ExternalInstance(ExternalInstance {
+                class_id: Some(class_id),
+                ..
+            }) => class_id,
+            _ => return None,
+        };
+        let class = self.get_symbol_for_class_id(class_id)?;
+        let mut classes: HashSet<Symbol> = self
+            .mro
+            .get(class)?
+            .iter()
+            .filter_map(|id| self.get_symbol_for_class_id(id))
+            .cloned()
+            .collect();
+        for (union, members) in [
+            (ACTOR_UNION_NAME, &self.resource_blocks.actors),
+            (RESOURCE_UNION_NAME, &self.resource_blocks.resources),
+        ] {
+            let is_member = members.iter().any(
+                |member| matches!(member.value(), Value::Variable(name) if classes.contains(name)),
+            );
+            if is_member {
+                classes.insert(Symbol::new(union));
+            }
+        }
+        Some(classes)
+    }
+
     // TODO(gj): currently no way to distinguish classes from other registered constants in the
     // core, so it's up to callers to ensure this is only called with terms we expect to be
     // registered as a _class_.
@@ -634,6 +835,7 @@ impl KnowledgeBase {
 
     /// Add the Method Resolution Order (MRO) list for a registered class.
     /// The `mro` argument is a list of the `instance_id` associated with a registered class.
+    /// It's taken to be exact; see `Polar::register_mro`.
     pub fn add_mro(&mut self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
         // Confirm name is a registered class
         if !self.is_constant(&name) {
@@ -647,10 +849,24 @@ impl KnowledgeBase {
         self.rules.clear();
         self.rule_types.reset();
         self.inline_queries.clear();
+        self.policy_tests.clear();
+        self.tabled_rules.clear();
         self.loaded_content.clear();
         self.resource_blocks.clear();
     }
 
+    /// Return a new knowledge base with the same constants and MROs but no rules.
+    /// IDs are drawn from the same counters, so they stay unique across both.
+    pub fn fork(&self) -> Self {
+        Self {
+            constants: self.constants.clone(),
+            mro: self.mro.clone(),
+            gensym_counter: self.gensym_counter.clone(),
+            id_counter: self.id_counter.clone(),
+            ..Self::default()
+        }
+    }
+
     // TODO(gj): Remove this fn & `FileLoading` error variant. These checks don't spark joy.
     pub(crate) fn add_source(&mut self, filename: &str, contents: &str) -> PolarResult<()> {
         let seen_filename = self.loaded_content.values().any(|name| name == filename);
@@ -92,6 +92,7 @@ pub enum Token {
     Not,       // not
     Matches,   // matches
     Type,      // type
+    Table,     // @table
 }
 
 impl ToString for Token {
@@ -142,6 +143,7 @@ impl ToString for Token {
             Token::Not => "not".to_owned(),         // not
             Token::Matches => "matches".to_owned(), // matches
             Token::Type => "type".to_owned(),       // type
+            Token::Table => "@table".to_owned(),    // @table
         }
     }
 }
@@ -243,7 +245,33 @@ impl<'input> Lexer<'input> {
             "rem" => Token::Rem,
             _ => Token::Symbol(Symbol::new(&self.buf)),
         };
-        Some(Ok((start, token, last + 1)))
+        // The symbol is the source text it was scanned from.
+        Some(Ok((start, token, start + self.buf.len())))
+    }
+
+    /// Scan an annotation like `@table`.
+    #[inline]
+    fn scan_annotation(&mut self, i: usize) -> Option<Spanned<Token, usize, ParseErrorKind>> {
+        self.c = self.chars.next();
+        let (end, name) = match self.c {
+            Some((j, chr))
+                if chr == '_' || (!chr.is_ascii_punctuation() && !chr.is_ascii_digit()) =>
+            {
+                match self.scan_symbol(j, chr)? {
+                    Ok((_, token, end)) => (end, token.to_string()),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            _ => (i + 1, "".to_owned()),
+        };
+        match name.as_ref() {
+            "table" => Some(Ok((i, Token::Table, end))),
+            _ => Some(Err(ParseErrorKind::InvalidTokenCharacter {
+                token: format!("@{}", name),
+                c: '@',
+                loc: i,
+            })),
+        }
     }
 
     #[inline]
@@ -488,6 +516,7 @@ impl<'input> Iterator for Lexer<'input> {
                 '*' => self.scan_1c_op(i, Token::Mul),
                 '/' => self.scan_1c_op(i, Token::Div),
                 ';' => self.scan_1c_op(i, Token::SemiColon),
+                '@' => self.scan_annotation(i),
                 _ => Some(Err(ParseErrorKind::InvalidTokenCharacter {
                     token: "".to_owned(),
                     c: char,
@@ -563,7 +592,7 @@ mod tests {
             matches!(lexer.next(), Some(Ok((13, Token::String(hunnid), 19))) if hunnid == "💯")
         );
         assert!(
-            matches!(lexer.next(), Some(Ok((20, Token::Symbol(hunnid), 21))) if hunnid == Symbol::new("💯"))
+            matches!(lexer.next(), Some(Ok((20, Token::Symbol(hunnid), 24))) if hunnid == Symbol::new("💯"))
         );
     }
 
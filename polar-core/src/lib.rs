@@ -8,6 +8,7 @@ extern crate maplit;
 pub mod macros;
 
 mod bindings;
+pub mod compiled;
 mod constants;
 mod counter;
 pub mod data_filtering;
@@ -15,28 +16,32 @@ mod debugger;
 pub mod diagnostic;
 pub mod error;
 pub mod events;
+pub mod explain;
 pub mod filter;
 mod folder;
+pub mod formatter;
 mod formatting;
 mod inverter;
 pub mod kb;
-mod lexer;
+pub mod lexer;
 pub mod messages;
 pub mod normalize;
 mod numerics;
 pub mod parser;
 mod partial;
 pub mod polar;
+pub mod policy_test;
 pub mod query;
 pub mod resource_block;
 mod rewrites;
 pub mod rules;
 mod runnable;
 pub mod sources;
+mod tabling;
 pub mod terms;
 pub mod traces;
 mod validations;
-mod visitor;
+pub mod visitor;
 mod vm;
 pub mod warning;
 
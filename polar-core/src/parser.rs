@@ -28,11 +28,19 @@ pub enum Line {
     Rule(Rule),
     RuleType(Rule),
     Query(Term),
+    /// `@table name;` memoizes calls to the rules named `name`.
+    Table(Term),
     ResourceBlock {
         keyword: Option<Term>,
         resource: Term,
         productions: Vec<Production>,
     },
+    /// `test "name" { ... }`, with the keyword and term of each statement.
+    Test {
+        keyword: Term,
+        name: Term,
+        statements: Vec<(Term, Term)>,
+    },
 }
 
 fn lalrpop_error_to_polar_error(
@@ -1,5 +1,6 @@
 use std::sync::{Arc, RwLock};
 
+use super::compiled::CompiledPolicy;
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
 use super::diagnostic::Diagnostic;
 use super::error::{PolarResult, RuntimeError, ValidationError};
@@ -7,6 +8,7 @@ use super::filter::Filter;
 use super::kb::*;
 use super::messages::*;
 use super::parser;
+use super::policy_test::{policy_test_from_statements, PolicyTest};
 use super::query::Query;
 use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
@@ -66,6 +68,9 @@ impl Polar {
                     parser::Line::Query(term) => {
                         kb.inline_queries.push(term);
                     }
+                    parser::Line::Table(name) => {
+                        kb.table_rule(name.as_symbol()?.clone());
+                    }
                     parser::Line::RuleType(rule_type) => {
                         // make sure rule_type doesn't have anything that needs to be rewritten in the head
                         let rule_type = rewrite_rule(rule_type, kb);
@@ -99,6 +104,21 @@ impl Polar {
                         errors.append(&mut block.add_to_kb(kb));
                         diagnostics.extend(errors.into_iter().map(Into::into));
                     }
+                    parser::Line::Test {
+                        keyword,
+                        name,
+                        statements,
+                    } => match policy_test_from_statements(keyword, name, statements) {
+                        Ok(test) if kb.policy_tests.iter().any(|t| t.name() == test.name()) => {
+                            let error = ValidationError::PolicyTest {
+                                msg: format!("Duplicate test '{}'.", test.name()),
+                                term: test.name,
+                            };
+                            diagnostics.push(Diagnostic::Error(error.into()));
+                        }
+                        Ok(test) => kb.policy_tests.push(test),
+                        Err(e) => diagnostics.push(Diagnostic::Error(e)),
+                    },
                 }
             }
             Ok(diagnostics)
@@ -186,6 +206,23 @@ impl Polar {
         Ok(())
     }
 
+    /// The loaded policy, serializable so that it can be loaded again with
+    /// `load_compiled` without parsing it.
+    pub fn compile(&self) -> CompiledPolicy {
+        self.kb.read().unwrap().compile()
+    }
+
+    /// Load a policy compiled by `compile`, skipping parsing, rewriting and
+    /// validation. The classes and instances the policy refers to must be
+    /// registered first.
+    pub fn load_compiled(&self, policy: CompiledPolicy) -> PolarResult<()> {
+        let mut kb = self.kb.write().unwrap();
+        if kb.has_rules() {
+            return Err(RuntimeError::MultipleLoadError.into());
+        }
+        kb.load_compiled(policy)
+    }
+
     // Used in integration tests
     pub fn load_str(&self, src: &str) -> PolarResult<()> {
         self.load(vec![Source::new(src)])
@@ -197,11 +234,26 @@ impl Polar {
         kb.clear_rules();
     }
 
+    /// Create a `Polar` with the same constants and classes as this one but no
+    /// rules, so a new policy can be loaded without affecting running queries.
+    pub fn fork(&self) -> Self {
+        Self {
+            kb: Arc::new(RwLock::new(self.kb.read().unwrap().fork())),
+            messages: MessageQueue::new(),
+            ignore_no_allow_warning: self.ignore_no_allow_warning,
+        }
+    }
+
     pub fn next_inline_query(&self, trace: bool) -> Option<Query> {
         let term = { self.kb.write().unwrap().inline_queries.pop() };
         term.map(|t| self.new_query_from_term(t, trace))
     }
 
+    /// The `test` blocks in the loaded policy.
+    pub fn policy_tests(&self) -> Vec<PolicyTest> {
+        self.kb.read().unwrap().policy_tests.clone()
+    }
+
     pub fn new_query(&self, src: &str, trace: bool) -> PolarResult<Query> {
         parser::parse_query(src).map(|term| self.new_query_from_term(term, trace))
     }
@@ -234,6 +286,12 @@ impl Polar {
     ///
     /// - `mro`: Should go from `name`, `name`'s next superclass, `name's furthest away superclass.
     ///          `mro` is a list of class ids.
+    ///
+    /// Instances of registered classes are assumed to be instances of exactly
+    /// the classes in their MRO: the VM answers `isa` checks against them
+    /// without asking the host, and skips rules specialized on other classes.
+    /// Hosts whose `isa` is broader than the MRO, like Python's virtual
+    /// subclasses of abstract base classes, get the narrower MRO answer.
     pub fn register_mro(&self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
         self.kb.write().unwrap().add_mro(name, mro)
     }
@@ -321,6 +379,23 @@ mod tests {
         assert!(!polar.kb.read().unwrap().has_rules());
     }
 
+    #[test]
+    fn fork_keeps_constants_but_not_rules() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("x"), term!(1)).unwrap();
+        polar.load_str("f(1);").unwrap();
+
+        let fork = polar.fork();
+        let kb = fork.kb.read().unwrap();
+        assert!(kb.is_constant(&sym!("x")));
+        assert!(!kb.has_rules());
+        drop(kb);
+
+        // The fork can load the same source again, and the original is untouched.
+        fork.load_str("f(2);").unwrap();
+        assert!(polar.kb.read().unwrap().has_rules());
+    }
+
     #[test]
     fn diagnostic_load_returns_multiple_diagnostics() {
         let polar = Polar::new();
@@ -346,6 +421,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn invalid_test_blocks_dont_stop_loading() {
+        let polar = Polar::new();
+        let src = r#"
+            test "one" { assert f(); }
+            test "two" { check f(); }
+            test "one" { assert_not f(); }
+            f();
+            allow(_, _, _);
+        "#;
+
+        let diagnostics = polar.diagnostic_load(vec![Source::new(src)]);
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+        assert!(diagnostics
+            .iter()
+            .all(|d| matches!(d, Diagnostic::Error(_))));
+        assert!(
+            diagnostics[0]
+                .to_string()
+                .starts_with("Expected 'fixture', 'assert' or 'assert_not'"),
+            "{}",
+            diagnostics[0]
+        );
+        assert!(
+            diagnostics[1]
+                .to_string()
+                .starts_with("Duplicate test 'one'."),
+            "{}",
+            diagnostics[1]
+        );
+
+        // The rest of the source is still loaded.
+        let kb = polar.kb.read().unwrap();
+        assert_eq!(kb.policy_tests.len(), 1);
+        assert!(kb.get_rules().contains_key(&sym!("f")));
+    }
+
     #[test]
     fn test_valid_shorthand_rules_still_rewritten_in_presence_of_invalid_shorthand_rules() {
         let polar = Polar::new();
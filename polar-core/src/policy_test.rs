@@ -0,0 +1,212 @@
+//! `test` blocks, which check that queries against a policy succeed or fail:
+//!
+//! ```polar
+//! test "owners can delete" {
+//!   fixture alice = new User("alice");
+//!   fixture repo = {owner: alice};
+//!   assert allow(alice, "delete", repo);
+//!   assert_not allow(new User("bob"), "delete", repo);
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{PolarResult, ValidationError};
+use super::terms::*;
+
+/// A statement in a `test` block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Assertion {
+    /// `assert <query>;` passes if the query has a result.
+    Assert(Term),
+    /// `assert_not <query>;` passes if the query has no results.
+    AssertNot(Term),
+}
+
+impl Assertion {
+    /// The asserted query, tracked for lexical context.
+    pub fn term(&self) -> &Term {
+        match self {
+            Self::Assert(term) | Self::AssertNot(term) => term,
+        }
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Self::Assert(_) => "assert",
+            Self::AssertNot(_) => "assert_not",
+        }
+    }
+}
+
+/// A `test "name" { ... }` block.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyTest {
+    /// Term<String> naming the test, tracked for lexical context.
+    pub name: Term,
+    /// `fixture <variable> = <term>;` statements, unified before each assertion.
+    pub fixtures: Vec<Term>,
+    pub assertions: Vec<Assertion>,
+}
+
+impl PolicyTest {
+    pub fn name(&self) -> &str {
+        self.name.as_string().unwrap_or_default()
+    }
+
+    /// A query that has a result iff `assertion` passes: the fixtures and then
+    /// the asserted query, negated for `assert_not`.
+    pub fn query(&self, assertion: &Assertion) -> Term {
+        let term = assertion.term();
+        let check = match assertion {
+            Assertion::Assert(_) => term.clone(),
+            Assertion::AssertNot(_) => {
+                term.clone_with_value(Value::Expression(op!(Not, term.clone())))
+            }
+        };
+        let mut args = self.fixtures.clone();
+        args.push(check);
+        term.clone_with_value(Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }))
+    }
+}
+
+fn validate_fixture(keyword: &Term, term: Term) -> PolarResult<Term> {
+    match term.value() {
+        Value::Expression(Operation {
+            operator: Operator::Unify,
+            args,
+        }) if matches!(args[0].value(), Value::Variable(_)) => Ok(term),
+        _ => Err(ValidationError::PolicyTest {
+            msg: format!(
+                "Expected a fixture of the form 'fixture <name> = <value>;' but found '{} {}'.",
+                keyword, term
+            ),
+            term,
+        }
+        .into()),
+    }
+}
+
+pub fn policy_test_from_statements(
+    keyword: Term,
+    name: Term,
+    statements: Vec<(Term, Term)>,
+) -> PolarResult<PolicyTest> {
+    if keyword.as_symbol()?.0 != "test" {
+        return Err(ValidationError::PolicyTest {
+            msg: format!("Expected 'test' but found '{}'.", keyword),
+            term: keyword,
+        }
+        .into());
+    }
+    if name.as_string()?.is_empty() {
+        return Err(ValidationError::PolicyTest {
+            msg: "Tests must have a name.".to_owned(),
+            term: name,
+        }
+        .into());
+    }
+
+    let mut test = PolicyTest {
+        name,
+        fixtures: vec![],
+        assertions: vec![],
+    };
+    for (keyword, term) in statements {
+        match keyword.as_symbol()?.0.as_ref() {
+            "fixture" => test.fixtures.push(validate_fixture(&keyword, term)?),
+            "assert" => test.assertions.push(Assertion::Assert(term)),
+            "assert_not" => test.assertions.push(Assertion::AssertNot(term)),
+            other => {
+                return Err(ValidationError::PolicyTest {
+                    msg: format!(
+                        "Expected 'fixture', 'assert' or 'assert_not' but found '{}'.",
+                        other
+                    ),
+                    term: keyword,
+                }
+                .into())
+            }
+        }
+    }
+    if test.assertions.is_empty() {
+        return Err(ValidationError::PolicyTest {
+            msg: format!("Test '{}' has no assertions.", test.name()),
+            term: test.name,
+        }
+        .into());
+    }
+    Ok(test)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::parser::{parse_lines, Line};
+    use crate::sources::Source;
+
+    fn parse_test(src: &str) -> PolarResult<PolicyTest> {
+        match parse_lines(Source::new(src))?.pop() {
+            Some(Line::Test {
+                keyword,
+                name,
+                statements,
+            }) => policy_test_from_statements(keyword, name, statements),
+            line => panic!("expected a test, found {:?}", line),
+        }
+    }
+
+    fn expect_error(src: &str, expected: &str) {
+        let e = parse_test(src).unwrap_err();
+        assert!(
+            matches!(&e.0, ErrorKind::Validation(ValidationError::PolicyTest { msg, .. }) if msg == expected),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn test_policy_test_statements() {
+        let test = parse_test(
+            r#"test "deny" {
+                 fixture u = {name: "u"};
+                 assert_not allow(u, "read", _);
+                 assert u.name = "u";
+               }"#,
+        )
+        .unwrap();
+        assert_eq!(test.name(), "deny");
+        assert_eq!(test.fixtures.len(), 1);
+        assert_eq!(test.assertions[0].keyword(), "assert_not");
+        assert_eq!(
+            test.query(&test.assertions[0]).to_string(),
+            r#"u = {name: "u"} and not allow(u, "read", _)"#
+        );
+        assert_eq!(
+            test.query(&test.assertions[1]).to_string(),
+            r#"u = {name: "u"} and u.name = "u""#
+        );
+    }
+
+    #[test]
+    fn test_policy_test_errors() {
+        expect_error(
+            r#"tset "x" { assert true; }"#,
+            "Expected 'test' but found 'tset'.",
+        );
+        expect_error(
+            r#"test "x" { asert true; }"#,
+            "Expected 'fixture', 'assert' or 'assert_not' but found 'asert'.",
+        );
+        expect_error(
+            r#"test "x" { fixture f(1); assert true; }"#,
+            "Expected a fixture of the form 'fixture <name> = <value>;' but found 'fixture f(1)'.",
+        );
+        expect_error(r#"test "x" { }"#, "Test 'x' has no assertions.");
+        expect_error(r#"test "" { assert true; }"#, "Tests must have a name.");
+    }
+}
@@ -1,10 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use super::error::PolarResult;
 use super::events::*;
+use super::explain::ExplainedRule;
 use super::messages::*;
+use super::rules::Rule;
 use super::runnable::Runnable;
 use super::terms::*;
 use super::vm::*;
 
+/// Limits on the work a query may do before it fails with a `RuntimeError`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryLimits {
+    /// Wall-clock timeout in milliseconds, or 0 for no timeout. Exceeding it
+    /// fails with `RuntimeError::QueryTimeout`.
+    pub timeout_ms: u64,
+    /// The most goals the VM may run, or `None` for no limit. Exceeding it
+    /// fails with `RuntimeError::GoalLimitExceeded`.
+    pub max_goals: Option<u64>,
+    /// The deepest the goal and choice stacks may grow. Exceeding it fails
+    /// with `RuntimeError::StackOverflow`.
+    pub max_stack_depth: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            timeout_ms: DEFAULT_TIMEOUT_MS,
+            max_goals: None,
+            max_stack_depth: MAX_STACK_SIZE,
+        }
+    }
+}
+
+impl QueryLimits {
+    /// The default limits, with the timeout read from the `POLAR_TIMEOUT_MS`
+    /// environment variable if it is set.
+    pub fn from_env() -> Self {
+        let timeout_ms = std::env::var("POLAR_TIMEOUT_MS")
+            .ok()
+            .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TIMEOUT_MS);
+        Self {
+            timeout_ms,
+            ..Self::default()
+        }
+    }
+}
+
+/// Cancels a running query from another thread. The query fails with
+/// `RuntimeError::QueryCancelled` before it runs its next goal.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 pub struct Query {
     runnable_stack: Vec<(Box<dyn Runnable>, u64)>, // Tuple of Runnable + call_id.
     vm: PolarVirtualMachine,
@@ -108,6 +167,35 @@ impl Query {
     pub fn bind(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
         self.vm.bind(&name, value)
     }
+
+    /// Replace the limits on the query, which default to `QueryLimits::from_env`.
+    pub fn set_limits(&mut self, limits: QueryLimits) {
+        self.vm.set_limits(limits)
+    }
+
+    pub fn limits(&self) -> QueryLimits {
+        self.vm.limits()
+    }
+
+    /// A token that cancels this query.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.vm.cancellation_token()
+    }
+
+    /// Record the rules the query tries, so they can be explained afterwards.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.vm.set_explain(explain)
+    }
+
+    /// The rules tried so far, if `set_explain` was called.
+    pub fn explanation(&self) -> Option<Vec<ExplainedRule>> {
+        self.vm.explanation()
+    }
+
+    /// The outermost rule that matched for the most recent result.
+    pub fn matched_rule(&self) -> Option<Arc<Rule>> {
+        self.vm.matched_rule()
+    }
 }
 
 // Query as an iterator returns `None` after the first time `Done` is seen
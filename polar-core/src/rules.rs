@@ -1,4 +1,4 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
@@ -16,6 +16,14 @@ impl Parameter {
     pub fn is_ground(&self) -> bool {
         self.specializer.is_none() && self.parameter.value().is_ground()
     }
+
+    /// The class the parameter is specialized on, if any.
+    pub fn specializer_class(&self) -> Option<&Symbol> {
+        match self.specializer.as_ref()?.value() {
+            Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) => Some(tag),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -44,7 +52,8 @@ impl Rule {
         self.params.iter().all(|p| p.is_ground())
     }
 
-    pub(crate) fn parsed_context(&self) -> Option<&Context> {
+    /// Where the rule was defined, if it was parsed from a policy.
+    pub fn parsed_context(&self) -> Option<&Context> {
         if let SourceInfo::Parser(context) = &self.source_info {
             Some(context)
         } else {
@@ -126,6 +135,21 @@ impl RuleTypes {
         rule_types.push(rule_type);
     }
 
+    /// Every rule type, ordered by name.
+    pub fn all(&self) -> Vec<&Rule> {
+        let mut names = self.0.keys().collect::<Vec<_>>();
+        names.sort();
+        names.into_iter().flat_map(|name| &self.0[name]).collect()
+    }
+
+    /// Replace every rule type, including the defaults, with `rule_types`.
+    pub fn replace(&mut self, rule_types: Vec<Rule>) {
+        self.0.clear();
+        for rule_type in rule_types {
+            self.add(rule_type);
+        }
+    }
+
     pub fn reset(&mut self) {
         self.0.clear();
         self.add_default_rule_types()
@@ -144,44 +168,65 @@ pub type Rules = Vec<Arc<Rule>>;
 
 type RuleSet = BTreeSet<u64>;
 
+/// The names of the classes each argument of a call is an instance of,
+/// where known. See `KnowledgeBase::instance_classes`.
+pub type ArgClasses = [Option<HashSet<Symbol>>];
+
 #[derive(Clone, Default, Debug)]
 struct RuleIndex {
     rules: RuleSet,
     index: HashMap<Option<Value>, RuleIndex>,
+    /// Rules whose parameter is specialized on a class, by class name.
+    classes: HashMap<Symbol, RuleIndex>,
 }
 
 impl RuleIndex {
     pub fn index_rule(&mut self, rule_id: u64, params: &[Parameter], i: usize) {
         if i < params.len() {
-            self.index
-                .entry({
-                    if params[i].is_ground() {
-                        Some(params[i].parameter.value().clone())
-                    } else {
-                        None
-                    }
-                })
-                .or_insert_with(RuleIndex::default)
-                .index_rule(rule_id, params, i + 1);
+            let index = if let Some(class) = params[i].specializer_class() {
+                self.classes.entry(class.clone()).or_default()
+            } else {
+                self.index
+                    .entry({
+                        if params[i].is_ground() {
+                            Some(params[i].parameter.value().clone())
+                        } else {
+                            None
+                        }
+                    })
+                    .or_default()
+            };
+            index.index_rule(rule_id, params, i + 1);
         } else {
             self.rules.insert(rule_id);
         }
     }
 
     #[allow(clippy::comparison_chain)]
-    pub fn get_applicable_rules(&self, args: &[Term], i: usize) -> RuleSet {
+    pub fn get_applicable_rules(&self, args: &[Term], classes: &ArgClasses, i: usize) -> RuleSet {
         if i < args.len() {
             // Check this argument and recurse on the rest.
             let filter_next_args =
-                |index: &RuleIndex| -> RuleSet { index.get_applicable_rules(args, i + 1) };
+                |index: &RuleIndex| -> RuleSet { index.get_applicable_rules(args, classes, i + 1) };
+
+            // Skip rules specialized on classes the argument isn't an instance of.
+            let mut ruleset = RuleSet::default();
+            let arg_classes = classes.get(i).and_then(Option::as_ref);
+            for (class, index) in &self.classes {
+                if arg_classes
+                    .map(|arg_classes| arg_classes.contains(class))
+                    .unwrap_or(true)
+                {
+                    ruleset.extend(filter_next_args(index));
+                }
+            }
+
             let arg = args[i].value();
             if arg.is_ground() {
                 // Check the index for a ground argument.
-                let mut ruleset = self
-                    .index
-                    .get(&Some(arg.clone()))
-                    .map(|index| filter_next_args(index))
-                    .unwrap_or_else(RuleSet::default);
+                if let Some(index) = self.index.get(&Some(arg.clone())) {
+                    ruleset.extend(filter_next_args(index));
+                }
 
                 // Extend for a variable parameter.
                 if let Some(index) = self.index.get(&None) {
@@ -190,13 +235,12 @@ impl RuleIndex {
                 ruleset
             } else {
                 // Accumulate all indexed arguments.
-                self.index.values().fold(
-                    RuleSet::default(),
-                    |mut result: RuleSet, index: &RuleIndex| {
+                self.index
+                    .values()
+                    .fold(ruleset, |mut result: RuleSet, index: &RuleIndex| {
                         result.extend(filter_next_args(index).into_iter());
                         result
-                    },
-                )
+                    })
             }
         } else {
             // No more arguments.
@@ -239,10 +283,12 @@ impl GenericRule {
         self.index.index_rule(rule_id, &rule.params[..], 0);
     }
 
+    /// The rules that may apply to `args`. Rules specialized on a class that
+    /// an argument is known not to be an instance of are left out.
     #[allow(clippy::ptr_arg)]
-    pub fn get_applicable_rules(&self, args: &TermList) -> Rules {
+    pub fn get_applicable_rules(&self, args: &TermList, classes: &ArgClasses) -> Rules {
         self.index
-            .get_applicable_rules(args, 0)
+            .get_applicable_rules(args, classes, 0)
             .iter()
             .map(|id| self.rules.get(id).expect("Rule missing"))
             .cloned()
@@ -321,4 +367,64 @@ mod tests {
         let index13 = index1.index.get(&Some(value!(3))).unwrap();
         assert_eq!(args, keys(index13));
     }
+
+    #[test]
+    fn test_rule_index_classes() {
+        let polar = Polar::new();
+        let instance = |instance_id, class_id| {
+            term!(Value::ExternalInstance(ExternalInstance {
+                instance_id,
+                constructor: None,
+                repr: None,
+                class_repr: None,
+                class_id: Some(class_id),
+            }))
+        };
+        for (id, name) in [(1, "Repo"), (2, "Issue")] {
+            polar
+                .register_constant(sym!(name), instance(id, id))
+                .unwrap();
+            polar.register_mro(sym!(name), vec![id]).unwrap();
+        }
+        polar
+            .load_str(
+                r#"
+            f(_: Repo, 1);
+            f(_: Repo{public: true}, 2);
+            f(_: Issue, 1);
+            f(_, 1);
+        "#,
+            )
+            .unwrap();
+
+        let kb = polar.kb.read().unwrap();
+        let generic_rule = kb.get_generic_rule(&sym!("f")).unwrap();
+        let classes =
+            |index: &RuleIndex| -> HashSet<Symbol> { index.classes.keys().cloned().collect() };
+        assert_eq!(
+            classes(&generic_rule.index),
+            HashSet::from([sym!("Repo"), sym!("Issue")])
+        );
+
+        let repo = instance(10, 1);
+        let args = vec![repo.clone(), term!(sym!("x"))];
+        let arg_classes = args
+            .iter()
+            .map(|arg| kb.instance_classes(arg))
+            .collect::<Vec<_>>();
+        assert_eq!(arg_classes[0], Some(HashSet::from([sym!("Repo")])));
+        assert_eq!(
+            generic_rule.get_applicable_rules(&args, &arg_classes).len(),
+            3
+        );
+        let args = vec![repo, term!(1)];
+        assert_eq!(
+            generic_rule.get_applicable_rules(&args, &arg_classes).len(),
+            2
+        );
+
+        // Without class information, every specialized rule may apply.
+        let args = vec![term!(sym!("x")), term!(sym!("y"))];
+        assert_eq!(generic_rule.get_applicable_rules(&args, &[]).len(), 4);
+    }
 }
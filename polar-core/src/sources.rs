@@ -33,9 +33,14 @@ impl Context {
         }
     }
 
+    /// The zero-based line and column where the context starts.
+    pub fn position(&self) -> (usize, usize) {
+        loc_to_pos(&self.source.src, self.left)
+    }
+
     pub(crate) fn source_position(&self) -> String {
         let mut f = String::new();
-        let (row, column) = loc_to_pos(&self.source.src, self.left);
+        let (row, column) = self.position();
         write!(f, " at line {}, column {}", row + 1, column + 1).unwrap();
         if let Some(ref filename) = self.source.filename {
             write!(f, " of file {}", filename).unwrap();
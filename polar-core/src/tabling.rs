@@ -0,0 +1,253 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::{PolarError, PolarResult};
+use crate::events::QueryEvent;
+use crate::folder::{fold_term, Folder};
+use crate::runnable::Runnable;
+use crate::terms::{Symbol, Term};
+use crate::vm::PolarVirtualMachine;
+
+/// Rename the variables in `term` in order of appearance, so that calls and
+/// answers that differ only in the names of their variables are equal.
+pub fn canonicalize(term: &Term) -> Term {
+    struct Canonicalizer(HashMap<Symbol, Symbol>);
+
+    impl Folder for Canonicalizer {
+        fn fold_variable(&mut self, v: Symbol) -> Symbol {
+            let next = self.0.len();
+            self.0
+                .entry(v)
+                .or_insert_with(|| Symbol(format!("_{}", next)))
+                .clone()
+        }
+
+        fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
+            self.fold_variable(v)
+        }
+    }
+
+    fold_term(term.clone(), &mut Canonicalizer(HashMap::new()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableState {
+    /// The call is being evaluated and more answers may appear.
+    InProgress,
+    /// The call was evaluated using the partial answers of another call that
+    /// was in progress, so it must be evaluated again.
+    Incomplete,
+    /// Every answer to the call is in the table.
+    Complete,
+}
+
+struct Table {
+    answers: Vec<Term>,
+    seen: HashSet<Term>,
+    state: TableState,
+    /// Whether the answers were read while in progress.
+    reread: bool,
+    /// Whether the evaluation read the answers of another call in progress.
+    dependent: bool,
+}
+
+/// The answers to calls to tabled rules, keyed by the canonical call.
+#[derive(Default)]
+pub struct Tables {
+    tables: HashMap<Term, Table>,
+    /// Calls being evaluated, innermost last.
+    active: Vec<Term>,
+}
+
+impl Tables {
+    /// The answers to `key` so far, or `None` if the call must be evaluated.
+    pub fn answers(&mut self, key: &Term) -> Option<Vec<Term>> {
+        let table = self.tables.get_mut(key)?;
+        let answers = table.answers.clone();
+        match table.state {
+            TableState::Complete => Some(answers),
+            TableState::Incomplete => None,
+            TableState::InProgress => {
+                // A recursive call: answer with what we have so far, and
+                // evaluate again once they're all in.
+                table.reread = true;
+                if let Some(i) = self.active.iter().rposition(|k| k == key) {
+                    for k in &self.active[i + 1..] {
+                        if let Some(table) = self.tables.get_mut(k) {
+                            table.dependent = true;
+                        }
+                    }
+                }
+                Some(answers)
+            }
+        }
+    }
+
+    /// Every answer to `key` so far.
+    pub fn all_answers(&self, key: &Term) -> Vec<Term> {
+        self.tables
+            .get(key)
+            .map(|table| table.answers.clone())
+            .unwrap_or_default()
+    }
+
+    fn count(&self, key: &Term) -> usize {
+        self.tables.get(key).map_or(0, |table| table.answers.len())
+    }
+
+    /// Start evaluating `key`, returning false if it's already complete.
+    fn begin(&mut self, key: &Term) -> bool {
+        let table = self.tables.entry(key.clone()).or_insert_with(|| Table {
+            answers: vec![],
+            seen: HashSet::new(),
+            state: TableState::InProgress,
+            reread: false,
+            dependent: false,
+        });
+        if table.state == TableState::Complete {
+            return false;
+        }
+        table.state = TableState::InProgress;
+        table.reread = false;
+        table.dependent = false;
+        self.active.push(key.clone());
+        true
+    }
+
+    fn add_answer(&mut self, key: &Term, answer: Term) {
+        if let Some(table) = self.tables.get_mut(key) {
+            if table.seen.insert(answer.clone()) {
+                table.answers.push(answer);
+            }
+        }
+    }
+
+    /// Whether the answers were read while in progress since the last call.
+    fn take_reread(&mut self, key: &Term) -> bool {
+        self.tables
+            .get_mut(key)
+            .map(|table| std::mem::take(&mut table.reread))
+            .unwrap_or(false)
+    }
+
+    fn finish(&mut self, key: &Term) {
+        if let Some(i) = self.active.iter().rposition(|k| k == key) {
+            self.active.remove(i);
+        }
+        if let Some(table) = self.tables.get_mut(key) {
+            table.state = if table.dependent {
+                TableState::Incomplete
+            } else {
+                TableState::Complete
+            };
+        }
+    }
+}
+
+/// A `Runnable` that fills the table for a call to a tabled rule.
+///
+/// The rules are queried in `vm` until there are no more answers. If the
+/// call was made again while it was being evaluated, the recursive call only
+/// saw some of the answers, so we query again until no new answers appear.
+#[derive(Clone)]
+pub struct TableRunnable {
+    /// Queries the rules for the call, cloned for each iteration.
+    template: PolarVirtualMachine,
+    vm: Option<PolarVirtualMachine>,
+    call: Term,
+    key: Term,
+    tables: Rc<RefCell<Tables>>,
+    started: bool,
+    /// The number of answers at the start of the current iteration.
+    answers_before: usize,
+}
+
+impl TableRunnable {
+    pub fn new(
+        template: PolarVirtualMachine,
+        call: Term,
+        key: Term,
+        tables: Rc<RefCell<Tables>>,
+    ) -> Self {
+        Self {
+            template,
+            vm: None,
+            call,
+            key,
+            tables,
+            started: false,
+            answers_before: 0,
+        }
+    }
+
+    fn current_vm(&mut self) -> &mut PolarVirtualMachine {
+        let template = &self.template;
+        self.vm.get_or_insert_with(|| template.clone())
+    }
+
+    fn done(&self) -> QueryEvent {
+        QueryEvent::Done {
+            result: self.tables.borrow().count(&self.key) > 0,
+        }
+    }
+}
+
+impl Runnable for TableRunnable {
+    fn run(&mut self, _: Option<&mut Counter>) -> PolarResult<QueryEvent> {
+        if !self.started {
+            self.started = true;
+            if !self.tables.borrow_mut().begin(&self.key) {
+                return Ok(self.done());
+            }
+        }
+
+        loop {
+            if self.vm.is_none() {
+                self.answers_before = self.tables.borrow().count(&self.key);
+                self.vm = Some(self.template.clone());
+            }
+            let vm = self.vm.as_mut().unwrap();
+
+            match vm.run(None)? {
+                QueryEvent::Done { .. } => {
+                    self.vm = None;
+                    let mut tables = self.tables.borrow_mut();
+                    let changed = tables.count(&self.key) > self.answers_before;
+                    if tables.take_reread(&self.key) && changed {
+                        continue;
+                    }
+                    tables.finish(&self.key);
+                    drop(tables);
+                    return Ok(self.done());
+                }
+                QueryEvent::Result { .. } => {
+                    let answer = vm.table_answer(&self.call)?;
+                    self.tables.borrow_mut().add_answer(&self.key, answer);
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> PolarResult<()> {
+        self.current_vm().external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> PolarResult<()> {
+        self.current_vm().external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        self.current_vm().debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: PolarError) -> PolarResult<QueryEvent> {
+        self.current_vm().handle_error(error)
+    }
+}
@@ -322,7 +322,8 @@ impl Term {
 
     // TODO(gj): Parsed<T> type (or something) so we can remove this meaningless distinction
     // between terms & rules.
-    pub(crate) fn parsed_context(&self) -> Option<&Context> {
+    /// Where the term was parsed, if it was parsed from a policy.
+    pub fn parsed_context(&self) -> Option<&Context> {
         if let SourceInfo::Parser(context) = self.source_info() {
             Some(context)
         } else {
@@ -1,4 +1,5 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
 
 use super::diagnostic::Diagnostic;
 use super::error::{PolarError, ValidationError};
@@ -224,6 +225,218 @@ pub fn check_undefined_rule_calls(kb: &KnowledgeBase) -> Vec<Diagnostic> {
     visitor.errors()
 }
 
+/// Check the calls in rule bodies against the rule types of the rules they call, using what's
+/// known about the classes of their arguments.
+struct RuleCallTypeVisitor<'kb> {
+    kb: &'kb KnowledgeBase,
+    /// The classes of the variables in the rule being checked, from its parameter specializers
+    /// and the `matches` conditions its body always checks.
+    classes: HashMap<Symbol, Symbol>,
+    /// Calls that can't match any rule type, and why.
+    invalid_calls: Vec<(Term, String)>,
+}
+
+impl<'kb> RuleCallTypeVisitor<'kb> {
+    fn new(kb: &'kb KnowledgeBase) -> Self {
+        Self {
+            kb,
+            classes: HashMap::new(),
+            invalid_calls: Vec::new(),
+        }
+    }
+
+    fn warnings(self) -> Vec<Diagnostic> {
+        let mut invalid_calls = self.invalid_calls;
+        invalid_calls.sort_by_key(|(term, _)| {
+            term.parsed_context()
+                .map(|context| (context.source.filename.clone(), context.left))
+        });
+        invalid_calls
+            .into_iter()
+            .map(|(term, msg)| {
+                Diagnostic::Warning(ValidationWarning::InvalidRuleCall { term, msg }.into())
+            })
+            .collect()
+    }
+
+    fn check_rule(&mut self, rule: &Rule) {
+        self.classes.clear();
+        for param in &rule.params {
+            if let (Value::Variable(name), Some(Value::Pattern(Pattern::Instance(instance)))) = (
+                param.parameter.value(),
+                param.specializer.as_ref().map(Term::value),
+            ) {
+                self.classes.insert(name.clone(), instance.tag.clone());
+            }
+        }
+        self.infer(&rule.body);
+        walk_rule(self, rule);
+    }
+
+    /// Record the classes of variables matched against instance patterns in the conjunction
+    /// `term`, unless more is already known about them.
+    fn infer(&mut self, term: &Term) {
+        if let Value::Expression(Operation { operator, args }) = term.value() {
+            match (operator, &args[..]) {
+                (Operator::And, _) => args.iter().for_each(|arg| self.infer(arg)),
+                (Operator::Isa, [left, right]) => {
+                    if let (Value::Variable(name), Value::Pattern(Pattern::Instance(instance))) =
+                        (left.value(), right.value())
+                    {
+                        let narrower = match self.classes.get(name) {
+                            Some(class) => self.kb.is_union(&term!(class.clone())),
+                            None => true,
+                        };
+                        if narrower {
+                            self.classes.insert(name.clone(), instance.tag.clone());
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The class of `arg`, if it's known.
+    fn class_of(&self, arg: &Term) -> Option<Symbol> {
+        let class = match arg.value() {
+            Value::Variable(name) => return self.classes.get(name).cloned(),
+            Value::String(_) => "String",
+            Value::Number(Numeric::Integer(_)) => "Integer",
+            Value::Number(Numeric::Float(_)) => "Float",
+            Value::Boolean(_) => "Boolean",
+            Value::List(_) => "List",
+            Value::Dictionary(_) => "Dictionary",
+            _ => return None,
+        };
+        Some(sym!(class))
+    }
+
+    /// The members of `class` if it's a union, or else just `class`.
+    fn members(&self, class: &Symbol) -> Vec<Symbol> {
+        let term = term!(class.clone());
+        if !self.kb.is_union(&term) {
+            return vec![class.clone()];
+        }
+        self.kb
+            .get_union_members(&term)
+            .iter()
+            .filter_map(|member| match member.value() {
+                Value::Variable(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Classes without a registered MRO are assumed to have no subclasses.
+    fn is_subclass(&self, class: &Symbol, superclass: &Symbol) -> bool {
+        if class == superclass {
+            return true;
+        }
+        let superclass = self.kb.get_registered_class(&term!(superclass.clone()));
+        match (self.kb.mro.get(class), superclass.map(Term::value)) {
+            (Some(mro), Ok(Value::ExternalInstance(ExternalInstance { instance_id, .. }))) => {
+                mro.contains(instance_id)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether an instance of `class` could also be an instance of `expected`. Unions without
+    /// members could be anything.
+    fn may_match(&self, class: &Symbol, expected: &Symbol) -> bool {
+        let (classes, expected) = (self.members(class), self.members(expected));
+        classes.is_empty()
+            || expected.is_empty()
+            || classes.iter().any(|class| {
+                expected.iter().any(|expected| {
+                    self.is_subclass(class, expected) || self.is_subclass(expected, class)
+                })
+            })
+    }
+
+    /// Why a call with arguments of `classes` can't match `rule_type`, if it can't.
+    fn mismatch(&self, classes: &[Option<Symbol>], rule_type: &Rule) -> Option<String> {
+        if classes.len() != rule_type.params.len() {
+            return Some(format!(
+                "Different number of arguments. Call has {} argument(s) but rule type has {} parameter(s).",
+                classes.len(),
+                rule_type.params.len()
+            ));
+        }
+        classes
+            .iter()
+            .zip(&rule_type.params)
+            .enumerate()
+            .find_map(|(i, (class, param))| {
+                let class = class.as_ref()?;
+                match param.specializer.as_ref().map(Term::value) {
+                    Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })))
+                        if !self.may_match(class, tag) =>
+                    {
+                        Some(format!(
+                            "Argument {} is a {} but rule type expected {}.",
+                            i + 1,
+                            class,
+                            tag
+                        ))
+                    }
+                    _ => None,
+                }
+            })
+    }
+
+    fn check_call(&mut self, term: &Term, call: &Call) {
+        let rule_types = match self.kb.get_rule_types(&call.name) {
+            Some(rule_types) if !rule_types.is_empty() => rule_types,
+            _ => return,
+        };
+        let classes = call
+            .args
+            .iter()
+            .map(|arg| self.class_of(arg))
+            .collect::<Vec<_>>();
+        let mut msg = "Must match one of the following rule types:\n".to_owned();
+        for rule_type in rule_types {
+            match self.mismatch(&classes, rule_type) {
+                Some(reason) => write!(
+                    msg,
+                    "\n{}\n\tFailed to match because: {}\n",
+                    rule_type, reason
+                )
+                .unwrap(),
+                None => return,
+            }
+        }
+        self.invalid_calls.push((term.clone(), msg));
+    }
+}
+
+impl<'kb> Visitor for RuleCallTypeVisitor<'kb> {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Expression(op)
+                if op.operator == Operator::Dot || op.operator == Operator::New =>
+            {
+                return
+            }
+            Value::Call(call) => self.check_call(term, call),
+            _ => {}
+        }
+        walk_term(self, term)
+    }
+}
+
+pub fn check_rule_call_types(kb: &KnowledgeBase) -> Vec<Diagnostic> {
+    let mut visitor = RuleCallTypeVisitor::new(kb);
+    for generic_rule in kb.get_rules().values() {
+        for rule in generic_rule.rules.values() {
+            visitor.check_rule(rule);
+        }
+    }
+    visitor.warnings()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
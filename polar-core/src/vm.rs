@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeMap;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
@@ -17,16 +17,19 @@ use crate::data_filtering::partition_equivs;
 use crate::debugger::{get_binding_for_var, DebugEvent, Debugger};
 use crate::error::{invalid_state, unsupported, PolarError, PolarResult, RuntimeError};
 use crate::events::*;
+use crate::explain::{ExplainLog, ExplainedRule};
 use crate::folder::Folder;
 use crate::inverter::Inverter;
 use crate::kb::*;
 use crate::messages::*;
 use crate::numerics::*;
 use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::query::{CancellationToken, QueryLimits};
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
 use crate::sources::Context;
+use crate::tabling::{canonicalize, TableRunnable, Tables};
 use crate::terms::*;
 use crate::traces::*;
 use crate::visitor::{walk_term, Visitor};
@@ -133,6 +136,12 @@ pub enum Goal {
         runnable: Box<dyn Runnable>,
     },
 
+    /// Choose among the answers in the table for the tabled call `term`.
+    TableAnswers {
+        term: Term,
+        key: Term,
+    },
+
     /// Add a new constraint
     AddConstraint {
         term: Term,
@@ -153,6 +162,7 @@ pub struct Choice {
     queries: Queries,      // query stack snapshot
     trace: Vec<Rc<Trace>>, // trace snapshot
     trace_stack: TraceStack,
+    explain_parent: Option<usize>, // explanation snapshot
 }
 
 pub type Choices = Vec<Choice>;
@@ -235,6 +245,11 @@ pub struct PolarVirtualMachine {
     pub trace_stack: TraceStack, // Stack of traces higher up the tree.
     pub trace: Vec<Rc<Trace>>,   // Traces for the current level of the trace tree.
 
+    /// Rule attempts, recorded when explaining the query.
+    explain: Option<ExplainLog>,
+    /// The rule attempt currently running.
+    explain_parent: Option<usize>,
+
     // Errors from outside the vm.
     pub external_error: Option<String>,
 
@@ -242,10 +257,13 @@ pub struct PolarVirtualMachine {
     query_start_time: Option<std::time::Instant>,
     #[cfg(target_arch = "wasm32")]
     query_start_time: Option<f64>,
-    query_timeout_ms: u64,
 
-    /// Maximum size of goal stack
-    stack_limit: usize,
+    limits: QueryLimits,
+    /// Goals run so far, shared with the VMs cloned from this one.
+    goal_count: Rc<Cell<u64>>,
+    cancellation: CancellationToken,
+    /// Answers to calls to tabled rules, shared with the VMs cloned from this one.
+    tables: Rc<RefCell<Tables>>,
 
     /// Binding stack constant below here.
     csp: Bsp,
@@ -302,10 +320,6 @@ impl PolarVirtualMachine {
         goals: Goals,
         messages: MessageQueue,
     ) -> Self {
-        let query_timeout_ms = std::env::var("POLAR_TIMEOUT_MS")
-            .ok()
-            .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
-            .unwrap_or(DEFAULT_TIMEOUT_MS);
         let constants = kb
             .read()
             .expect("cannot acquire KB read lock")
@@ -316,14 +330,18 @@ impl PolarVirtualMachine {
             goals: GoalStack::new_reversed(goals),
             binding_manager: BindingManager::new(),
             query_start_time: None,
-            query_timeout_ms,
-            stack_limit: MAX_STACK_SIZE,
+            limits: QueryLimits::from_env(),
+            goal_count: Rc::new(Cell::new(0)),
+            cancellation: CancellationToken::default(),
+            tables: Rc::new(RefCell::new(Tables::default())),
             csp: Bsp::default(),
             choices: vec![],
             queries: vec![],
             tracing,
             trace_stack: vec![],
             trace: vec![],
+            explain: None,
+            explain_parent: None,
             external_error: None,
             debugger: Debugger::default(),
             kb,
@@ -417,12 +435,24 @@ impl PolarVirtualMachine {
         vm.binding_manager.clone_from(&self.binding_manager);
         vm.query_contains_partial = self.query_contains_partial;
         vm.debugger = self.debugger.clone();
+        vm.limits = self.limits;
+        vm.goal_count = self.goal_count.clone();
+        vm.cancellation = self.cancellation.clone();
+        vm.tables = self.tables.clone();
+        vm.query_start_time = self.query_start_time;
         vm
     }
 
-    #[cfg(test)]
-    fn set_stack_limit(&mut self, limit: usize) {
-        self.stack_limit = limit;
+    pub fn set_limits(&mut self, limits: QueryLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> QueryLimits {
+        self.limits
+    }
+
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
     }
 
     fn kb(&self) -> RwLockReadGuard<KnowledgeBase> {
@@ -433,6 +463,27 @@ impl PolarVirtualMachine {
         self.kb().new_id()
     }
 
+    /// Record the rules tried by the query, for `explanation`.
+    pub fn set_explain(&mut self, explain: bool) {
+        self.explain = explain.then(ExplainLog::default);
+    }
+
+    /// The rules tried so far, if `set_explain` was called.
+    pub fn explanation(&self) -> Option<Vec<ExplainedRule>> {
+        self.explain.as_ref().map(ExplainLog::explain)
+    }
+
+    /// The outermost rule in the trace of the current result.
+    pub fn matched_rule(&self) -> Option<Arc<Rule>> {
+        fn find(trace: &Trace) -> Option<Arc<Rule>> {
+            match &trace.node {
+                Node::Rule(rule) => Some(rule.clone()),
+                Node::Term(_) => trace.children.iter().find_map(|child| find(child)),
+            }
+        }
+        self.trace.iter().find_map(|trace| find(trace))
+    }
+
     pub fn id_counter(&self) -> Counter {
         self.kb().id_counter()
     }
@@ -461,7 +512,11 @@ impl PolarVirtualMachine {
     fn next(&mut self, goal: Rc<Goal>) -> PolarResult<QueryEvent> {
         self.log(LogLevel::Trace, || goal.to_string(), &[]);
 
+        if self.cancellation.is_cancelled() {
+            return Err(RuntimeError::QueryCancelled.into());
+        }
         self.check_timeout()?;
+        self.count_goal()?;
 
         match goal.as_ref() {
             Goal::Backtrack => self.backtrack()?,
@@ -523,11 +578,21 @@ impl PolarVirtualMachine {
                 let trace = Rc::make_mut(&mut trace);
                 trace.children.append(&mut children);
                 self.trace.push(Rc::new(trace.clone()));
+                if let (Some(id), Some(explain)) = (self.explain_parent, self.explain.as_ref()) {
+                    let call = self.deref(explain.call(id));
+                    self.explain_parent = self.explain.as_mut().unwrap().matched(id, &call);
+                }
                 self.maybe_break(DebugEvent::Pop)?;
             }
             Goal::TraceRule { trace } => {
                 if let Node::Rule(rule) = &trace.node {
                     self.log(LogLevel::Info, || format!("RULE: {}", rule), &[]);
+                    if let (Some(_), Some(call)) = (&self.explain, self.queries.last()) {
+                        let call = self.deref(call);
+                        let explain = self.explain.as_mut().unwrap();
+                        self.explain_parent =
+                            Some(explain.enter(self.explain_parent, rule.clone(), call));
+                    }
                 }
                 self.trace.push(trace.clone());
                 self.maybe_break(DebugEvent::Rule)?;
@@ -541,6 +606,10 @@ impl PolarVirtualMachine {
                     .try_for_each(|(_, constraint)| self.add_constraint(&constraint))?
             }
             Goal::Run { runnable } => return self.run_runnable(runnable.clone_runnable()),
+            Goal::TableAnswers { term, key } => {
+                let answers = self.tables.borrow().all_answers(key);
+                self.choose_table_answers(term, answers)?
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -548,8 +617,11 @@ impl PolarVirtualMachine {
     /// Push a goal onto the goal stack.
     pub fn push_goal(&mut self, goal: Goal) -> PolarResult<()> {
         use {Goal::*, VariableState::Unbound};
-        if self.goals.len() >= self.stack_limit {
-            let msg = format!("Goal stack overflow! MAX_GOALS = {}", self.stack_limit);
+        if self.goals.len() >= self.limits.max_stack_depth {
+            let msg = format!(
+                "Goal stack overflow! MAX_GOALS = {}",
+                self.limits.max_stack_depth
+            );
             Err(RuntimeError::StackOverflow { msg }.into())
         } else if matches!(goal, LookupExternal { call_id, ..} | NextExternal { call_id, .. } if self.variable_state(self.get_call_sym(call_id)) != Unbound)
         {
@@ -581,7 +653,7 @@ impl PolarVirtualMachine {
             .rev()
             .map(GoalStack::new_reversed)
             .collect();
-        if self.choices.len() >= self.stack_limit {
+        if self.choices.len() >= self.limits.max_stack_depth {
             let msg = "Too many choices.".to_owned();
             Err(RuntimeError::StackOverflow { msg }.into())
         } else {
@@ -592,6 +664,7 @@ impl PolarVirtualMachine {
                 queries: self.queries.clone(),
                 trace: self.trace.clone(),
                 trace_stack: self.trace_stack.clone(),
+                explain_parent: self.explain_parent,
             });
             Ok(())
         }
@@ -886,7 +959,7 @@ impl PolarVirtualMachine {
     }
 
     fn is_query_timeout_disabled(&self) -> bool {
-        self.query_timeout_ms == 0
+        self.limits.timeout_ms == 0
     }
 
     fn check_timeout(&self) -> PolarResult<()> {
@@ -896,12 +969,21 @@ impl PolarVirtualMachine {
         }
 
         let elapsed = self.query_duration();
-        let timeout = self.query_timeout_ms;
+        let timeout = self.limits.timeout_ms;
         if elapsed > timeout {
             return Err(RuntimeError::QueryTimeout { elapsed, timeout }.into());
         }
         Ok(())
     }
+
+    fn count_goal(&self) -> PolarResult<()> {
+        let count = self.goal_count.get() + 1;
+        self.goal_count.set(count);
+        match self.limits.max_goals {
+            Some(limit) if count > limit => Err(RuntimeError::GoalLimitExceeded { limit }.into()),
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Implementations of instructions.
@@ -911,6 +993,22 @@ impl PolarVirtualMachine {
     fn backtrack(&mut self) -> PolarResult<()> {
         self.log(LogLevel::Trace, || "BACKTRACK", &[]);
 
+        if let (Some(id), Some(query)) = (self.explain_parent, self.queries.last()) {
+            // Show the values of variables in the failed operation.
+            let failed_at = match query.value() {
+                Value::Expression(Operation { operator, args }) => {
+                    query.clone_with_value(Value::Expression(Operation {
+                        operator: *operator,
+                        args: args.iter().map(|arg| self.deref(arg)).collect(),
+                    }))
+                }
+                _ => self.deref(query),
+            };
+            if let Some(explain) = self.explain.as_mut() {
+                explain.failed(id, failed_at);
+            }
+        }
+
         loop {
             match self.choices.pop() {
                 None => return self.push_goal(Goal::Halt),
@@ -921,8 +1019,10 @@ impl PolarVirtualMachine {
                     queries,
                     trace,
                     trace_stack,
+                    explain_parent,
                 }) => {
                     self.binding_manager.backtrack(&bsp);
+                    self.explain_parent = explain_parent;
                     if let Some(mut alternative) = alternatives.pop() {
                         if alternatives.is_empty() {
                             self.goals = goals;
@@ -941,6 +1041,7 @@ impl PolarVirtualMachine {
                                 queries,
                                 trace,
                                 trace_stack,
+                                explain_parent,
                             })
                         }
                         self.goals.append(&mut alternative);
@@ -1506,6 +1607,9 @@ impl PolarVirtualMachine {
         }));
 
         match &term.value() {
+            Value::Call(predicate) if self.kb().is_tabled(&predicate.name) => {
+                self.query_tabled(term)?;
+            }
             Value::Call(predicate) => {
                 self.query_for_predicate(predicate.clone())?;
             }
@@ -1552,19 +1656,25 @@ impl PolarVirtualMachine {
     /// Sort applicable rules by specificity.
     /// Create a choice over the applicable rules.
     fn query_for_predicate(&mut self, predicate: Call) -> PolarResult<()> {
+        let goals = self.predicate_goals(predicate)?;
+        self.polar_trace_mute = true;
+        self.append_goals(goals)
+    }
+
+    /// The goals that query the applicable rules for predicate.
+    fn predicate_goals(&self, predicate: Call) -> PolarResult<Goals> {
         if predicate.kwargs.is_some() {
             return invalid_state(format!(
                 "query_for_predicate: unexpected kwargs: {}",
                 predicate
             ));
         }
-        let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
-            None => {
-                return Err(RuntimeError::QueryForUndefinedRule {
-                    name: predicate.name.0.clone(),
-                }
-                .into())
+        let kb = self.kb();
+        match kb.get_generic_rule(&predicate.name) {
+            None => Err(RuntimeError::QueryForUndefinedRule {
+                name: predicate.name.0.clone(),
             }
+            .into()),
             Some(generic_rule) => {
                 if generic_rule.name != predicate.name {
                     return invalid_state(format!(
@@ -1574,13 +1684,15 @@ impl PolarVirtualMachine {
                 }
 
                 // Pre-filter rules.
-                let args = predicate.args.iter().map(|t| self.deref(t)).collect();
-                let pre_filter = generic_rule.get_applicable_rules(&args);
-
-                self.polar_trace_mute = true;
+                let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
+                let classes = args
+                    .iter()
+                    .map(|arg| kb.instance_classes(arg))
+                    .collect::<Vec<_>>();
+                let pre_filter = generic_rule.get_applicable_rules(&args, &classes);
 
                 // Filter rules by applicability.
-                vec![
+                Ok(vec![
                     Goal::TraceStackPush,
                     Goal::FilterRules {
                         applicable_rules: vec![],
@@ -1588,10 +1700,90 @@ impl PolarVirtualMachine {
                         args: predicate.args,
                     },
                     Goal::TraceStackPop,
-                ]
+                ])
+            }
+        }
+    }
+
+    /// Query a tabled rule. The first call with given arguments evaluates
+    /// the rules in a sub-VM, memoizing the answers; later calls, including
+    /// recursive ones, choose among the memoized answers.
+    fn query_tabled(&mut self, term: &Term) -> PolarResult<()> {
+        let call = self.deref(term);
+        let key = canonicalize(&call);
+        let answers = self.tables.borrow_mut().answers(&key);
+        match answers {
+            Some(answers) => self.choose_table_answers(term, answers),
+            None => {
+                let goals = self.predicate_goals(call.as_call()?.clone())?;
+                let mut vm = self.clone_with_goals(goals);
+                vm.queries.push(term.clone());
+                vm.trace.push(Rc::new(Trace {
+                    node: Node::Term(term.clone()),
+                    children: vec![],
+                }));
+                let runnable = Box::new(TableRunnable::new(
+                    vm,
+                    call,
+                    key.clone(),
+                    self.tables.clone(),
+                ));
+                self.append_goals(vec![
+                    Goal::Run { runnable },
+                    Goal::TableAnswers {
+                        term: term.clone(),
+                        key,
+                    },
+                ])
             }
+        }
+    }
+
+    fn choose_table_answers(&mut self, term: &Term, answers: Vec<Term>) -> PolarResult<()> {
+        let alternatives = {
+            let kb = self.kb();
+            answers
+                .into_iter()
+                .map(|answer| {
+                    let answer = Renamer::new(&kb).fold_term(answer);
+                    vec![Goal::Unify {
+                        left: term.clone(),
+                        right: answer,
+                    }]
+                })
+                .collect::<Vec<_>>()
         };
-        self.append_goals(goals)
+        self.choose(alternatives)
+    }
+
+    /// The answer to the tabled call `call` given the current bindings.
+    pub fn table_answer(&self, call: &Term) -> PolarResult<Term> {
+        struct PartialVisitor<'vm> {
+            has_partial: bool,
+            vm: &'vm PolarVirtualMachine,
+        }
+
+        impl<'vm> Visitor for PartialVisitor<'vm> {
+            fn visit_variable(&mut self, v: &Symbol) {
+                if matches!(self.vm.variable_state(v), VariableState::Partial) {
+                    self.has_partial = true;
+                }
+            }
+        }
+
+        let answer = self.deref(call);
+        let mut visitor = PartialVisitor {
+            has_partial: false,
+            vm: self,
+        };
+        walk_term(&mut visitor, &answer);
+        if visitor.has_partial {
+            return unsupported(
+                format!("Tabled rules cannot have partial answers: {}", answer),
+                answer,
+            );
+        }
+        Ok(canonicalize(&answer))
     }
 
     fn query_for_operation(&mut self, term: &Term) -> PolarResult<QueryEvent> {
@@ -3742,6 +3934,99 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_external_isa_for_class_specialized_rules() {
+        let mut kb = KnowledgeBase::new();
+        let class = |id| {
+            term!(Value::ExternalInstance(ExternalInstance {
+                instance_id: id,
+                constructor: None,
+                repr: None,
+                class_repr: None,
+                class_id: Some(id),
+            }))
+        };
+        for (name, id) in [("Base", 1), ("Repo", 2), ("Issue", 3)] {
+            kb.register_constant(sym!(name), class(id)).unwrap();
+        }
+        kb.add_mro(sym!("Base"), vec![1]).unwrap();
+        kb.add_mro(sym!("Repo"), vec![2, 1]).unwrap();
+        kb.add_mro(sym!("Issue"), vec![3, 1]).unwrap();
+        kb.add_generic_rule(GenericRule::new(
+            sym!("f"),
+            vec![
+                Arc::new(rule!("f", ["_"; instance!("Base"), value!(1)])),
+                Arc::new(rule!("f", ["_"; instance!("Repo"), value!(2)])),
+                Arc::new(rule!("f", ["_"; instance!("Issue"), value!(3)])),
+            ],
+        ));
+        let kb = Arc::new(RwLock::new(kb));
+
+        // Run `f(instance, x)`, answering `ExternalIsa` from `mro`.
+        let run = |class_id, mro: &[&str]| {
+            let instance = Value::ExternalInstance(ExternalInstance {
+                instance_id: 4,
+                constructor: None,
+                repr: None,
+                class_repr: None,
+                class_id,
+            });
+            let query = query!(call!("f", [instance, sym!("x")]));
+            let mut vm = PolarVirtualMachine::new_test(kb.clone(), false, vec![query]);
+            let mut external_isas = vec![];
+            let mut results = vec![];
+            loop {
+                match vm.run(None).unwrap() {
+                    QueryEvent::Done { .. } => break,
+                    QueryEvent::Result { bindings, .. } => {
+                        results.push(bindings[&sym!("x")].clone())
+                    }
+                    QueryEvent::ExternalIsa {
+                        call_id, class_tag, ..
+                    } => {
+                        let isa = mro.contains(&class_tag.0.as_str());
+                        external_isas.push(class_tag);
+                        vm.external_question_result(call_id, isa).unwrap()
+                    }
+                    QueryEvent::ExternalIsSubSpecializer {
+                        call_id,
+                        left_class_tag,
+                        right_class_tag,
+                        ..
+                    } => {
+                        let left = mro.iter().position(|c| *c == left_class_tag.0);
+                        let right = mro.iter().position(|c| *c == right_class_tag.0);
+                        vm.external_question_result(call_id, left < right).unwrap()
+                    }
+                    e => panic!("Unexpected event: {:?}", e),
+                }
+            }
+            external_isas.sort();
+            (external_isas, results)
+        };
+
+        // Without a class ID, the host is asked about every specialized rule
+        // when filtering them, and again about each applicable one.
+        let (external_isas, results) = run(None, &["Repo", "Base"]);
+        assert_eq!(
+            external_isas,
+            vec![
+                sym!("Base"),
+                sym!("Base"),
+                sym!("Issue"),
+                sym!("Repo"),
+                sym!("Repo")
+            ]
+        );
+        assert_eq!(results, vec![term!(2), term!(1)]);
+
+        // With one, the registered MRO answers instead: rules specialized on
+        // classes outside it are skipped, and the host is never asked.
+        let (external_isas, results) = run(Some(2), &["Repo", "Base"]);
+        assert!(external_isas.is_empty(), "{:?}", external_isas);
+        assert_eq!(results, vec![term!(2), term!(1)]);
+    }
+
     #[test]
     fn test_sort_rules() {
         // Test sort rule by mocking ExternalIsSubSpecializer and ExternalIsa.
@@ -3859,7 +4144,7 @@ mod tests {
     #[test]
     fn test_timeout() {
         let vm = PolarVirtualMachine::default();
-        assert!(vm.query_timeout_ms == DEFAULT_TIMEOUT_MS);
+        assert!(vm.limits.timeout_ms == DEFAULT_TIMEOUT_MS);
 
         std::env::set_var("POLAR_TIMEOUT_MS", "0");
         let vm = PolarVirtualMachine::default();
@@ -3870,7 +4155,7 @@ mod tests {
         let mut vm = PolarVirtualMachine::default();
         std::env::remove_var("POLAR_TIMEOUT_MS");
         // Turn this off so we don't hit it.
-        vm.set_stack_limit(std::usize::MAX);
+        vm.limits.max_stack_depth = std::usize::MAX;
 
         loop {
             vm.push_goal(Goal::Noop).unwrap();
@@ -3895,6 +4180,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_limits() {
+        let mut vm = PolarVirtualMachine::default();
+        vm.set_limits(QueryLimits {
+            max_goals: Some(3),
+            ..QueryLimits::default()
+        });
+        for _ in 0..5 {
+            vm.push_goal(Goal::Noop).unwrap();
+        }
+        let err = vm.run(None).unwrap_err();
+        assert!(matches!(
+            err.0,
+            ErrorKind::Runtime(RuntimeError::GoalLimitExceeded { limit: 3 })
+        ));
+
+        let mut vm = PolarVirtualMachine::default();
+        vm.set_limits(QueryLimits {
+            max_stack_depth: 2,
+            ..QueryLimits::default()
+        });
+        vm.push_goal(Goal::Noop).unwrap();
+        vm.push_goal(Goal::Noop).unwrap();
+        let err = vm.push_goal(Goal::Noop).unwrap_err();
+        assert!(matches!(
+            err.0,
+            ErrorKind::Runtime(RuntimeError::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let mut vm = PolarVirtualMachine::default();
+        vm.push_goal(Goal::Noop).unwrap();
+        vm.cancellation_token().cancel();
+        let err = vm.run(None).unwrap_err();
+        assert!(matches!(
+            err.0,
+            ErrorKind::Runtime(RuntimeError::QueryCancelled)
+        ));
+
+        // VMs cloned for inner queries share the token.
+        let vm = PolarVirtualMachine::default();
+        let mut inner = vm.clone_with_goals(vec![Goal::Noop]);
+        vm.cancellation_token().cancel();
+        assert!(inner.run(None).is_err());
+    }
+
     #[test]
     fn test_prefiltering() {
         let bar_rule = GenericRule::new(
@@ -18,9 +18,9 @@ impl PolarWarning {
         use ValidationWarning::*;
 
         match &self.0 {
-            AmbiguousPrecedence { term } | UnknownSpecializer { term, .. } => {
-                term.parsed_context().cloned()
-            }
+            AmbiguousPrecedence { term }
+            | InvalidRuleCall { term, .. }
+            | UnknownSpecializer { term, .. } => term.parsed_context().cloned(),
             MissingAllowRule | MissingHasPermissionRule => None,
         }
     }
@@ -40,6 +40,8 @@ impl fmt::Display for PolarWarning {
 pub enum ValidationWarning {
     // Category: general
     AmbiguousPrecedence { term: Term },
+    // Category: rule types
+    InvalidRuleCall { term: Term, msg: String },
     // Category: enforcement
     MissingAllowRule,
     // Category: resource blocks
@@ -131,6 +133,7 @@ impl fmt::Display for ValidationWarning {
 
         match self {
             AmbiguousPrecedence { .. } => write!(f, "{}", AMBIGUOUS_PRECEDENCE_MSG)?,
+            InvalidRuleCall { term, msg } => write!(f, "Invalid rule call: {} {}", term, msg)?,
             MissingAllowRule => write!(f, "{}", MISSING_ALLOW_RULE_MSG)?,
             MissingHasPermissionRule => write!(f, "{}", MISSING_HAS_PERMISSION_RULE_MSG)?,
             UnknownSpecializer { term, sym } => {
@@ -1821,6 +1821,104 @@ fn test_unknown_specializer_suggestions() -> TestResult {
     Ok(())
 }
 
+fn warnings(p: &Polar) -> Vec<String> {
+    let mut warnings = vec![];
+    while let Some(msg) = p.next_message() {
+        if matches!(msg.kind, MessageKind::Warning) {
+            warnings.push(msg.msg);
+        }
+    }
+    warnings
+}
+
+fn register_class(p: &Polar, name: &str, instance_id: u64, mro: Vec<u64>) -> TestResult {
+    let instance = ExternalInstance {
+        instance_id,
+        constructor: None,
+        repr: None,
+        class_repr: None,
+        class_id: None,
+    };
+    p.register_constant(sym!(name), term!(Value::ExternalInstance(instance)))?;
+    p.register_mro(sym!(name), mro)?;
+    Ok(())
+}
+
+#[test]
+fn test_invalid_rule_call_warning() -> TestResult {
+    let p = polar();
+    register_class(&p, "User", 1, vec![1])?;
+    register_class(&p, "Organization", 2, vec![2])?;
+    register_class(&p, "Repository", 3, vec![3])?;
+    p.load_str(indoc! {r#"
+        actor User {}
+        resource Organization {
+          roles = ["admin"];
+          permissions = ["read"];
+          "read" if "admin";
+        }
+        has_role(user: User, "admin", org: Organization) if org.admin = user;
+        allow(actor, action, resource) if has_permission(actor, action, resource);
+        allow(user: User, "read", repo: Repository) if has_role(user, "admin", repo);"#})?;
+    // Repository doesn't have a resource block, so it isn't a Resource.
+    assert_eq!(
+        warnings(&p),
+        vec![indoc! {"
+            Invalid rule call: has_role(user, \"admin\", repo) Must match one of the following rule types:
+
+            has_role(actor: Actor{}, role: String{}, resource: Resource{});
+            \tFailed to match because: Argument 3 is a Repository but rule type expected Resource.
+             at line 9, column 48:
+            \t009: allow(user: User, \"read\", repo: Repository) if has_role(user, \"admin\", repo);
+            \t                                                    ^
+        "}]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_rule_call_types() -> TestResult {
+    let p = polar();
+    register_class(&p, "Animal", 1, vec![1])?;
+    register_class(&p, "Dog", 2, vec![2, 1])?;
+    register_class(&p, "Rock", 3, vec![3])?;
+    p.load_str(indoc! {r#"
+        type f(x: Integer);
+        f(_: Integer);
+        type pet(animal: Animal);
+        pet(_: Animal);
+        ok(x) if x matches Integer and f(x);
+        ok(x) if x matches Integer and f(x + 1);
+        ok(dog: Dog) if pet(dog);
+        ok(animal: Animal) if pet(animal);
+        not_ok() if f("one");
+        not_ok(x: String) if f(x);
+        not_ok(x) if x matches Float and f(x);
+        not_ok(rock: Rock) if pet(rock);
+        not_ok() if f(1, 2);"#})?;
+    // The built-in classes aren't registered, so their specializers are unknown.
+    let warnings: Vec<_> = warnings(&p)
+        .into_iter()
+        .filter(|warning| !warning.starts_with("Unknown specializer"))
+        .collect();
+    let calls: Vec<_> = warnings
+        .iter()
+        .map(|warning| warning.split(" Must match").next().unwrap())
+        .collect();
+    assert_eq!(
+        calls,
+        vec![
+            r#"Invalid rule call: f("one")"#,
+            "Invalid rule call: f(x)",
+            "Invalid rule call: f(x)",
+            "Invalid rule call: pet(rock)",
+            "Invalid rule call: f(1, 2)",
+        ]
+    );
+    assert!(warnings[4].contains("Call has 2 argument(s) but rule type has 1 parameter(s)."));
+    Ok(())
+}
+
 #[test]
 fn test_partial_grounding() -> TestResult {
     let rules = r#"
@@ -2765,3 +2863,163 @@ fn test_internal_isa_check() -> TestResult {
     assert_eq!(results.len(), 1);
     Ok(())
 }
+
+#[test]
+fn test_tabling() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"@table path;
+           edge(1, 2);
+           edge(2, 3);
+           edge(3, 1);
+           edge(3, 4);
+           path(x, y) if path(x, z) and edge(z, y);
+           path(x, y) if edge(x, y);"#,
+    )?;
+    // Left recursion through a cycle terminates, and each answer is found once.
+    let mut reachable = var(&p, "path(1, y)", "y");
+    reachable.sort_by_key(|v| v.to_string());
+    assert_eq!(reachable, values![1, 2, 3, 4]);
+    qeval(&p, "path(4, 4) or path(2, 1)");
+    qnull(&p, "path(4, _)");
+    assert_eq!(vars(&p, "path(x, y)", &["x", "y"]).len(), 12);
+
+    // Mutually recursive tabled rules.
+    p.clear_rules();
+    p.load_str(
+        r#"@table even;
+           @table odd;
+           succ(0, 1);
+           succ(1, 2);
+           succ(2, 3);
+           succ(3, 4);
+           even(0);
+           even(x) if odd(y) and succ(y, x);
+           odd(x) if even(y) and succ(y, x);"#,
+    )?;
+    let mut evens = var(&p, "even(x)", "x");
+    evens.sort_by_key(|v| v.to_string());
+    assert_eq!(evens, values![0, 2, 4]);
+    qeval(&p, "odd(3)");
+    qnull(&p, "odd(4)");
+
+    qparse!(
+        "@memo f;",
+        ParseErrorKind::InvalidTokenCharacter { c: '@', .. }
+    );
+    Ok(())
+}
+
+#[test]
+fn test_compiled_policy() -> Result<(), Box<dyn std::error::Error>> {
+    let p = polar();
+    p.register_constant(sym!("Limit"), term!(3))?;
+    register_class(&p, "User", 1, vec![1])?;
+    p.load_str(
+        r#"@table reach;
+           type count(x);
+           count(x) if x in [1, 2, 3] and x <= Limit;
+           edge(1, 2);
+           edge(2, 1);
+           reach(x, y) if reach(x, z) and edge(z, y);
+           reach(x, y) if edge(x, y);
+           named(d, n) if n = d.name;
+           user(_: User);
+           test "counts to the limit" {
+             fixture x = Limit;
+             assert count(x);
+           }"#,
+    )?;
+    let json = serde_json::to_string(&p.compile())?;
+
+    // Constants that aren't host instances are compiled with the policy.
+    let q = polar();
+    register_class(&q, "User", 1, vec![1])?;
+    q.load_compiled(serde_json::from_str(&json)?)?;
+    // Compiling the loaded policy again gives the same policy.
+    assert_eq!(serde_json::to_string(&q.compile())?, json);
+    assert_eq!(var(&q, "count(x)", "x"), values![1, 2, 3]);
+    let tests = q.policy_tests();
+    assert_eq!(tests, p.policy_tests());
+    qeval(&q, &tests[0].query(&tests[0].assertions[0]).to_string());
+    let mut reachable = var(&q, "reach(1, y)", "y");
+    reachable.sort_by_key(|v| v.to_string());
+    assert_eq!(reachable, values![1, 2]);
+    assert_eq!(var(&q, "named({name: \"d\"}, n)", "n"), values!["d"]);
+
+    // Constants registered by the host take precedence.
+    let r = polar();
+    register_class(&r, "User", 1, vec![1])?;
+    r.register_constant(sym!("Limit"), term!(2))?;
+    r.load_compiled(serde_json::from_str(&json)?)?;
+    assert_eq!(var(&r, "count(x)", "x"), values![1, 2]);
+
+    // Like `load`, a compiled policy can only be loaded into an empty
+    // knowledge base, and needs the classes it refers to.
+    assert!(matches!(
+        q.load_compiled(serde_json::from_str(&json)?).unwrap_err().0,
+        ErrorKind::Runtime(MultipleLoadError)
+    ));
+
+    let e = polar()
+        .load_compiled(serde_json::from_str(&json)?)
+        .unwrap_err();
+    assert!(
+        matches!(e.0, ErrorKind::Validation(UnregisteredClass { .. })),
+        "{}",
+        e
+    );
+
+    let mut old: serde_json::Value = serde_json::from_str(&json)?;
+    old["version"] = 0.into();
+    let e = q
+        .fork()
+        .load_compiled(serde_json::from_value(old)?)
+        .unwrap_err();
+    assert!(
+        matches!(
+            e.0,
+            ErrorKind::Operational(OperationalError::Serialization { .. })
+        ),
+        "{}",
+        e
+    );
+    Ok(())
+}
+
+#[test]
+fn test_policy_test_blocks() -> TestResult {
+    let p = polar();
+    // The keywords aren't reserved words.
+    p.load_str(
+        r#"test(x) if assert(x);
+           assert(1);
+           test "one" {
+             fixture x = 1;
+             assert test(x);
+             assert_not test(2);
+           }"#,
+    )?;
+    qeval(&p, "test(1)");
+    let tests = p.policy_tests();
+    assert_eq!(tests.len(), 1);
+    assert_eq!(tests[0].name(), "one");
+    for assertion in &tests[0].assertions {
+        qeval(&p, &tests[0].query(assertion).to_string());
+    }
+
+    p.clear_rules();
+    assert!(p.policy_tests().is_empty());
+    let e = p
+        .load_str(
+            r#"test "one" { assert true; }
+               test "one" { assert false; }"#,
+        )
+        .unwrap_err();
+    assert!(
+        matches!(&e.0, ErrorKind::Validation(PolicyTest { msg, .. }) if msg == "Duplicate test 'one'."),
+        "{}",
+        e
+    );
+    Ok(())
+}
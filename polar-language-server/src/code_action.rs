@@ -0,0 +1,154 @@
+//! Quick fixes for the diagnostics produced by loading the tracked documents.
+
+use std::collections::BTreeSet;
+
+use lsp_types::{Position, Range, TextDocumentItem, TextEdit};
+use polar_core::{
+    diagnostic::Diagnostic,
+    error::{ErrorKind::Validation, PolarError, ValidationError::SingletonVariable},
+    kb::KnowledgeBase,
+    resource_block::Declaration,
+    terms::{Symbol, Value},
+    warning::{PolarWarning, ValidationWarning::*},
+};
+
+use crate::helpers::position_at;
+
+/// The rule added when a policy that uses permissions has no `allow` rule, or never calls
+/// `has_permission`.
+const HAS_PERMISSION_ALLOW_RULE: &str =
+    "allow(actor, action, resource) if\n  has_permission(actor, action, resource);\n";
+
+/// The rule added when a policy that doesn't use permissions has no `allow` rule. It denies
+/// everything until its body is filled in.
+const ALLOW_RULE: &str =
+    "allow(_actor, _action, _resource) if\n  # Add the conditions for allowing.\n  false;\n";
+
+/// How far an unknown specializer can be from a class name to be suggested as a typo for it.
+const MAX_TYPO_DISTANCE: usize = 2;
+
+/// A titled set of edits to one document.
+pub(crate) type Fix = (String, Vec<TextEdit>);
+
+/// Whether the policy in `kb` defines `has_permission` rules or declares permissions in a
+/// resource block.
+pub(crate) fn uses_permissions(kb: &KnowledgeBase) -> bool {
+    kb.get_rules().contains_key(&Symbol::new("has_permission"))
+        || kb
+            .resource_blocks
+            .declarations()
+            .values()
+            .flat_map(|declarations| declarations.values())
+            .any(|declaration| matches!(declaration, Declaration::Permission))
+}
+
+/// The fixes for `diagnostic` in `doc`. Diagnostics without a location are fixed by adding to
+/// the end of `doc`, with `permissions` saying whether the policy uses permissions.
+pub(crate) fn quick_fixes(
+    diagnostic: &Diagnostic,
+    doc: &TextDocumentItem,
+    classes: &BTreeSet<String>,
+    permissions: bool,
+) -> Vec<Fix> {
+    let context = diagnostic.get_context();
+    let span = context
+        .as_ref()
+        .map(|context| (context.source.src.as_str(), context.left, context.right));
+    match (diagnostic, span) {
+        (
+            Diagnostic::Error(PolarError(Validation(SingletonVariable { term }))),
+            Some((src, left, _)),
+        ) => match term.value() {
+            Value::Variable(name) => {
+                let start = position_at(src, left);
+                vec![(
+                    format!("Rename `{0}` to `_{0}`", name),
+                    vec![insert(start, "_")],
+                )]
+            }
+            _ => vec![],
+        },
+
+        (
+            Diagnostic::Warning(PolarWarning(AmbiguousPrecedence { .. })),
+            Some((src, left, right)),
+        ) => {
+            vec![(
+                "Add parentheses".to_owned(),
+                vec![
+                    insert(position_at(src, left), "("),
+                    insert(position_at(src, right), ")"),
+                ],
+            )]
+        }
+
+        (
+            Diagnostic::Warning(PolarWarning(UnknownSpecializer { sym, .. })),
+            Some((src, left, _)),
+        ) => {
+            let range = Range::new(position_at(src, left), position_at(src, left + sym.0.len()));
+            let mut candidates: Vec<_> = classes
+                .iter()
+                .map(|class| (distance(&sym.0, class), class))
+                .filter(|(distance, _)| *distance <= MAX_TYPO_DISTANCE)
+                .collect();
+            candidates.sort();
+            candidates
+                .into_iter()
+                .map(|(_, class)| {
+                    (
+                        format!("Change `{}` to `{}`", sym, class),
+                        vec![TextEdit::new(range, class.clone())],
+                    )
+                })
+                .collect()
+        }
+
+        (Diagnostic::Warning(PolarWarning(MissingAllowRule | MissingHasPermissionRule)), _) => {
+            let text = &doc.text;
+            let mut rule = String::new();
+            if !text.is_empty() && !text.ends_with('\n') {
+                rule.push('\n');
+            }
+            if !text.trim().is_empty() {
+                rule.push('\n');
+            }
+            let missing_has_permission = matches!(
+                diagnostic,
+                Diagnostic::Warning(PolarWarning(MissingHasPermissionRule))
+            );
+            let title = if missing_has_permission || permissions {
+                rule.push_str(HAS_PERMISSION_ALLOW_RULE);
+                "Add an `allow` rule that calls `has_permission`"
+            } else {
+                rule.push_str(ALLOW_RULE);
+                "Add an `allow` rule"
+            };
+            vec![(
+                title.to_owned(),
+                vec![insert(position_at(text, text.len()), &rule)],
+            )]
+        }
+
+        _ => vec![],
+    }
+}
+
+fn insert(position: Position, text: &str) -> TextEdit {
+    TextEdit::new(Range::new(position, position), text.to_owned())
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut previous: Vec<_> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
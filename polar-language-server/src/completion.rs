@@ -0,0 +1,173 @@
+//! Completion of the names used inside resource blocks.
+
+use std::collections::BTreeSet;
+
+use lsp_types::{CompletionItem, CompletionItemKind};
+use polar_core::{
+    kb::KnowledgeBase,
+    resource_block::Declaration,
+    terms::{Term, Value},
+};
+
+/// Classes that every host library registers.
+const BUILTIN_CLASSES: [&str; 6] = [
+    "Boolean",
+    "Dictionary",
+    "Float",
+    "Integer",
+    "List",
+    "String",
+];
+
+/// What the end of a document prefix is inside of.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Context<'a> {
+    /// The type of the enclosing `resource` or `actor` block.
+    pub(crate) block: Option<&'a str>,
+    pub(crate) in_string: bool,
+    pub(crate) in_comment: bool,
+}
+
+impl<'a> Context<'a> {
+    /// Scan `prefix`, the text before the cursor, for unclosed strings, comments and braces.
+    pub(crate) fn new(prefix: &'a str) -> Self {
+        let mut context = Self::default();
+        let mut escaped = false;
+        let mut braces = vec![];
+        for (i, c) in prefix.char_indices() {
+            if context.in_comment {
+                context.in_comment = c != '\n';
+            } else if context.in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => context.in_string = false,
+                    _ => (),
+                }
+            } else {
+                match c {
+                    '"' => context.in_string = true,
+                    '#' => context.in_comment = true,
+                    '{' => braces.push(i),
+                    '}' => {
+                        braces.pop();
+                    }
+                    _ => (),
+                }
+            }
+        }
+        context.block = braces
+            .first()
+            .and_then(|&brace| block_type(&prefix[..brace]));
+        context
+    }
+}
+
+/// The type named by the `resource Type` or `actor Type` at the end of `header`.
+fn block_type(header: &str) -> Option<&str> {
+    let mut words = header.split_whitespace().rev();
+    let name = words.next()?;
+    match words.next()? {
+        "resource" | "actor" => Some(name),
+        _ => None,
+    }
+}
+
+fn block<'a>(
+    kb: &'a KnowledgeBase,
+    resource: &str,
+) -> Option<impl Iterator<Item = (&'a String, &'a Declaration)>> {
+    let (_, declarations) = kb
+        .resource_blocks
+        .declarations()
+        .iter()
+        .find(|(block, _)| block.to_string() == resource)?;
+    Some(
+        declarations
+            .iter()
+            .filter_map(|(name, declaration)| match name.value() {
+                Value::String(name) => Some((name, declaration)),
+                _ => None,
+            }),
+    )
+}
+
+/// The declaration of `name` in the resource block for `resource`.
+pub(crate) fn declaration<'a>(
+    kb: &'a KnowledgeBase,
+    resource: &str,
+    name: &str,
+) -> Option<&'a Declaration> {
+    block(kb, resource)?
+        .find(|(declared, _)| declared.as_str() == name)
+        .map(|(_, declaration)| declaration)
+}
+
+/// The names that can be used in strings in the resource block for `resource`: its own roles,
+/// permissions and relations, and the roles and permissions of the types it's related to.
+pub(crate) fn declaration_items(kb: &KnowledgeBase, resource: &str) -> Vec<CompletionItem> {
+    let own: Vec<_> = match block(kb, resource) {
+        Some(declarations) => declarations.collect(),
+        None => return vec![],
+    };
+    let related: BTreeSet<_> = own
+        .iter()
+        .filter_map(|(_, declaration)| match declaration {
+            Declaration::Relation(related) => Some(related.to_string()),
+            _ => None,
+        })
+        .filter(|related| related != resource)
+        .collect();
+
+    let mut items: Vec<_> = own
+        .into_iter()
+        .map(|(name, declaration)| item(name, declaration, resource))
+        .collect();
+    for related in &related {
+        for (name, declaration) in block(kb, related).into_iter().flatten() {
+            if !matches!(declaration, Declaration::Relation(_)) {
+                items.push(item(name, declaration, related));
+            }
+        }
+    }
+    items.sort_by(|a, b| (&a.label, &a.detail).cmp(&(&b.label, &b.detail)));
+    items
+}
+
+fn item(name: &str, declaration: &Declaration, resource: &str) -> CompletionItem {
+    let kind = match declaration {
+        Declaration::Relation(_) => CompletionItemKind::Field,
+        _ => CompletionItemKind::EnumMember,
+    };
+    CompletionItem {
+        label: name.to_owned(),
+        kind: Some(kind),
+        detail: Some(format!("{} on {}", declaration, resource)),
+        ..Default::default()
+    }
+}
+
+/// The built-in classes, the registered classes and the types with resource blocks.
+pub(crate) fn class_names(kb: &KnowledgeBase) -> BTreeSet<String> {
+    let mut names: BTreeSet<String> = BUILTIN_CLASSES.iter().map(|&name| name.into()).collect();
+    names.extend(kb.registered_class_names().map(|name| name.0.clone()));
+    names.extend(
+        kb.resource_blocks
+            .actors
+            .iter()
+            .chain(&kb.resource_blocks.resources)
+            .map(Term::to_string),
+    );
+    names
+}
+
+pub(crate) fn class_items(kb: &KnowledgeBase) -> Vec<CompletionItem> {
+    class_names(kb)
+        .into_iter()
+        .map(|label| CompletionItem {
+            label,
+            kind: Some(CompletionItemKind::Class),
+            ..Default::default()
+        })
+        .collect()
+}
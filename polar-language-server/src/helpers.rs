@@ -1,7 +1,11 @@
 use std::collections::{BTreeMap, HashSet};
 
 use lsp_types::{Position, PublishDiagnosticsParams, Range, TextDocumentItem, Url};
-use polar_core::diagnostic::Diagnostic;
+use polar_core::{
+    diagnostic::Diagnostic,
+    rules::Rule,
+    terms::{InstanceLiteral, Pattern, Value},
+};
 use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
@@ -11,7 +15,7 @@ extern "C" {
     fn console_log(s: &str);
 }
 
-#[cfg(not(test))]
+#[cfg(all(not(test), target_arch = "wasm32"))]
 pub(crate) fn log(s: &str) {
     #[allow(unused_unsafe)]
     unsafe {
@@ -19,6 +23,12 @@ pub(crate) fn log(s: &str) {
     }
 }
 
+// Stdout is for LSP messages when running natively.
+#[cfg(all(not(test), not(target_arch = "wasm32")))]
+pub(crate) fn log(s: &str) {
+    eprintln!("[pls] {}", s)
+}
+
 #[cfg(test)]
 pub(crate) fn log(_: &str) {}
 
@@ -40,6 +50,62 @@ pub(crate) fn range_from_polar_diagnostic_context(diagnostic: &Diagnostic) -> Ra
         .unwrap_or_default()
 }
 
+/// The position of byte `offset` in `text`, counting columns in UTF-16 code units.
+pub(crate) fn position_at(text: &str, offset: usize) -> Position {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset += 1;
+    }
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = before.matches('\n').count();
+    let character = before[line_start..].encode_utf16().count();
+    Position::new(line as _, character as _)
+}
+
+/// The byte offset of `position` in `text`, the inverse of `position_at`.
+pub(crate) fn offset_at(text: &str, position: Position) -> usize {
+    let line_start = text
+        .split_inclusive('\n')
+        .take(position.line as usize)
+        .map(str::len)
+        .sum::<usize>();
+    let mut character = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if c == '\n' || character >= position.character as usize {
+            return line_start + i;
+        }
+        character += c.len_utf16();
+    }
+    text.len()
+}
+
+/// Whether `name` can be used as a rule name.
+pub(crate) fn is_rule_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// `rule_type` as it would be written, without the `{}` that follows each specializer class.
+pub(crate) fn rule_type_signature(rule_type: &Rule) -> String {
+    let params: Vec<_> = rule_type
+        .params
+        .iter()
+        .map(
+            |param| match param.specializer.as_ref().map(|s| s.value()) {
+                Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, fields })))
+                    if fields.fields.is_empty() =>
+                {
+                    format!("{}: {}", param.parameter, tag)
+                }
+                _ => param.to_string(),
+            },
+        )
+        .collect();
+    format!("type {}({});", rule_type.name, params.join(", "))
+}
+
 pub(crate) fn uri_from_polar_diagnostic_context(diagnostic: &Diagnostic) -> Option<Url> {
     if let Some(context) = diagnostic.get_context() {
         if let Some(filename) = context.source.filename.as_ref() {
@@ -0,0 +1,497 @@
+//! Where rules and resource block declarations are defined and used across the tracked
+//! documents.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use lsp_types::{DocumentSymbol, Location, Position, Range, SymbolKind, Url};
+use polar_core::{
+    parser::{parse_lines, Line},
+    resource_block::Production,
+    rules::Rule,
+    sources::Source,
+    terms::{InstanceLiteral, Operation, Operator, Pattern, Term, Value},
+    visitor::{walk_call, walk_term, Visitor},
+};
+
+use crate::helpers::{position_at, Documents};
+
+/// Something that's defined in one place and used in others.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Item {
+    /// The rules with a name and arity.
+    Rule { name: String, arity: usize },
+    /// A role, permission or relation declared in the resource block for `resource`.
+    Declaration { resource: String, name: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Usage {
+    /// A rule definition, or a declaration in a resource block.
+    Definition,
+    RuleType,
+    /// A rule call or `@table` directive, or a role, permission or relation in a shorthand
+    /// rule.
+    Reference,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Occurrence {
+    pub(crate) item: Item,
+    pub(crate) usage: Usage,
+    /// Where the item is named, not including the quotes around strings.
+    pub(crate) location: Location,
+}
+
+/// The rules and declarations in every tracked document that parses.
+#[derive(Default)]
+pub(crate) struct Index {
+    pub(crate) occurrences: Vec<Occurrence>,
+    /// The outline of each document that parses.
+    pub(crate) symbols: BTreeMap<Url, Vec<DocumentSymbol>>,
+}
+
+/// Relation name -> related type for the resource block of each type.
+type Relations = HashMap<String, HashMap<String, String>>;
+
+impl Index {
+    pub(crate) fn new(documents: &Documents) -> Self {
+        let parsed: Vec<_> = documents
+            .iter()
+            .filter_map(|(uri, doc)| {
+                let lines = parse_lines(Source::new_with_name(uri, &doc.text)).ok()?;
+                Some((uri, doc.text.as_str(), lines))
+            })
+            .collect();
+
+        // Resolve `on "relation"` in shorthand rules to the related type's block.
+        let mut relations = Relations::new();
+        for (_, _, lines) in &parsed {
+            for line in lines {
+                if let Line::ResourceBlock {
+                    resource,
+                    productions,
+                    ..
+                } = line
+                {
+                    relations
+                        .entry(resource.to_string())
+                        .or_default()
+                        .extend(block_relations(productions));
+                }
+            }
+        }
+
+        let mut index = Self::default();
+        let mut tables = vec![];
+        for (uri, text, lines) in parsed {
+            let mut document = Document {
+                uri,
+                text,
+                relations: &relations,
+                occurrences: vec![],
+                tables: vec![],
+            };
+            let symbols = lines
+                .iter()
+                .filter_map(|line| document.line(line))
+                .collect();
+            index.occurrences.append(&mut document.occurrences);
+            tables.append(&mut document.tables);
+            index.symbols.insert(uri.clone(), symbols);
+        }
+
+        // `@table name;` refers to the rules called `name` of every arity.
+        for (name, location) in tables {
+            let arities: BTreeSet<_> = index
+                .occurrences
+                .iter()
+                .filter_map(|occurrence| match &occurrence.item {
+                    Item::Rule { name: rule, arity } if rule == &name => Some(*arity),
+                    _ => None,
+                })
+                .collect();
+            for arity in arities {
+                index.occurrences.push(Occurrence {
+                    item: Item::Rule {
+                        name: name.clone(),
+                        arity,
+                    },
+                    usage: Usage::Reference,
+                    location: location.clone(),
+                });
+            }
+        }
+        index
+    }
+
+    /// The occurrence of an item at `position` in `uri`.
+    pub(crate) fn occurrence_at(&self, uri: &Url, position: Position) -> Option<&Occurrence> {
+        self.occurrences.iter().find(|Occurrence { location, .. }| {
+            &location.uri == uri
+                && location.range.start <= position
+                && position <= location.range.end
+        })
+    }
+
+    /// The item named at `position` in `uri`.
+    pub(crate) fn item_at(&self, uri: &Url, position: Position) -> Option<&Item> {
+        self.occurrence_at(uri, position)
+            .map(|occurrence| &occurrence.item)
+    }
+
+    /// Where `item` occurs with one of `usages`.
+    pub(crate) fn locations(&self, item: &Item, usages: &[Usage]) -> Vec<Location> {
+        self.occurrences
+            .iter()
+            .filter(|occurrence| &occurrence.item == item && usages.contains(&occurrence.usage))
+            .map(|occurrence| occurrence.location.clone())
+            .collect()
+    }
+}
+
+fn block_relations(productions: &[Production]) -> impl Iterator<Item = (String, String)> + '_ {
+    productions
+        .iter()
+        .filter_map(|production| match production {
+            Production::Declaration((name, value)) if name.to_string() == "relations" => {
+                match value.value() {
+                    Value::Dictionary(dict) => Some(dict),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .flat_map(|dict| {
+            dict.fields
+                .iter()
+                .map(|(name, related)| (name.0.clone(), related.to_string()))
+        })
+}
+
+/// The rule calls in a term, with the offset of each. Method calls and constructors are skipped.
+#[derive(Default)]
+struct Calls(Vec<(String, usize, usize)>);
+
+impl Visitor for Calls {
+    fn visit_term(&mut self, term: &Term) {
+        match term.value() {
+            Value::Call(call) => {
+                if let Some(context) = term.parsed_context() {
+                    self.0
+                        .push((call.name.0.clone(), call.args.len(), context.left));
+                }
+                walk_term(self, term);
+            }
+            Value::Expression(Operation {
+                operator: Operator::Dot | Operator::New,
+                args,
+            }) => {
+                for arg in args {
+                    match arg.value() {
+                        Value::Call(call) => walk_call(self, call),
+                        _ => self.visit_term(arg),
+                    }
+                }
+            }
+            _ => walk_term(self, term),
+        }
+    }
+}
+
+#[allow(deprecated)]
+fn symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
+
+/// Collects the occurrences and symbols in one document.
+struct Document<'a> {
+    uri: &'a Url,
+    text: &'a str,
+    relations: &'a Relations,
+    occurrences: Vec<Occurrence>,
+    /// The rule names in `@table` directives, resolved once every document is indexed.
+    tables: Vec<(String, Location)>,
+}
+
+impl Document<'_> {
+    fn range(&self, left: usize, right: usize) -> Range {
+        Range::new(position_at(self.text, left), position_at(self.text, right))
+    }
+
+    /// The offset just after the next `c` from `offset`.
+    fn after(&self, offset: usize, c: char) -> usize {
+        self.text
+            .get(offset..)
+            .and_then(|rest| rest.find(c))
+            .map(|i| offset + i + 1)
+            .unwrap_or(offset)
+    }
+
+    fn push(&mut self, item: Item, usage: Usage, left: usize, right: usize) {
+        let location = Location::new(self.uri.clone(), self.range(left, right));
+        self.occurrences.push(Occurrence {
+            item,
+            usage,
+            location,
+        });
+    }
+
+    /// Record the string literal `term` as an occurrence of a declaration in the block for
+    /// `resource`, returning the span of its contents.
+    fn string(&mut self, term: &Term, resource: &str, usage: Usage) -> Option<(usize, usize)> {
+        let (name, context) = match (term.value(), term.parsed_context()) {
+            (Value::String(name), Some(context)) => (name, context),
+            _ => return None,
+        };
+        let (left, right) = (context.left + 1, context.right - 1);
+        let item = Item::Declaration {
+            resource: resource.to_owned(),
+            name: name.clone(),
+        };
+        self.push(item, usage, left, right);
+        Some((left, right))
+    }
+
+    fn calls(&mut self, term: &Term) {
+        let mut calls = Calls::default();
+        calls.visit_term(term);
+        for (name, arity, left) in calls.0 {
+            if self.text[left..].starts_with(&name) {
+                let right = left + name.len();
+                self.push(Item::Rule { name, arity }, Usage::Reference, left, right);
+            }
+        }
+    }
+
+    fn line(&mut self, line: &Line) -> Option<DocumentSymbol> {
+        match line {
+            Line::Rule(rule) => self.rule(rule, Usage::Definition),
+            Line::RuleType(rule) => self.rule(rule, Usage::RuleType),
+            Line::Query(term) => {
+                self.calls(term);
+                None
+            }
+            Line::Table(term) => {
+                if let (Value::Variable(name), Some(context)) =
+                    (term.value(), term.parsed_context())
+                {
+                    let range = self.range(context.left, context.right);
+                    let location = Location::new(self.uri.clone(), range);
+                    self.tables.push((name.0.clone(), location));
+                }
+                None
+            }
+            Line::ResourceBlock {
+                keyword,
+                resource,
+                productions,
+            } => self.resource_block(keyword.as_ref(), resource, productions),
+            Line::Test {
+                keyword,
+                name,
+                statements,
+            } => self.test(keyword, name, statements),
+        }
+    }
+
+    fn rule(&mut self, rule: &Rule, usage: Usage) -> Option<DocumentSymbol> {
+        let context = rule.parsed_context()?;
+        let name = &rule.name.0;
+        let (left, name_right) = (context.left, context.left + name.len());
+        let item = Item::Rule {
+            name: name.clone(),
+            arity: rule.params.len(),
+        };
+        self.push(item, usage, left, name_right);
+        self.calls(&rule.body);
+        self.declaration_rule(rule);
+
+        let head = self.text[left..context.right]
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let (detail, kind) = match usage {
+            Usage::RuleType => (format!("type {}", head), SymbolKind::Interface),
+            _ => (head, SymbolKind::Function),
+        };
+        let end = rule
+            .body
+            .parsed_context()
+            .map(|body| body.right)
+            .unwrap_or(context.right);
+        Some(symbol(
+            name.clone(),
+            Some(detail),
+            kind,
+            self.range(left, self.after(end, ';')),
+            self.range(left, name_right),
+            None,
+        ))
+    }
+
+    /// Record the string in the head of a `has_role`, `has_permission` or `has_relation` rule
+    /// as a reference to a declaration in the block for the rule's resource specializer.
+    fn declaration_rule(&mut self, rule: &Rule) {
+        if !matches!(
+            rule.name.0.as_str(),
+            "has_role" | "has_permission" | "has_relation"
+        ) {
+            return;
+        }
+        if let [_, name, resource] = &rule.params[..] {
+            if let Some(Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. }))) =
+                resource.specializer.as_ref().map(Term::value)
+            {
+                self.string(&name.parameter, &tag.0, Usage::Reference);
+            }
+        }
+    }
+
+    fn resource_block(
+        &mut self,
+        keyword: Option<&Term>,
+        resource: &Term,
+        productions: &[Production],
+    ) -> Option<DocumentSymbol> {
+        let context = resource.parsed_context()?;
+        let name = resource.to_string();
+        let start = keyword
+            .and_then(Term::parsed_context)
+            .map(|keyword| keyword.right)
+            .unwrap_or(context.left);
+        let left = start + self.text[start..context.right].find(&name)?;
+
+        let mut children = vec![];
+        for production in productions {
+            match production {
+                Production::Declaration((kind, value)) => {
+                    children.extend(self.declarations(&name, &kind.to_string(), value))
+                }
+                Production::ShorthandRule(head, (implier, relation)) => {
+                    self.string(head, &name, Usage::Reference);
+                    let implier_resource = match relation {
+                        Some((_, relation)) => {
+                            self.string(relation, &name, Usage::Reference);
+                            match relation.value() {
+                                Value::String(relation) => self
+                                    .relations
+                                    .get(&name)
+                                    .and_then(|relations| relations.get(relation))
+                                    .cloned(),
+                                _ => None,
+                            }
+                        }
+                        None => Some(name.clone()),
+                    };
+                    if let Some(implier_resource) = implier_resource {
+                        self.string(implier, &implier_resource, Usage::Reference);
+                    }
+                }
+            }
+        }
+
+        Some(symbol(
+            name.clone(),
+            keyword.map(Term::to_string),
+            SymbolKind::Class,
+            self.range(context.left, context.right),
+            self.range(left, left + name.len()),
+            Some(children),
+        ))
+    }
+
+    /// The roles, permissions or relations declared by `kind = value;`.
+    fn declarations(&mut self, resource: &str, kind: &str, value: &Term) -> Vec<DocumentSymbol> {
+        let mut symbols = vec![];
+        match value.value() {
+            Value::List(names) => {
+                let detail = kind.trim_end_matches('s');
+                for term in names {
+                    if let Some((left, right)) = self.string(term, resource, Usage::Definition) {
+                        symbols.push(symbol(
+                            self.text[left..right].to_owned(),
+                            Some(detail.to_owned()),
+                            SymbolKind::EnumMember,
+                            self.range(left - 1, right + 1),
+                            self.range(left, right),
+                            None,
+                        ));
+                    }
+                }
+            }
+            Value::Dictionary(dict) => {
+                for (name, related) in &dict.fields {
+                    let context = match related.parsed_context() {
+                        Some(context) => context,
+                        None => continue,
+                    };
+                    let left = match self.text[..context.left].rfind(name.0.as_str()) {
+                        Some(left) => left,
+                        None => continue,
+                    };
+                    let right = left + name.0.len();
+                    let item = Item::Declaration {
+                        resource: resource.to_owned(),
+                        name: name.0.clone(),
+                    };
+                    self.push(item, Usage::Definition, left, right);
+                    symbols.push(symbol(
+                        name.0.clone(),
+                        Some(related.to_string()),
+                        SymbolKind::Field,
+                        self.range(left, context.right),
+                        self.range(left, right),
+                        None,
+                    ));
+                }
+            }
+            _ => (),
+        }
+        symbols
+    }
+
+    fn test(
+        &mut self,
+        keyword: &Term,
+        name: &Term,
+        statements: &[(Term, Term)],
+    ) -> Option<DocumentSymbol> {
+        for (_, term) in statements {
+            self.calls(term);
+        }
+        let (start, context) = (keyword.parsed_context()?, name.parsed_context()?);
+        let test_name = match name.value() {
+            Value::String(test_name) => test_name.clone(),
+            _ => return None,
+        };
+        let end = statements
+            .last()
+            .and_then(|(_, term)| term.parsed_context())
+            .map(|last| last.right)
+            .unwrap_or(context.right);
+        Some(symbol(
+            test_name,
+            Some(keyword.to_string()),
+            SymbolKind::Method,
+            self.range(start.left, self.after(end, '}')),
+            self.range(context.left, context.right),
+            None,
+        ))
+    }
+}
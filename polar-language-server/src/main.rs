@@ -0,0 +1,16 @@
+//! The Polar language server, speaking LSP over stdio.
+
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    match polar_language_server::stdio::run(stdin.lock(), io::stdout()) {
+        Ok(true) => (),
+        // Exiting without a `shutdown` request is an error.
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("polar-language-server: {}", e);
+            process::exit(1);
+        }
+    }
+}
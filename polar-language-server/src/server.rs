@@ -0,0 +1,2086 @@
+//! The language server, independent of how it talks to the client.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::Split,
+};
+
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidDeleteFiles,
+        DidOpenTextDocument, DidSaveTextDocument, Initialized, Notification,
+    },
+    request::{
+        CodeActionRequest, Completion, DocumentSymbolRequest, FoldingRangeRequest, Formatting,
+        GotoDefinition, HoverRequest, PrepareRenameRequest, References, Rename, Request,
+        SemanticTokensFullRequest,
+    },
+    CodeAction, CodeActionKind, CodeActionOrCommand, CodeActionParams, CompletionItem,
+    CompletionParams, CompletionResponse, DeleteFilesParams, Diagnostic, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidChangeWatchedFilesParams, DidOpenTextDocumentParams,
+    DocumentFormattingParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    FileChangeType, FileDelete, FileEvent, FoldingRange, FoldingRangeParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, Location, MarkupContent, MarkupKind,
+    NumberOrString, Position, PrepareRenameResponse, PublishDiagnosticsParams, Range,
+    ReferenceParams, RenameParams, SemanticTokens, SemanticTokensParams, SemanticTokensResult,
+    TextDocumentItem, TextDocumentPositionParams, TextEdit, Url, VersionedTextDocumentIdentifier,
+    WorkspaceEdit,
+};
+use polar_core::{
+    diagnostic::Diagnostic as PolarDiagnostic, formatter::format_policy, polar::Polar,
+    resource_block::Declaration, sources::Source, terms::Symbol,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    code_action::{quick_fixes, uses_permissions},
+    completion::{class_items, class_names, declaration, declaration_items, Context},
+    helpers::{
+        empty_diagnostics_for_doc, is_rule_name, log, offset_at, position_at,
+        range_from_polar_diagnostic_context, rule_type_signature, unique_extensions,
+        uri_from_polar_diagnostic_context, Diagnostics, Documents, LspEvent,
+    },
+    index::{Index, Item, Usage},
+    syntax::{folding_ranges, semantic_tokens},
+};
+
+/// Where the server sends what it produces outside of request results.
+pub trait Client {
+    fn publish_diagnostics(&self, params: PublishDiagnosticsParams);
+    fn telemetry(&self, event: serde_json::Value);
+}
+
+/// The `params` of a request or notification don't match its method.
+#[derive(Debug)]
+pub struct InvalidParams(pub String);
+
+/// Why a request has no result.
+#[derive(Debug)]
+pub enum RequestError {
+    /// The server doesn't handle the request's method.
+    MethodNotFound,
+    InvalidParams(String),
+}
+
+impl From<InvalidParams> for RequestError {
+    fn from(InvalidParams(msg): InvalidParams) -> Self {
+        Self::InvalidParams(msg)
+    }
+}
+
+/// The state and LSP handlers shared by the wasm and stdio front-ends.
+pub struct Server<C> {
+    documents: Documents,
+    polar: Polar,
+    client: C,
+}
+
+impl<C: Client> Server<C> {
+    pub fn new(client: C) -> Self {
+        Self {
+            documents: BTreeMap::new(),
+            polar: Polar::default(),
+            client,
+        }
+    }
+
+    pub fn client(&self) -> &C {
+        &self.client
+    }
+
+    /// Catch-all handler for notifications sent by the LSP client.
+    ///
+    /// This function receives a notification's `method` and `params` and dispatches to the
+    /// appropriate handler function based on `method`. Notifications with invalid `params` are
+    /// logged and dropped.
+    pub fn on_notification<'de, D: Deserializer<'de>>(&mut self, method: &str, params: D) {
+        log(method);
+
+        if let Err(InvalidParams(e)) = self.dispatch_notification(method, params) {
+            log(&format!("\tinvalid params: {}", e));
+        }
+    }
+
+    fn dispatch_notification<'de, D: Deserializer<'de>>(
+        &mut self,
+        method: &str,
+        params: D,
+    ) -> Result<(), InvalidParams> {
+        match method {
+            DidOpenTextDocument::METHOD => {
+                let DidOpenTextDocumentParams { text_document } = deserialize(params)?;
+
+                let event = LspEvent {
+                    lsp_method: method,
+                    lsp_file_extensions: unique_extensions(&[&text_document.uri]),
+                };
+
+                let diagnostics = self.on_did_open_text_document(text_document);
+                self.send_diagnostics(&diagnostics);
+
+                self.send_telemetry(event, diagnostics);
+            }
+
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = deserialize(params)?;
+
+                // Ensure we receive full -- not incremental -- updates.
+                let change = match &params.content_changes[..] {
+                    [change] if change.range.is_none() => change.clone(),
+                    _ => {
+                        let msg = "expected one change to the full document".to_owned();
+                        return Err(InvalidParams(msg));
+                    }
+                };
+
+                let VersionedTextDocumentIdentifier { uri, version } = params.text_document;
+
+                let event = LspEvent {
+                    lsp_method: method,
+                    lsp_file_extensions: unique_extensions(&[&uri]),
+                };
+
+                let updated_doc = TextDocumentItem::new(uri, "polar".into(), version, change.text);
+                let diagnostics = self.on_did_change_text_document(updated_doc);
+                self.send_diagnostics(&diagnostics);
+
+                self.send_telemetry(event, diagnostics);
+            }
+
+            // This is the type of event we'll receive when a Polar file is deleted, either via the
+            // VS Code UI (right-click delete) or otherwise (e.g., `rm blah.polar` in a terminal).
+            // The event comes from the `deleteWatcher` file watcher in the extension client.
+            DidChangeWatchedFiles::METHOD => {
+                let DidChangeWatchedFilesParams { changes } = deserialize(params)?;
+                let uris: Vec<_> = changes
+                    .into_iter()
+                    .map(|FileEvent { uri, typ }| {
+                        assert_eq!(typ, FileChangeType::Deleted); // We only watch for `Deleted` events.
+                        uri
+                    })
+                    .collect();
+
+                let event = LspEvent {
+                    lsp_method: method,
+                    lsp_file_extensions: unique_extensions(&uris.iter().collect::<Vec<_>>()),
+                };
+
+                let diagnostics = self.on_did_change_watched_files(uris);
+                self.send_diagnostics(&diagnostics);
+
+                self.send_telemetry(event, diagnostics);
+            }
+
+            // This is the type of event we'll receive when *any* file or folder is deleted via the
+            // VS Code UI (right-click delete). These events are triggered by the
+            // `workspace.fileOperations.didDelete.filters[0].glob = '**'` capability we send from
+            // the TS server -> client, which then sends us `didDelete` events for *all files and
+            // folders within the current workspace*. This is how we are notified of directory
+            // deletions that might contain Polar files, since they won't get picked up by the
+            // `deleteWatcher` created in the client for reasons elaborated below.
+            //
+            // We can ignore any Polar file URIs received via this handler since they'll already be
+            // covered by a corresponding `DidChangeWatchedFiles` event emitted by the
+            // `deleteWatcher` file watcher in the extension client that watches for any
+            // `**/*.polar` files deleted in the current workspace.
+            //
+            // In this handler we only care about *non-Polar* URIs, which we treat as potential
+            // deletions of directories containing Polar files since those won't get picked up by
+            // the `deleteWatcher` due to [a limitation of VS Code's file watching
+            // capabilities][0].
+            //
+            // [0]: https://github.com/microsoft/vscode/issues/60813
+            DidDeleteFiles::METHOD => {
+                let DeleteFilesParams { files } = deserialize(params)?;
+                let mut uris = vec![];
+                for FileDelete { uri } in files {
+                    match Url::parse(&uri) {
+                        Ok(uri) => uris.push(uri),
+                        Err(e) => log(&format!("\tfailed to parse URI: {}", e)),
+                    }
+                }
+
+                let event = LspEvent {
+                    lsp_method: method,
+                    lsp_file_extensions: unique_extensions(&uris.iter().collect::<Vec<_>>()),
+                };
+
+                if let Some(diagnostics) = self.on_did_delete_files(uris) {
+                    self.send_diagnostics(&diagnostics);
+                    self.send_telemetry(event, diagnostics);
+                }
+            }
+
+            // We don't care when a document is saved -- we already have the updated state thanks
+            // to `DidChangeTextDocument`.
+            DidSaveTextDocument::METHOD => (),
+            // We don't care when a document is closed -- we care about all Polar files in a
+            // workspace folder regardless of which ones remain open.
+            DidCloseTextDocument::METHOD => (),
+            // Nothing to do when we receive the `Initialized` notification.
+            Initialized::METHOD => (),
+
+            _ => log("unexpected notification"),
+        }
+        Ok(())
+    }
+
+    /// Catch-all handler for requests sent by the LSP client.
+    ///
+    /// This function receives a request's `method` and `params` and returns the `result` of the
+    /// appropriate handler function, or `null` if there's nothing to respond with.
+    pub fn on_request<'de, D: Deserializer<'de>>(
+        &self,
+        method: &str,
+        params: D,
+    ) -> Result<serde_json::Value, RequestError> {
+        log(method);
+
+        let result = match method {
+            Formatting::METHOD => {
+                let DocumentFormattingParams { text_document, .. } = deserialize(params)?;
+                self.on_formatting(&text_document.uri)
+                    .map(|edits| serde_json::to_value(edits).unwrap())
+            }
+
+            GotoDefinition::METHOD => {
+                let GotoDefinitionParams {
+                    text_document_position_params,
+                    ..
+                } = deserialize(params)?;
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = text_document_position_params;
+                let locations = self.on_goto_definition(&text_document.uri, position);
+                Some(serde_json::to_value(GotoDefinitionResponse::Array(locations)).unwrap())
+            }
+
+            References::METHOD => {
+                let ReferenceParams {
+                    text_document_position,
+                    context,
+                    ..
+                } = deserialize(params)?;
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = text_document_position;
+                let locations =
+                    self.on_references(&text_document.uri, position, context.include_declaration);
+                Some(serde_json::to_value(locations).unwrap())
+            }
+
+            DocumentSymbolRequest::METHOD => {
+                let DocumentSymbolParams { text_document, .. } = deserialize(params)?;
+                self.on_document_symbol(&text_document.uri).map(|symbols| {
+                    serde_json::to_value(DocumentSymbolResponse::Nested(symbols)).unwrap()
+                })
+            }
+
+            Completion::METHOD => {
+                let CompletionParams {
+                    text_document_position,
+                    ..
+                } = deserialize(params)?;
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = text_document_position;
+                self.on_completion(&text_document.uri, position)
+                    .map(|items| serde_json::to_value(CompletionResponse::Array(items)).unwrap())
+            }
+
+            HoverRequest::METHOD => {
+                let HoverParams {
+                    text_document_position_params,
+                    ..
+                } = deserialize(params)?;
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = text_document_position_params;
+                self.on_hover(&text_document.uri, position)
+                    .map(|hover| serde_json::to_value(hover).unwrap())
+            }
+
+            PrepareRenameRequest::METHOD => {
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = deserialize(params)?;
+                self.on_prepare_rename(&text_document.uri, position)
+                    .map(|range| serde_json::to_value(PrepareRenameResponse::Range(range)).unwrap())
+            }
+
+            Rename::METHOD => {
+                let RenameParams {
+                    text_document_position,
+                    new_name,
+                    ..
+                } = deserialize(params)?;
+                let TextDocumentPositionParams {
+                    text_document,
+                    position,
+                } = text_document_position;
+                self.on_rename(&text_document.uri, position, &new_name)
+                    .map(|edit| serde_json::to_value(edit).unwrap())
+            }
+
+            CodeActionRequest::METHOD => {
+                let CodeActionParams {
+                    text_document,
+                    range,
+                    context,
+                    ..
+                } = deserialize(params)?;
+                let actions = self.on_code_action(&text_document.uri, range, &context.diagnostics);
+                Some(serde_json::to_value(actions).unwrap())
+            }
+
+            SemanticTokensFullRequest::METHOD => {
+                let SemanticTokensParams { text_document, .. } = deserialize(params)?;
+                self.on_semantic_tokens(&text_document.uri).map(|tokens| {
+                    serde_json::to_value(SemanticTokensResult::Tokens(tokens)).unwrap()
+                })
+            }
+
+            FoldingRangeRequest::METHOD => {
+                let FoldingRangeParams { text_document, .. } = deserialize(params)?;
+                self.on_folding_range(&text_document.uri)
+                    .map(|ranges| serde_json::to_value(ranges).unwrap())
+            }
+
+            _ => {
+                log("unexpected request");
+                return Err(RequestError::MethodNotFound);
+            }
+        };
+        Ok(result.unwrap_or_default())
+    }
+}
+
+/// Individual LSP notification handlers.
+impl<C: Client> Server<C> {
+    fn on_did_open_text_document(&mut self, doc: TextDocumentItem) -> Diagnostics {
+        log(&format!("\topening: {}", doc.uri));
+        if self.upsert_document(doc).is_some() {
+            log("\t\treopened tracked doc");
+        }
+        self.reload_kb()
+    }
+
+    fn on_did_change_text_document(&mut self, doc: TextDocumentItem) -> Diagnostics {
+        let uri = doc.uri.clone();
+        if self.upsert_document(doc).is_none() {
+            log(&format!("\tupdated untracked doc: {}", uri));
+        }
+        self.reload_kb()
+    }
+
+    // This is (currently) only used to handle deletions of Polar *files*. `DidChangeWatchedFiles`
+    // events come from the `deleteWatcher` filesystem watcher in the extension client. Due to [a
+    // limitation of VS Code's filesystem watcher][0], we don't receive deletion events for Polar
+    // files nested inside of a deleted directory. See corresponding comments on `DidDeleteFiles`
+    // and `DidChangeWatchedFiles` in `Server::on_notification`.
+    //
+    // [0]: https://github.com/microsoft/vscode/issues/60813
+    fn on_did_change_watched_files(&mut self, uris: Vec<Url>) -> Diagnostics {
+        let mut diagnostics = Diagnostics::new();
+
+        for uri in uris {
+            log(&format!("\tdeleting: {}", uri));
+
+            // If this returns `None`, `uri` was already removed from the local set of tracked
+            // documents. An easy way to encounter this is to right-click delete a Polar file via
+            // the VS Code UI, which races the `DidDeleteFiles` and `DidChangeWatchedFiles` events.
+            if let Some(removed) = self.remove_document(&uri) {
+                let (_, empty_diagnostics) = empty_diagnostics_for_doc((&uri, &removed));
+                if diagnostics.insert(uri, empty_diagnostics).is_some() {
+                    log("\t\tduplicate URIs in event payload");
+                }
+            } else {
+                log("\t\tcannot delete untracked doc");
+            }
+        }
+
+        diagnostics.append(&mut self.reload_kb());
+        diagnostics
+    }
+
+    // Returns `None` if no Polar files were deleted.
+    fn on_did_delete_files(&mut self, uris: Vec<Url>) -> Option<Diagnostics> {
+        let mut diagnostics = Diagnostics::new();
+
+        for uri in uris {
+            // If `removed` is empty, `uri` wasn't a directory containing tracked Polar files or
+            // `uri` itself was a Polar file that was already removed via `DidChangeWatchedFiles`.
+            let removed = self.remove_documents_in_dir(&uri);
+            if !removed.is_empty() {
+                log(&format!("\tdeleting: {}", uri));
+
+                for (uri, params) in removed {
+                    log(&format!("\t\tdeleted: {}", uri));
+
+                    // NOTE(gj): fairly sure this will never be true.
+                    if diagnostics.insert(uri, params).is_some() {
+                        log("\t\t\tmultiple deletions of same doc");
+                    }
+                }
+            }
+        }
+
+        if diagnostics.is_empty() {
+            None
+        } else {
+            diagnostics.append(&mut self.reload_kb());
+            Some(diagnostics)
+        }
+    }
+}
+
+/// Individual LSP request handlers.
+impl<C: Client> Server<C> {
+    // Returns `None` if the document isn't tracked or doesn't parse.
+    fn on_formatting(&self, uri: &Url) -> Option<Vec<TextEdit>> {
+        let doc = self.documents.get(uri)?;
+        let formatted = match format_policy(Source::new_with_name(uri, &doc.text)) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                log(&format!("\tcannot format {}: {}", uri, e));
+                return None;
+            }
+        };
+        if formatted == doc.text {
+            return Some(vec![]);
+        }
+
+        // Replace the whole document.
+        let range = Range::new(Position::new(0, 0), position_at(&doc.text, doc.text.len()));
+        Some(vec![TextEdit::new(range, formatted)])
+    }
+
+    // Goes from a rule call to every rule with the same name and arity, and from a role,
+    // permission or relation in a shorthand rule to its declaration.
+    fn on_goto_definition(&self, uri: &Url, position: Position) -> Vec<Location> {
+        let index = Index::new(&self.documents);
+        index
+            .item_at(uri, position)
+            .map(|item| index.locations(item, &[Usage::Definition]))
+            .unwrap_or_default()
+    }
+
+    fn on_references(
+        &self,
+        uri: &Url,
+        position: Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        let index = Index::new(&self.documents);
+        let usages: &[Usage] = if include_declaration {
+            &[Usage::Definition, Usage::RuleType, Usage::Reference]
+        } else {
+            &[Usage::Reference]
+        };
+        index
+            .item_at(uri, position)
+            .map(|item| index.locations(item, usages))
+            .unwrap_or_default()
+    }
+
+    // Returns `None` if the document isn't tracked or doesn't parse.
+    fn on_document_symbol(&self, uri: &Url) -> Option<Vec<DocumentSymbol>> {
+        Index::new(&self.documents).symbols.remove(uri)
+    }
+
+    // Inside a resource block, offers the declared names in strings and class names elsewhere.
+    // Returns `None` outside resource blocks.
+    fn on_completion(&self, uri: &Url, position: Position) -> Option<Vec<CompletionItem>> {
+        let doc = self.documents.get(uri)?;
+        let context = Context::new(&doc.text[..offset_at(&doc.text, position)]);
+        let resource = context.block.filter(|_| !context.in_comment)?;
+        // The line being edited probably doesn't parse yet.
+        let polar = self.load_scratch(Some((uri, position.line)));
+        let kb = polar.kb.read().unwrap();
+        if context.in_string {
+            Some(declaration_items(&kb, resource))
+        } else {
+            Some(class_items(&kb))
+        }
+    }
+
+    // Returns the range of the name to rename, or `None` if there's nothing renameable there.
+    fn on_prepare_rename(&self, uri: &Url, position: Position) -> Option<Range> {
+        let index = Index::new(&self.documents);
+        let occurrence = index.occurrence_at(uri, position)?;
+        Some(occurrence.location.range)
+    }
+
+    // Renames a rule, or a role, permission or relation, everywhere it occurs. Returns `None` if
+    // there's nothing to rename or `new_name` isn't a valid name for it.
+    fn on_rename(&self, uri: &Url, position: Position, new_name: &str) -> Option<WorkspaceEdit> {
+        let index = Index::new(&self.documents);
+        let item = index.item_at(uri, position)?;
+        let valid = match item {
+            Item::Rule { .. } => is_rule_name(new_name),
+            Item::Declaration { resource, name } => {
+                let polar = self.load_scratch(None);
+                let kb = polar.kb.read().unwrap();
+                match declaration(&kb, resource, name) {
+                    // Relations are named by symbols in `relations = {...}`.
+                    Some(Declaration::Relation(_)) => is_rule_name(new_name),
+                    _ => !new_name.is_empty() && !new_name.contains(['"', '\\', '\n']),
+                }
+            }
+        };
+        if !valid {
+            log(&format!("\tinvalid name for {:?}: {}", item, new_name));
+            return None;
+        }
+
+        let usages = [Usage::Definition, Usage::RuleType, Usage::Reference];
+        let mut changes = HashMap::<_, Vec<_>>::new();
+        for Location { uri, range } in index.locations(item, &usages) {
+            changes
+                .entry(uri)
+                .or_default()
+                .push(TextEdit::new(range, new_name.to_owned()));
+        }
+        Some(WorkspaceEdit::new(changes))
+    }
+
+    // Offers quick fixes for the diagnostics in `range`, including the ones that aren't
+    // published because they might depend on app data. Policy-wide diagnostics are only fixed
+    // when the client includes them in `diagnostics`.
+    fn on_code_action(
+        &self,
+        uri: &Url,
+        range: Range,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeActionOrCommand> {
+        let doc = match self.documents.get(uri) {
+            Some(doc) => doc,
+            None => return vec![],
+        };
+        let polar = Polar::default();
+        let polar_diagnostics = polar.diagnostic_load(self.documents_to_polar_sources());
+        let (classes, permissions) = {
+            let kb = polar.kb.read().unwrap();
+            (class_names(&kb), uses_permissions(&kb))
+        };
+
+        let mut actions = vec![];
+        for diagnostic in &polar_diagnostics {
+            let code = Some(NumberOrString::String(diagnostic.kind()));
+            let diagnostic_range = range_from_polar_diagnostic_context(diagnostic);
+            let applies = match diagnostic.get_context() {
+                Some(context) => {
+                    context.source.filename.as_deref() == Some(uri.as_str())
+                        && diagnostic_range.start <= range.end
+                        && range.start <= diagnostic_range.end
+                }
+                None => diagnostics.iter().any(|published| published.code == code),
+            };
+            if !applies {
+                continue;
+            }
+
+            let fixed: Vec<_> = diagnostics
+                .iter()
+                .filter(|published| published.code == code && published.range == diagnostic_range)
+                .cloned()
+                .collect();
+            for (title, edits) in quick_fixes(diagnostic, doc, &classes, permissions) {
+                actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    kind: Some(CodeActionKind::QUICKFIX),
+                    diagnostics: Some(fixed.clone()).filter(|fixed| !fixed.is_empty()),
+                    edit: Some(WorkspaceEdit::new(HashMap::from([(uri.clone(), edits)]))),
+                    ..Default::default()
+                }));
+            }
+        }
+        actions
+    }
+
+    // Returns `None` if the document isn't tracked. Falls back to the tokens alone where the
+    // document doesn't parse.
+    fn on_semantic_tokens(&self, uri: &Url) -> Option<SemanticTokens> {
+        let doc = self.documents.get(uri)?;
+        let classes = class_names(&self.polar.kb.read().unwrap());
+        Some(SemanticTokens {
+            result_id: None,
+            data: semantic_tokens(&doc.text, &classes),
+        })
+    }
+
+    // Folds multi-line rules, resource blocks and tests, and runs of comments. Returns `None` if
+    // the document isn't tracked.
+    fn on_folding_range(&self, uri: &Url) -> Option<Vec<FoldingRange>> {
+        let doc = self.documents.get(uri)?;
+        let symbols = Index::new(&self.documents)
+            .symbols
+            .remove(uri)
+            .unwrap_or_default();
+        Some(folding_ranges(&doc.text, &symbols))
+    }
+
+    // Shows the rule types for a rule name, and the kind of a declared name in a resource block.
+    fn on_hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let index = Index::new(&self.documents);
+        let occurrence = index.occurrence_at(uri, position)?;
+        let polar = self.load_scratch(None);
+        let kb = polar.kb.read().unwrap();
+        let value = match &occurrence.item {
+            Item::Rule { name, arity } => {
+                let rule_types: Vec<_> = kb
+                    .get_rule_types(&Symbol::new(name))?
+                    .iter()
+                    .filter(|rule_type| rule_type.params.len() == *arity)
+                    .map(rule_type_signature)
+                    .collect();
+                if rule_types.is_empty() {
+                    return None;
+                }
+                format!("```polar\n{}\n```", rule_types.join("\n"))
+            }
+            Item::Declaration { resource, name } => match declaration(&kb, resource, name)? {
+                Declaration::Relation(related) => format!(
+                    "`\"{}\"` is a relation from `{}` to `{}`",
+                    name, resource, related
+                ),
+                declaration => format!("`\"{}\"` is a {} on `{}`", name, declaration, resource),
+            },
+        };
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(occurrence.location.range),
+        })
+    }
+}
+
+/// Helper methods.
+impl<C: Client> Server<C> {
+    fn upsert_document(&mut self, doc: TextDocumentItem) -> Option<TextDocumentItem> {
+        self.documents.insert(doc.uri.clone(), doc)
+    }
+
+    fn remove_document(&mut self, uri: &Url) -> Option<TextDocumentItem> {
+        self.documents.remove(uri)
+    }
+
+    /// Remove tracked docs inside `dir`.
+    fn remove_documents_in_dir(&mut self, dir: &Url) -> Diagnostics {
+        let (in_dir, not_in_dir): (Documents, Documents) =
+            self.documents.clone().into_iter().partition(|(uri, _)| {
+                // Zip pair of `Option<Split<char>>`s into `Option<(Split<char>, Split<char>)>`.
+                let maybe_segments = dir.path_segments().zip(uri.path_segments());
+                // Compare paths (`Split<char>`) by zipping them together and comparing pairwise.
+                let compare_paths = |(l, r): (Split<_>, Split<_>)| l.zip(r).all(|(l, r)| l == r);
+                // If all path segments match b/w dir & uri, uri is in dir and should be removed.
+                maybe_segments.map_or(false, compare_paths)
+            });
+        // Replace tracked docs w/ docs that aren't in the removed dir.
+        self.documents = not_in_dir;
+        in_dir.iter().map(empty_diagnostics_for_doc).collect()
+    }
+
+    fn send_diagnostics(&self, diagnostics: &Diagnostics) {
+        for params in diagnostics.values() {
+            self.client.publish_diagnostics(params.clone());
+        }
+    }
+
+    fn send_telemetry(&self, lsp_event: LspEvent, diagnostics: Diagnostics) {
+        use polar_core::parser::{parse_lines, Line};
+
+        #[derive(Default, Serialize)]
+        struct PolicyStats {
+            inline_queries: usize,
+            longhand_rules: usize,
+            polar_chars: usize,
+            polar_files: usize,
+            rule_types: usize,
+            total_rules: usize,
+        }
+
+        #[derive(Default, Serialize)]
+        struct ResourceBlockStats {
+            resource_blocks: usize,
+            actors: usize,
+            resources: usize,
+            declarations: usize,
+            roles: usize,
+            permissions: usize,
+            relations: usize,
+            shorthand_rules: usize,
+            cross_resource_shorthand_rules: usize,
+        }
+
+        #[derive(Default, Serialize)]
+        struct TelemetryEvent<'a> {
+            diagnostics: Vec<Diagnostic>,
+            lsp_event: LspEvent<'a>,
+            policy_stats: PolicyStats,
+            resource_block_stats: ResourceBlockStats,
+        }
+
+        let polar_files = diagnostics.len();
+        let diagnostics = diagnostics.into_values().flat_map(|ps| ps.diagnostics);
+
+        let polar_chars = self
+            .documents
+            .values()
+            .map(|d| d.text.chars().count())
+            .sum();
+
+        let mut event = TelemetryEvent {
+            diagnostics: diagnostics.collect(),
+            lsp_event,
+            policy_stats: PolicyStats {
+                polar_chars,
+                polar_files,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let lines = self.documents.values();
+        let lines = lines
+            .filter_map(|d| parse_lines(Source::new_with_name(&d.uri, &d.text)).ok())
+            .flatten();
+        for line in lines {
+            match line {
+                Line::Query(_) => event.policy_stats.inline_queries += 1,
+                Line::ResourceBlock {
+                    keyword,
+                    productions,
+                    resource,
+                } => {
+                    use polar_core::resource_block::{
+                        block_type_from_keyword, validate_parsed_declaration, BlockType,
+                        ParsedDeclaration, Production,
+                    };
+
+                    event.resource_block_stats.resource_blocks += 1;
+
+                    match block_type_from_keyword(keyword, &resource) {
+                        Ok(BlockType::Actor) => event.resource_block_stats.actors += 1,
+                        Ok(BlockType::Resource) => event.resource_block_stats.resources += 1,
+                        _ => (),
+                    }
+
+                    for production in productions {
+                        match production {
+                            Production::Declaration(declaration) => {
+                                event.resource_block_stats.declarations += 1;
+
+                                if let Ok(declaration) = validate_parsed_declaration(declaration) {
+                                    match declaration {
+                                        ParsedDeclaration::Permissions(permissions) => {
+                                            event.resource_block_stats.permissions +=
+                                                permissions.as_list().unwrap().len();
+                                        }
+                                        ParsedDeclaration::Relations(relations) => {
+                                            event.resource_block_stats.relations +=
+                                                relations.as_dict().unwrap().fields.len();
+                                        }
+                                        ParsedDeclaration::Roles(roles) => {
+                                            event.resource_block_stats.roles +=
+                                                roles.as_list().unwrap().len();
+                                        }
+                                    }
+                                }
+                            }
+                            Production::ShorthandRule(_, (_, relation)) => {
+                                event.resource_block_stats.shorthand_rules += 1;
+                                event.policy_stats.total_rules += 1;
+
+                                if relation.is_some() {
+                                    event.resource_block_stats.cross_resource_shorthand_rules += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                Line::RuleType(_) => event.policy_stats.rule_types += 1,
+                Line::Table(_) | Line::Test { .. } => (),
+                Line::Rule(_) => {
+                    event.policy_stats.longhand_rules += 1;
+                    event.policy_stats.total_rules += 1;
+                }
+            }
+        }
+
+        self.client.telemetry(serde_json::to_value(event).unwrap());
+    }
+
+    fn empty_diagnostics_for_all_documents(&self) -> Diagnostics {
+        self.documents
+            .iter()
+            .map(empty_diagnostics_for_doc)
+            .collect()
+    }
+
+    fn document_from_polar_diagnostic_context(
+        &self,
+        diagnostic: &PolarDiagnostic,
+    ) -> Option<TextDocumentItem> {
+        uri_from_polar_diagnostic_context(diagnostic).and_then(|uri| {
+            if let Some(document) = self.documents.get(&uri) {
+                Some(document.clone())
+            } else {
+                let tracked_docs = self.documents.keys().map(ToString::to_string);
+                let tracked_docs = tracked_docs.collect::<Vec<_>>().join(", ");
+                log(&format!(
+                    "untracked doc: {}\n\tTracked: {}\n\tDiagnostic: {}",
+                    uri, tracked_docs, diagnostic
+                ));
+                None
+            }
+        })
+    }
+
+    /// Create one or more `Diagnostic`s from `polar_core::diagnostic::Diagnostic`s, filtering out
+    /// "ignored" diagnostics.
+    fn diagnostics_from_polar_diagnostic(
+        &self,
+        diagnostic: PolarDiagnostic,
+    ) -> Vec<(TextDocumentItem, Diagnostic)> {
+        use polar_core::error::{ErrorKind::Validation, ValidationError::*};
+        use polar_core::warning::ValidationWarning::UnknownSpecializer;
+
+        // Ignore diagnostics that depend on app data.
+        match &diagnostic {
+            PolarDiagnostic::Error(e) => match e.0 {
+                Validation(UnregisteredClass { .. }) | Validation(SingletonVariable { .. }) => {
+                    return vec![];
+                }
+                _ => (),
+            },
+            PolarDiagnostic::Warning(w) if matches!(w.0, UnknownSpecializer { .. }) => {
+                return vec![];
+            }
+            _ => (),
+        }
+
+        // NOTE(gj): We stringify the error / warning variant instead of the full `PolarError` /
+        // `PolarWarning` because we don't want source context as part of the error message.
+        let (message, severity) = match &diagnostic {
+            PolarDiagnostic::Error(e) => (e.0.to_string(), DiagnosticSeverity::Error),
+            PolarDiagnostic::Warning(w) => (w.0.to_string(), DiagnosticSeverity::Warning),
+        };
+
+        // If the diagnostic applies to a single doc, use it; otherwise, default to emitting a
+        // duplicate diagnostic for all docs.
+        let docs = self
+            .document_from_polar_diagnostic_context(&diagnostic)
+            .map_or_else(
+                || self.documents.values().cloned().collect(),
+                |doc| vec![doc],
+            );
+
+        docs.into_iter()
+            .map(|doc| {
+                let diagnostic = Diagnostic {
+                    code: Some(NumberOrString::String(diagnostic.kind())),
+                    range: range_from_polar_diagnostic_context(&diagnostic),
+                    severity: Some(severity),
+                    source: Some("Polar Language Server".to_owned()),
+                    message: message.clone(),
+                    ..Default::default()
+                };
+                (doc, diagnostic)
+            })
+            .collect()
+    }
+
+    /// Turn tracked documents into a set of Polar `Source` structs for `Polar::diagnostic_load`.
+    fn documents_to_polar_sources(&self) -> Vec<Source> {
+        self.documents
+            .values()
+            .map(|doc| Source::new_with_name(&doc.uri, &doc.text))
+            .collect()
+    }
+
+    /// Load the tracked documents into a new `Polar`, leaving out `line` of `uri` if given.
+    fn load_scratch(&self, skip: Option<(&Url, u32)>) -> Polar {
+        let sources = self
+            .documents
+            .values()
+            .map(|doc| match skip {
+                Some((uri, line)) if *uri == doc.uri => {
+                    let lines: Vec<_> = doc
+                        .text
+                        .split('\n')
+                        .enumerate()
+                        .map(|(i, text)| if i == line as usize { "" } else { text })
+                        .collect();
+                    Source::new_with_name(&doc.uri, lines.join("\n"))
+                }
+                _ => Source::new_with_name(&doc.uri, &doc.text),
+            })
+            .collect();
+        let polar = Polar::default();
+        polar.diagnostic_load(sources);
+        polar
+    }
+
+    fn load_documents(&self) -> Vec<PolarDiagnostic> {
+        self.polar.clear_rules();
+        self.polar
+            .diagnostic_load(self.documents_to_polar_sources())
+    }
+
+    fn get_diagnostics(&self) -> Diagnostics {
+        self.load_documents()
+            .into_iter()
+            .flat_map(|diagnostic| self.diagnostics_from_polar_diagnostic(diagnostic))
+            .fold(Diagnostics::new(), |mut acc, (doc, diagnostic)| {
+                let params = acc.entry(doc.uri.clone()).or_insert_with(|| {
+                    PublishDiagnosticsParams::new(doc.uri, vec![], Some(doc.version))
+                });
+                params.diagnostics.push(diagnostic);
+                acc
+            })
+    }
+
+    /// Reloads tracked documents into the `KnowledgeBase`, translates `polar-core` diagnostics
+    /// into `polar-language-server` diagnostics, and returns a set of diagnostics for publishing.
+    ///
+    /// NOTE(gj): we republish 'empty' diagnostics for all documents in order to purge stale
+    /// diagnostics.
+    fn reload_kb(&self) -> Diagnostics {
+        let mut diagnostics = self.empty_diagnostics_for_all_documents();
+        diagnostics.extend(self.get_diagnostics());
+        diagnostics
+    }
+}
+
+fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    params: D,
+) -> Result<T, InvalidParams> {
+    T::deserialize(params).map_err(|e| InvalidParams(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{FoldingRangeKind, SymbolKind};
+
+    use super::*;
+    use crate::syntax::legend;
+
+    struct NoopClient;
+
+    impl Client for NoopClient {
+        fn publish_diagnostics(&self, _: PublishDiagnosticsParams) {}
+        fn telemetry(&self, _: serde_json::Value) {}
+    }
+
+    #[track_caller]
+    fn new_pls() -> Server<NoopClient> {
+        let pls = Server::new(NoopClient);
+        assert!(pls.reload_kb().is_empty());
+        pls
+    }
+
+    #[track_caller]
+    fn polar_uri(path: &str) -> Url {
+        Url::parse(&format!("file:///{}.polar", path)).unwrap()
+    }
+
+    #[track_caller]
+    fn polar_doc(path: &str, contents: String) -> TextDocumentItem {
+        TextDocumentItem::new(polar_uri(path), "polar".to_owned(), 0, contents)
+    }
+
+    #[track_caller]
+    fn doc_with_no_errors(path: &str) -> TextDocumentItem {
+        let file_name = path.split('/').last().unwrap();
+        polar_doc(path, format!("{}();", file_name))
+    }
+
+    #[track_caller]
+    fn doc_with_missing_semicolon(path: &str) -> TextDocumentItem {
+        let file_name = path.split('/').last().unwrap();
+        polar_doc(path, format!("{}()", file_name))
+    }
+
+    #[track_caller]
+    fn add_doc_with_no_errors(pls: &mut Server<NoopClient>, path: &str) -> TextDocumentItem {
+        let doc = doc_with_no_errors(path);
+        assert!(pls.upsert_document(doc.clone()).is_none());
+        doc
+    }
+
+    #[track_caller]
+    fn add_doc_with_missing_semicolon(
+        pls: &mut Server<NoopClient>,
+        path: &str,
+    ) -> TextDocumentItem {
+        let doc = doc_with_missing_semicolon(path);
+        assert!(pls.upsert_document(doc.clone()).is_none());
+        doc
+    }
+
+    #[track_caller]
+    fn update_text(doc: TextDocumentItem, text: &str) -> TextDocumentItem {
+        TextDocumentItem::new(doc.uri, doc.language_id, doc.version + 1, text.into())
+    }
+
+    #[track_caller]
+    fn assert_missing_semicolon_error(diagnostics: &Diagnostics, docs: Vec<&TextDocumentItem>) {
+        for doc in docs {
+            let params = diagnostics.get(&doc.uri).unwrap();
+            assert_eq!(params.uri, doc.uri);
+            assert_eq!(params.version.unwrap(), doc.version);
+            assert_eq!(params.diagnostics.len(), 1, "{}", doc.uri);
+            let diagnostic = params.diagnostics.get(0).unwrap();
+            assert_eq!(
+                diagnostic.message,
+                "hit the end of the file unexpectedly. Did you forget a semi-colon"
+            );
+        }
+    }
+
+    #[track_caller]
+    fn assert_no_errors(diagnostics: &Diagnostics, docs: Vec<&TextDocumentItem>) {
+        for doc in docs {
+            let params = diagnostics.get(&doc.uri).unwrap();
+            assert_eq!(params.uri, doc.uri);
+            assert_eq!(params.version.unwrap(), doc.version);
+            assert!(params.diagnostics.is_empty(), "{:?}", params.diagnostics);
+        }
+    }
+
+    #[track_caller]
+    fn assert_missing_allow_rule_warning(diagnostics: &Diagnostics, docs: Vec<&TextDocumentItem>) {
+        for doc in docs {
+            let params = diagnostics.get(&doc.uri).unwrap();
+            assert_eq!(params.uri, doc.uri);
+            assert_eq!(params.version.unwrap(), doc.version);
+            assert_eq!(params.diagnostics.len(), 1, "{}", doc.uri);
+            let diagnostic = params.diagnostics.get(0).unwrap();
+            let expected = diagnostic
+                .message
+                .starts_with("Your policy does not contain an allow rule");
+            assert!(expected, "{}", diagnostic.message);
+        }
+    }
+
+    #[allow(clippy::many_single_char_names)]
+    #[test]
+    fn test_on_did_open_text_document() {
+        let mut pls = new_pls();
+
+        let a = doc_with_no_errors("apple");
+        let b = doc_with_no_errors("banana");
+        let c = doc_with_missing_semicolon("canteloupe");
+        let d = doc_with_missing_semicolon("date");
+        let e = doc_with_no_errors("elderberry");
+
+        // Load a single doc w/ no errors.
+        let diagnostics = pls.on_did_open_text_document(a.clone());
+        assert_eq!(diagnostics.len(), 1);
+        assert_missing_allow_rule_warning(&diagnostics, vec![&a]);
+
+        // Load a second doc w/ no errors.
+        let diagnostics = pls.on_did_open_text_document(b.clone());
+        assert_eq!(diagnostics.len(), 2);
+        assert_missing_allow_rule_warning(&diagnostics, vec![&a, &b]);
+
+        // Load a third doc w/ errors.
+        let diagnostics = pls.on_did_open_text_document(c.clone());
+        assert_eq!(diagnostics.len(), 3);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(&diagnostics, vec![&a, &b]);
+        assert_missing_semicolon_error(&diagnostics, vec![&c]);
+
+        // Load a fourth doc w/ errors.
+        let diagnostics = pls.on_did_open_text_document(d.clone());
+        assert_eq!(diagnostics.len(), 4);
+        assert_no_errors(&diagnostics, vec![&a, &b]);
+        assert_missing_semicolon_error(&diagnostics, vec![&c, &d]);
+
+        // Load a fifth doc w/ no errors.
+        let diagnostics = pls.on_did_open_text_document(e.clone());
+        assert_eq!(diagnostics.len(), 5);
+        assert_no_errors(&diagnostics, vec![&a, &b, &e]);
+        assert_missing_semicolon_error(&diagnostics, vec![&c, &d]);
+    }
+
+    #[test]
+    fn test_on_did_change_text_document() {
+        let mut pls = new_pls();
+
+        // 'Change' untracked doc w/ no errors.
+        let a0 = doc_with_no_errors("apple");
+        let diagnostics0 = pls.on_did_change_text_document(a0.clone());
+        assert_eq!(diagnostics0.len(), 1);
+        assert_missing_allow_rule_warning(&diagnostics0, vec![&a0]);
+
+        // Change tracked doc w/o introducing an error.
+        let a1 = update_text(a0, "pie();");
+        let diagnostics1 = pls.on_did_change_text_document(a1.clone());
+        assert_eq!(diagnostics1.len(), 1);
+        assert_missing_allow_rule_warning(&diagnostics1, vec![&a1]);
+
+        // Change tracked doc, introducing an error.
+        let a2 = update_text(a1, "pie()");
+        let diagnostics2 = pls.on_did_change_text_document(a2.clone());
+        assert_eq!(diagnostics2.len(), 1);
+        assert_missing_semicolon_error(&diagnostics2, vec![&a2]);
+
+        // 'Change' untracked doc, introducing a second error.
+        let b3 = doc_with_missing_semicolon("banana");
+        let diagnostics3 = pls.on_did_change_text_document(b3.clone());
+        assert_eq!(diagnostics3.len(), 2);
+        assert_missing_semicolon_error(&diagnostics3, vec![&a2, &b3]);
+
+        // Change tracked doc, fixing an error.
+        let a4 = update_text(a2, "pie();");
+        let diagnostics4 = pls.on_did_change_text_document(a4.clone());
+        assert_eq!(diagnostics4.len(), 2);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(&diagnostics4, vec![&a4]);
+        assert_missing_semicolon_error(&diagnostics4, vec![&b3]);
+
+        // Change tracked doc, fixing the last error.
+        let b5 = update_text(b3, "split();");
+        let diagnostics5 = pls.on_did_change_text_document(b5.clone());
+        assert_eq!(diagnostics5.len(), 2);
+        assert_missing_allow_rule_warning(&diagnostics5, vec![&a4, &b5]);
+    }
+
+    #[test]
+    fn test_on_did_delete_files() {
+        let mut pls = new_pls();
+
+        // Empty event has no effect.
+        let diagnostics0 = pls.on_did_change_watched_files(vec![]);
+        assert!(diagnostics0.is_empty());
+        assert!(pls.documents.is_empty());
+
+        // Deleting untracked doc has no effect.
+        let events1 = vec![polar_uri("apple")];
+        let diagnostics1 = pls.on_did_change_watched_files(events1);
+        assert!(diagnostics1.is_empty());
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/o error.
+        let a2 = add_doc_with_no_errors(&mut pls, "apple");
+        let events2 = vec![a2.uri.clone()];
+        let diagnostics2 = pls.on_did_change_watched_files(events2);
+        assert_eq!(diagnostics2.len(), 1);
+        assert_no_errors(&diagnostics2, vec![&a2]);
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/ error.
+        let a3 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let events3 = vec![a3.uri.clone()];
+        let diagnostics3 = pls.on_did_change_watched_files(events3);
+        assert_eq!(diagnostics3.len(), 1);
+        assert_no_errors(&diagnostics3, vec![&a3]);
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/o error; doc w/o error remains.
+        let a4 = add_doc_with_no_errors(&mut pls, "apple");
+        let b4 = add_doc_with_no_errors(&mut pls, "banana");
+        let events4 = vec![a4.uri.clone()];
+        let diagnostics4 = pls.on_did_change_watched_files(events4);
+        assert_eq!(diagnostics4.len(), 2);
+        assert_no_errors(&diagnostics4, vec![&a4]);
+        assert_missing_allow_rule_warning(&diagnostics4, vec![&b4]);
+        assert!(pls.remove_document(&b4.uri).is_some());
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/ error; doc w/o error remains.
+        let a5 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let b5 = add_doc_with_no_errors(&mut pls, "banana");
+        let events5 = vec![a5.uri.clone()];
+        let diagnostics5 = pls.on_did_change_watched_files(events5);
+        assert_eq!(diagnostics5.len(), 2);
+        assert_no_errors(&diagnostics4, vec![&a5]);
+        assert_missing_allow_rule_warning(&diagnostics5, vec![&b5]);
+        assert!(pls.remove_document(&b5.uri).is_some());
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/o error; doc w/ error remains.
+        let a6 = add_doc_with_no_errors(&mut pls, "apple");
+        let b6 = add_doc_with_missing_semicolon(&mut pls, "banana");
+        let events6 = vec![a6.uri.clone()];
+        let diagnostics6 = pls.on_did_change_watched_files(events6);
+        assert_eq!(diagnostics6.len(), 2);
+        assert_no_errors(&diagnostics6, vec![&a6]);
+        assert_missing_semicolon_error(&diagnostics6, vec![&b6]);
+        assert!(pls.remove_document(&b6.uri).is_some());
+        assert!(pls.documents.is_empty());
+
+        // Deleting tracked doc w/ error; doc w/ error remains.
+        let a7 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let b7 = add_doc_with_missing_semicolon(&mut pls, "banana");
+        let events7 = vec![a7.uri.clone()];
+        let diagnostics7 = pls.on_did_change_watched_files(events7);
+        assert_eq!(diagnostics7.len(), 2);
+        assert_no_errors(&diagnostics7, vec![&a7]);
+        assert_missing_semicolon_error(&diagnostics7, vec![&b7]);
+        assert!(pls.remove_document(&b7.uri).is_some());
+        assert!(pls.documents.is_empty());
+
+        // Deleting multiple docs at once.
+        let a8 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let b8 = add_doc_with_missing_semicolon(&mut pls, "banana");
+        let c8 = add_doc_with_missing_semicolon(&mut pls, "canteloupe");
+        let d8 = add_doc_with_no_errors(&mut pls, "date");
+        let e8 = add_doc_with_no_errors(&mut pls, "elderberry");
+        let f8 = add_doc_with_no_errors(&mut pls, "fig");
+        let events8 = vec![
+            a8.uri.clone(),
+            b8.uri.clone(),
+            d8.uri.clone(),
+            e8.uri.clone(),
+        ];
+        let diagnostics8 = pls.on_did_change_watched_files(events8);
+        assert_eq!(diagnostics8.len(), 6);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(&diagnostics8, vec![&a8, &b8, &d8, &e8, &f8]);
+        assert_missing_semicolon_error(&diagnostics8, vec![&c8]);
+        assert!(pls.remove_document(&c8.uri).is_some());
+        assert!(pls.remove_document(&f8.uri).is_some());
+        assert!(pls.documents.is_empty());
+
+        // Deleting directories containing Polar files.
+        let a9 = add_doc_with_missing_semicolon(&mut pls, "apple");
+        let b9 = add_doc_with_no_errors(&mut pls, "a/b/banana");
+        let ca9a = add_doc_with_no_errors(&mut pls, "a/b/c/ca/calabash");
+        let ca9b = add_doc_with_no_errors(&mut pls, "a/b/c/ca/canteloupe");
+        let ch9 = add_doc_with_no_errors(&mut pls, "a/b/c/ch/cherry");
+        let d9 = add_doc_with_no_errors(&mut pls, "a/b/c/d/date");
+        let g9a = add_doc_with_no_errors(&mut pls, "a/b/c/d/e/f/g/grape");
+        let g9b = add_doc_with_no_errors(&mut pls, "a/b/c/d/e/f/g/grapefruit");
+
+        // Deleting a deeply nested directory.
+        let d_dir = Url::parse(d9.uri.as_str().strip_suffix("/date.polar").unwrap()).unwrap();
+        let events9a = vec![d_dir];
+        assert_eq!(pls.documents.len(), 8);
+        let diagnostics9a = pls.on_did_delete_files(events9a).unwrap();
+        assert_eq!(diagnostics9a.len(), 8);
+        assert_missing_semicolon_error(&diagnostics9a, vec![&a9]);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(
+            &diagnostics9a,
+            vec![&b9, &ca9a, &ca9b, &ch9, &d9, &g9a, &g9b],
+        );
+        assert_eq!(pls.documents.len(), 5);
+
+        // Deleting multiple directories at once.
+        let ca_dir = ca9a.uri.as_str().strip_suffix("/calabash.polar");
+        let ca_dir = Url::parse(ca_dir.unwrap()).unwrap();
+        let ch_dir = ch9.uri.as_str().strip_suffix("/cherry.polar");
+        let ch_dir = Url::parse(ch_dir.unwrap()).unwrap();
+        let events9b = vec![ca_dir, ch_dir];
+        assert_eq!(pls.documents.len(), 5);
+        let diagnostics9b = pls.on_did_delete_files(events9b).unwrap();
+        assert_eq!(diagnostics9b.len(), 5);
+        assert_missing_semicolon_error(&diagnostics9b, vec![&a9]);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(&diagnostics9b, vec![&b9, &ca9a, &ca9b, &ch9]);
+        assert_eq!(pls.documents.len(), 2);
+
+        // Deleting a top-level directory.
+        let a_dir = b9.uri.as_str().strip_suffix("/b/banana.polar");
+        let a_dir = Url::parse(a_dir.unwrap()).unwrap();
+        let events9c = vec![a_dir];
+        assert_eq!(pls.documents.len(), 2);
+        let diagnostics9c = pls.on_did_delete_files(events9c).unwrap();
+        assert_eq!(diagnostics9c.len(), 2);
+        assert_missing_semicolon_error(&diagnostics9c, vec![&a9]);
+        // No 'missing allow rule' warnings b/c the parse error halts validation before reaching
+        // that check.
+        assert_no_errors(&diagnostics9c, vec![&b9]);
+        assert_eq!(pls.documents.len(), 1);
+        assert!(pls.remove_document(&a9.uri).is_some());
+        assert!(pls.documents.is_empty());
+    }
+
+    #[test]
+    fn test_ignoring_errors_dependent_on_app_data() {
+        let mut pls = new_pls();
+
+        let resource_block_unregistered_constant = r#"
+            allow(_, _, _) if has_permission(_, _, _);
+            has_permission(_: Actor, _: String, _: Resource);
+            actor User {}
+        "#;
+        let doc = polar_doc("whatever", resource_block_unregistered_constant.to_owned());
+        pls.upsert_document(doc.clone());
+
+        // `load_documents()` API performs no filtering.
+        let polar_diagnostics = pls.load_documents();
+        assert_eq!(polar_diagnostics.len(), 2, "{:?}", polar_diagnostics);
+        let unknown_specializer = polar_diagnostics.get(0).unwrap();
+        let expected_message = "Unknown specializer String at line 3, column 41 of file file:///whatever.polar:\n\t003:             has_permission(_: Actor, _: String, _: Resource);\n\t                                             ^\n";
+        assert_eq!(unknown_specializer.to_string(), expected_message);
+        let unregistered_class = polar_diagnostics.get(1).unwrap();
+        assert!(unregistered_class
+            .to_string()
+            .starts_with("Unregistered class: User"));
+
+        // `reload_kb()` API filters out diagnostics dependent on app data.
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc.uri).unwrap();
+        assert_eq!(params.uri, doc.uri);
+        assert_eq!(params.version.unwrap(), doc.version);
+        assert!(params.diagnostics.is_empty(), "{:?}", params.diagnostics);
+
+        let rule_type_unregistered_constant = r#"
+            allow(_, _, _);
+            type f(a: A);
+            f(_: B);
+        "#;
+        let doc = polar_doc("whatever", rule_type_unregistered_constant.to_owned());
+        pls.upsert_document(doc.clone());
+
+        // `load_documents()` API performs no filtering.
+        let polar_diagnostics = pls.load_documents();
+        assert_eq!(polar_diagnostics.len(), 2, "{:?}", polar_diagnostics);
+        let unknown_specializer = polar_diagnostics.get(0).unwrap();
+        let expected_message = "Unknown specializer B at line 4, column 18 of file file:///whatever.polar:\n\t004:             f(_: B);\n\t                      ^\n";
+        assert_eq!(unknown_specializer.to_string(), expected_message);
+        let unregistered_constant = polar_diagnostics.get(1).unwrap();
+        let expected_message = "Unregistered class: A";
+        assert_eq!(unregistered_constant.to_string(), expected_message);
+
+        // `reload_kb()` API filters out diagnostics dependent on app data.
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc.uri).unwrap();
+        assert_eq!(params.uri, doc.uri);
+        assert_eq!(params.version.unwrap(), doc.version);
+        assert!(params.diagnostics.is_empty(), "{:?}", params.diagnostics);
+
+        let singleton_variable = "allow(a, _, _);".to_owned();
+        let doc = polar_doc("whatever", singleton_variable);
+        pls.upsert_document(doc.clone());
+
+        // `load_documents()` API performs no filtering.
+        let polar_diagnostics = pls.load_documents();
+        assert_eq!(polar_diagnostics.len(), 1, "{:?}", polar_diagnostics);
+        let singleton_variable = polar_diagnostics.get(0).unwrap();
+        assert!(singleton_variable
+            .to_string()
+            .starts_with("Singleton variable a is unused or undefined; try renaming to _a or _"));
+
+        // `reload_kb()` API filters out diagnostics dependent on app data.
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc.uri).unwrap();
+        assert_eq!(params.uri, doc.uri);
+        assert_eq!(params.version.unwrap(), doc.version);
+        assert!(params.diagnostics.is_empty(), "{:?}", params.diagnostics);
+    }
+
+    #[test]
+    fn test_diagnostic_range() {
+        let mut pls = new_pls();
+        let debug = "debug";
+        let doc = polar_doc("whatever", debug.to_owned());
+        pls.upsert_document(doc.clone());
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc.uri).unwrap();
+        assert_eq!(params.uri, doc.uri);
+        assert_eq!(params.version.unwrap(), doc.version);
+        assert_eq!(params.diagnostics.len(), 1);
+        let diagnostic = params.diagnostics.get(0).unwrap();
+        assert_eq!(
+            diagnostic.message,
+            "debug is a reserved Polar word and cannot be used here"
+        );
+        assert_eq!(diagnostic.range.start, Position::new(0, 0));
+        assert_eq!(diagnostic.range.end, Position::new(0, 5));
+    }
+
+    #[test]
+    fn test_resource_block_errors() {
+        let mut pls = new_pls();
+
+        let policy = r#"
+            resource Repo {
+              "read" if "write";
+            }
+        "#;
+        let doc = polar_doc("whatever", policy.to_owned());
+        pls.upsert_document(doc.clone());
+
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc.uri).unwrap();
+        assert_eq!(params.uri, doc.uri);
+        assert_eq!(params.version.unwrap(), doc.version);
+        assert_eq!(params.diagnostics.len(), 1, "{:?}", params.diagnostics);
+        let undeclared_term = &params.diagnostics.get(0).unwrap().message;
+        assert!(
+            undeclared_term.starts_with("Undeclared term \"read\""),
+            "{}",
+            undeclared_term
+        );
+    }
+
+    #[test]
+    fn test_file_loading_errors() {
+        let mut pls = new_pls();
+
+        let doc1 = polar_doc("one", "".to_owned());
+        let doc2 = polar_doc("two", "".to_owned());
+        pls.upsert_document(doc1.clone());
+        pls.upsert_document(doc2.clone());
+
+        let diagnostics = pls.reload_kb();
+        let params = diagnostics.get(&doc1.uri).unwrap();
+        assert_eq!(params.uri, doc1.uri);
+        assert_eq!(params.version.unwrap(), doc1.version);
+        assert!(params.diagnostics.is_empty(), "{:?}", params.diagnostics);
+
+        let params = diagnostics.get(&doc2.uri).unwrap();
+        assert_eq!(params.uri, doc2.uri);
+        assert_eq!(params.version.unwrap(), doc2.version);
+        assert_eq!(params.diagnostics.len(), 1, "{:?}", params.diagnostics);
+        let undeclared_term = &params.diagnostics.get(0).unwrap().message;
+        assert_eq!(
+            undeclared_term,
+            &format!("Problem loading file: A file with the same contents as {} named {} has already been loaded.", doc2.uri, doc1.uri),
+            "{}",
+            undeclared_term
+        );
+    }
+
+    #[test]
+    fn test_formatting() {
+        let mut pls = new_pls();
+
+        let formatted = polar_doc("formatted", "f(x) if\n  x = 1;\n".to_owned());
+        let unformatted = polar_doc("unformatted", "f(x)if x=1;\n\n# done".to_owned());
+        let unparseable = polar_doc("unparseable", "f(x) if".to_owned());
+        for doc in [&formatted, &unformatted, &unparseable] {
+            pls.upsert_document(doc.clone());
+        }
+
+        assert_eq!(pls.on_formatting(&formatted.uri), Some(vec![]));
+        assert_eq!(pls.on_formatting(&unparseable.uri), None);
+        assert_eq!(pls.on_formatting(&polar_uri("untracked")), None);
+
+        let edits = pls.on_formatting(&unformatted.uri).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.start, Position::new(0, 0));
+        assert_eq!(edits[0].range.end, Position::new(2, 6));
+        assert_eq!(edits[0].new_text, "f(x) if\n  x = 1;\n\n# done\n");
+
+        // Columns count UTF-16 code units.
+        let emoji = polar_doc("emoji", "f(x)if x=1; # 💯".to_owned());
+        pls.upsert_document(emoji.clone());
+        let edits = pls.on_formatting(&emoji.uri).unwrap();
+        assert_eq!(edits[0].range.end, Position::new(0, 16));
+    }
+
+    #[track_caller]
+    fn location(path: &str, line: u32, start: u32, end: u32) -> Location {
+        let range = Range::new(Position::new(line, start), Position::new(line, end));
+        Location::new(polar_uri(path), range)
+    }
+
+    #[test]
+    fn test_goto_definition_and_references() {
+        let mut pls = new_pls();
+        let rules = polar_doc(
+            "rules",
+            "allow(actor, action, resource) if\n  f(actor) and f(action, resource);\n\nf(x) if g(x);\nf(_x, _y);\n".to_owned(),
+        );
+        let other = polar_doc("other", "f(1);\n?= f(2);\n".to_owned());
+        pls.upsert_document(rules.clone());
+        pls.upsert_document(other.clone());
+
+        assert_eq!(
+            pls.on_goto_definition(&rules.uri, Position::new(1, 2)),
+            vec![location("other", 0, 0, 1), location("rules", 3, 0, 1)]
+        );
+        assert_eq!(
+            pls.on_goto_definition(&rules.uri, Position::new(1, 15)),
+            vec![location("rules", 4, 0, 1)]
+        );
+        assert!(pls
+            .on_goto_definition(&rules.uri, Position::new(1, 8))
+            .is_empty());
+
+        assert_eq!(
+            pls.on_references(&rules.uri, Position::new(3, 0), false),
+            vec![location("other", 1, 3, 4), location("rules", 1, 2, 3)]
+        );
+        assert_eq!(
+            pls.on_references(&other.uri, Position::new(0, 1), true),
+            vec![
+                location("other", 0, 0, 1),
+                location("other", 1, 3, 4),
+                location("rules", 1, 2, 3),
+                location("rules", 3, 0, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_block_navigation() {
+        let mut pls = new_pls();
+        let blocks = polar_doc(
+            "blocks",
+            r#"resource Org {
+  roles = ["member"];
+}
+resource Repo {
+  roles = ["reader"];
+  relations = {parent: Org};
+  "reader" if "member" on "parent";
+}
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(blocks.clone());
+
+        assert_eq!(
+            pls.on_goto_definition(&blocks.uri, Position::new(6, 16)),
+            vec![location("blocks", 1, 12, 18)]
+        );
+        assert_eq!(
+            pls.on_goto_definition(&blocks.uri, Position::new(6, 28)),
+            vec![location("blocks", 5, 15, 21)]
+        );
+        assert_eq!(
+            pls.on_references(&blocks.uri, Position::new(4, 13), false),
+            vec![location("blocks", 6, 3, 9)]
+        );
+    }
+
+    #[test]
+    fn test_document_symbols() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "symbols",
+            "f(x) if x = 1;\ntype g(x: String);\nresource Repo {\n  roles = [\"reader\"];\n  relations = {parent: Org};\n}\ntest \"f\" {\n  assert f(1);\n}\n"
+                .to_owned(),
+        );
+        let unparseable = polar_doc("unparseable", "f(x) if".to_owned());
+        pls.upsert_document(doc.clone());
+        pls.upsert_document(unparseable.clone());
+
+        let symbols = pls.on_document_symbol(&doc.uri).unwrap();
+        let outline: Vec<_> = symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.kind, symbol.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            outline,
+            vec![
+                ("f", SymbolKind::Function, Some("f(x)")),
+                ("g", SymbolKind::Interface, Some("type g(x: String)")),
+                ("Repo", SymbolKind::Class, Some("resource")),
+                ("f", SymbolKind::Method, Some("test")),
+            ]
+        );
+        let children: Vec<_> = symbols[2]
+            .children
+            .iter()
+            .flatten()
+            .map(|symbol| (symbol.name.as_str(), symbol.detail.as_deref()))
+            .collect();
+        assert_eq!(
+            children,
+            vec![("reader", Some("role")), ("parent", Some("Org"))]
+        );
+
+        assert!(pls.on_document_symbol(&unparseable.uri).is_none());
+        assert!(pls.on_document_symbol(&polar_uri("untracked")).is_none());
+    }
+
+    fn completions(
+        pls: &Server<NoopClient>,
+        doc: &TextDocumentItem,
+        line: u32,
+        character: u32,
+    ) -> Option<Vec<(String, Option<String>)>> {
+        let items = pls.on_completion(&doc.uri, Position::new(line, character))?;
+        Some(
+            items
+                .into_iter()
+                .map(|item| (item.label, item.detail))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "completion",
+            r#"resource Org {
+  roles = ["member"];
+}
+resource Repo {
+  roles = ["reader"];
+  relations = {parent: Org};
+  "reader" if "
+}
+# resource Issue {
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+
+        assert_eq!(
+            completions(&pls, &doc, 6, 15).unwrap(),
+            vec![
+                ("member".to_owned(), Some("role on Org".to_owned())),
+                ("parent".to_owned(), Some("relation on Repo".to_owned())),
+                ("reader".to_owned(), Some("role on Repo".to_owned())),
+            ]
+        );
+        assert!(completions(&pls, &doc, 3, 0).is_none());
+
+        // Block types are only known if the rest of the document parses.
+        let text = doc
+            .text
+            .replace("if \"\n", "if \"member\" on \"parent\";\n");
+        let doc = update_text(doc, &text);
+        pls.upsert_document(doc.clone());
+        let classes: Vec<_> = completions(&pls, &doc, 5, 23)
+            .unwrap()
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(
+            classes,
+            vec![
+                "Boolean",
+                "Dictionary",
+                "Float",
+                "Integer",
+                "List",
+                "Org",
+                "Repo",
+                "String"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hover() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "hover",
+            r#"type f(x: Integer);
+f(1);
+resource Org {
+  roles = ["member"];
+}
+resource Repo {
+  roles = ["reader"];
+  relations = {parent: Org};
+  "reader" if "member" on "parent";
+}
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+        let hover = |line, character| {
+            pls.on_hover(&doc.uri, Position::new(line, character))
+                .map(|hover| match hover.contents {
+                    HoverContents::Markup(markup) => markup.value,
+                    _ => panic!("expected markup"),
+                })
+        };
+
+        assert_eq!(hover(1, 0).unwrap(), "```polar\ntype f(x: Integer);\n```");
+        assert_eq!(hover(8, 4).unwrap(), "`\"reader\"` is a role on `Repo`");
+        assert_eq!(hover(8, 16).unwrap(), "`\"member\"` is a role on `Org`");
+        assert_eq!(
+            hover(8, 28).unwrap(),
+            "`\"parent\"` is a relation from `Repo` to `Org`"
+        );
+        assert!(hover(1, 2).is_none());
+    }
+
+    fn renamed(edit: WorkspaceEdit) -> Vec<(String, Range, String)> {
+        let mut renamed: Vec<_> = edit
+            .changes
+            .unwrap()
+            .into_iter()
+            .flat_map(|(uri, edits)| {
+                let path = uri.path().trim_start_matches('/').to_owned();
+                edits
+                    .into_iter()
+                    .map(move |edit| (path.clone(), edit.range, edit.new_text))
+            })
+            .collect();
+        renamed.sort_by_key(|(path, range, _)| (path.clone(), range.start));
+        renamed
+    }
+
+    #[test]
+    fn test_rename() {
+        let mut pls = new_pls();
+        let blocks = polar_doc(
+            "blocks",
+            r#"resource Org {
+  roles = ["member"];
+}
+resource Repo {
+  roles = ["reader"];
+  relations = {parent: Org};
+  "reader" if "member" on "parent";
+}
+"#
+            .to_owned(),
+        );
+        let rules = polar_doc(
+            "rules",
+            r#"has_role(user: User, "member", org: Org) if org.owner = user;
+allow(actor, action, resource) if f(actor) and has_role(actor, action, resource);
+f(_x);
+@table f;
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(blocks.clone());
+        pls.upsert_document(rules.clone());
+        let range =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+
+        assert_eq!(
+            pls.on_prepare_rename(&blocks.uri, Position::new(6, 17)),
+            Some(range(6, 15, 21))
+        );
+        assert!(pls
+            .on_prepare_rename(&blocks.uri, Position::new(6, 11))
+            .is_none());
+
+        let edit = pls
+            .on_rename(&blocks.uri, Position::new(1, 13), "maintainer")
+            .unwrap();
+        let maintainer = "maintainer".to_owned();
+        assert_eq!(
+            renamed(edit),
+            vec![
+                (
+                    "blocks.polar".to_owned(),
+                    range(1, 12, 18),
+                    maintainer.clone()
+                ),
+                (
+                    "blocks.polar".to_owned(),
+                    range(6, 15, 21),
+                    maintainer.clone()
+                ),
+                ("rules.polar".to_owned(), range(0, 22, 28), maintainer),
+            ]
+        );
+
+        let edit = pls
+            .on_rename(&rules.uri, Position::new(2, 0), "is_member")
+            .unwrap();
+        let is_member = "is_member".to_owned();
+        assert_eq!(
+            renamed(edit),
+            vec![
+                (
+                    "rules.polar".to_owned(),
+                    range(1, 34, 35),
+                    is_member.clone()
+                ),
+                ("rules.polar".to_owned(), range(2, 0, 1), is_member.clone()),
+                ("rules.polar".to_owned(), range(3, 7, 8), is_member),
+            ]
+        );
+
+        let edit = pls
+            .on_rename(&blocks.uri, Position::new(5, 16), "owner")
+            .unwrap();
+        let owner = "owner".to_owned();
+        assert_eq!(
+            renamed(edit),
+            vec![
+                ("blocks.polar".to_owned(), range(5, 15, 21), owner.clone()),
+                ("blocks.polar".to_owned(), range(6, 27, 33), owner),
+            ]
+        );
+        assert!(pls
+            .on_rename(&blocks.uri, Position::new(6, 28), "org parent")
+            .is_none());
+
+        assert!(pls
+            .on_rename(&rules.uri, Position::new(2, 0), "is member")
+            .is_none());
+        assert!(pls
+            .on_rename(&blocks.uri, Position::new(1, 13), "mem\"ber")
+            .is_none());
+    }
+
+    fn quick_fixes(actions: Vec<CodeActionOrCommand>) -> Vec<(String, Vec<TextEdit>)> {
+        actions
+            .into_iter()
+            .map(|action| match action {
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title,
+                    edit: Some(edit),
+                    ..
+                }) => (
+                    title,
+                    edit.changes.unwrap().into_values().flatten().collect(),
+                ),
+                _ => panic!("expected a code action with an edit"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_code_actions() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "fixes",
+            r#"actor User {}
+allow(actor: Usr, action, resource) if has_permission(actor, action, resource);
+f(x, y) if x = 1 or x = 2 and x = 3;
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+        let range =
+            |line, start, end| Range::new(Position::new(line, start), Position::new(line, end));
+        let insert = |line, character, text: &str| {
+            TextEdit::new(range(line, character, character), text.to_owned())
+        };
+
+        assert_eq!(
+            quick_fixes(pls.on_code_action(&doc.uri, range(1, 14, 14), &[])),
+            vec![(
+                "Change `Usr` to `User`".to_owned(),
+                vec![TextEdit::new(range(1, 13, 16), "User".to_owned())]
+            )]
+        );
+        assert_eq!(
+            quick_fixes(pls.on_code_action(&doc.uri, range(2, 0, 36), &[])),
+            vec![
+                ("Rename `y` to `_y`".to_owned(), vec![insert(2, 5, "_")]),
+                (
+                    "Add parentheses".to_owned(),
+                    vec![insert(2, 20, "("), insert(2, 35, ")")]
+                ),
+            ]
+        );
+        assert!(pls.on_code_action(&doc.uri, range(0, 0, 0), &[]).is_empty());
+    }
+
+    #[test]
+    fn test_missing_allow_rule_code_action() {
+        let mut pls = new_pls();
+        let doc = polar_doc("no_allow", "f(1);".to_owned());
+        pls.upsert_document(doc.clone());
+        let diagnostics = pls.reload_kb().remove(&doc.uri).unwrap().diagnostics;
+        assert_eq!(diagnostics.len(), 1);
+
+        let actions = pls.on_code_action(&doc.uri, diagnostics[0].range, &diagnostics);
+        match &actions[..] {
+            [CodeActionOrCommand::CodeAction(action)] => {
+                assert_eq!(action.diagnostics.as_ref(), Some(&diagnostics))
+            }
+            _ => panic!("expected one code action"),
+        }
+        let end = Position::new(0, 5);
+        assert_eq!(
+            quick_fixes(actions),
+            vec![(
+                "Add an `allow` rule".to_owned(),
+                vec![TextEdit::new(
+                    Range::new(end, end),
+                    "\n\nallow(_actor, _action, _resource) if\n  # Add the conditions for allowing.\n  false;\n".to_owned()
+                )]
+            )]
+        );
+        assert!(pls
+            .on_code_action(&doc.uri, diagnostics[0].range, &[])
+            .is_empty());
+
+        // Policies that use permissions get an `allow` rule that checks them.
+        let doc = polar_doc(
+            "no_allow",
+            "has_permission(_actor, \"read\", _resource);\n".to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+        let diagnostics = pls.reload_kb().remove(&doc.uri).unwrap().diagnostics;
+        let actions = pls.on_code_action(&doc.uri, diagnostics[0].range, &diagnostics);
+        let end = Position::new(1, 0);
+        assert_eq!(
+            quick_fixes(actions),
+            vec![(
+                "Add an `allow` rule that calls `has_permission`".to_owned(),
+                vec![TextEdit::new(
+                    Range::new(end, end),
+                    "\nallow(actor, action, resource) if\n  has_permission(actor, action, resource);\n".to_owned()
+                )]
+            )]
+        );
+    }
+
+    /// The semantic tokens of `doc` as `type:text`, with declarations marked by `*`.
+    fn highlighted(pls: &Server<NoopClient>, doc: &TextDocumentItem) -> Vec<String> {
+        let legend = legend();
+        let lines: Vec<_> = doc.text.lines().collect();
+        let mut position = Position::new(0, 0);
+        pls.on_semantic_tokens(&doc.uri)
+            .unwrap()
+            .data
+            .into_iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    position = Position::new(position.line + token.delta_line, 0);
+                }
+                position.character += token.delta_start;
+                let start = position.character as usize;
+                let text = &lines[position.line as usize][start..start + token.length as usize];
+                let kind = legend.token_types[token.token_type as usize].as_str();
+                let declaration = if token.token_modifiers_bitset == 1 {
+                    "*"
+                } else {
+                    ""
+                };
+                format!("{}{}:{}", declaration, kind, text)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "highlight",
+            r#"# Members of
+# an org.
+actor User {}
+resource Org {
+  roles = ["member"];
+  relations = {parent: Org};
+  "member" if "member" on "parent";
+}
+has_role(user: User, _: String, org: Org) if
+  org.members.contains(user) and
+  forall(x in user.orgs, x matches Org{}) and cut;
+"#
+            .to_owned(),
+        );
+        pls.on_did_open_text_document(doc.clone());
+        let tokens = highlighted(&pls, &doc);
+        assert_eq!(
+            tokens,
+            vec![
+                "comment:# Members of",
+                "comment:# an org.",
+                "keyword:actor",
+                "*class:User",
+                "keyword:resource",
+                "*class:Org",
+                "keyword:roles",
+                "operator:=",
+                "string:\"member\"",
+                "keyword:relations",
+                "operator:=",
+                "property:parent",
+                "class:Org",
+                "string:\"member\"",
+                "keyword:if",
+                "string:\"member\"",
+                "keyword:on",
+                "string:\"parent\"",
+                "*function:has_role",
+                "*parameter:user",
+                "class:User",
+                "*parameter:_",
+                "class:String",
+                "*parameter:org",
+                "class:Org",
+                "keyword:if",
+                "parameter:org",
+                "property:members",
+                "method:contains",
+                "parameter:user",
+                "keyword:and",
+                "keyword:forall",
+                "variable:x",
+                "keyword:in",
+                "parameter:user",
+                "property:orgs",
+                "variable:x",
+                "keyword:matches",
+                "class:Org",
+                "keyword:and",
+                "keyword:cut",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_with_invalid_character() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "invalid",
+            "f(x) if x = 1;\ng($) if \"#\";\n# comment\n".to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+        assert_eq!(
+            highlighted(&pls, &doc),
+            vec![
+                "function:f",
+                "variable:x",
+                "keyword:if",
+                "variable:x",
+                "operator:=",
+                "number:1",
+                "function:g",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let mut pls = new_pls();
+        let doc = polar_doc(
+            "fold",
+            r#"# A comment
+# on two lines.
+resource Org {
+  roles = ["member"];
+}
+f(x) if
+  x = 1;
+# One line.
+g(1);
+"#
+            .to_owned(),
+        );
+        pls.upsert_document(doc.clone());
+        let ranges: Vec<_> = pls
+            .on_folding_range(&doc.uri)
+            .unwrap()
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (0, 1, Some(FoldingRangeKind::Comment)),
+                (2, 3, None),
+                (5, 6, None),
+            ]
+        );
+    }
+}
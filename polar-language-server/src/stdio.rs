@@ -0,0 +1,379 @@
+//! JSON-RPC over stdio, for editors other than VS Code.
+//!
+//! The VS Code extension opens every Polar file in the workspace itself. Other clients only open
+//! the files being edited, so this front-end opens the rest when the client is initialized.
+
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use lsp_types::{
+    notification::{DidOpenTextDocument, Exit, Initialized, Notification, PublishDiagnostics},
+    request::{Initialize, Request, Shutdown},
+    CodeActionProviderCapability, CompletionOptions, DidOpenTextDocumentParams,
+    FoldingRangeProviderCapability, HoverProviderCapability, InitializeParams, InitializeResult,
+    OneOf, PublishDiagnosticsParams, RenameOptions, SemanticTokensFullOptions,
+    SemanticTokensOptions, SemanticTokensServerCapabilities, ServerCapabilities, ServerInfo,
+    TextDocumentItem, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
+    Url,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    helpers::log,
+    server::{Client, RequestError, Server},
+    syntax::legend,
+};
+
+/// JSON-RPC error code for messages that aren't valid JSON.
+const PARSE_ERROR: i64 = -32700;
+/// JSON-RPC error code for requests the server doesn't handle.
+const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for requests whose params don't match their method.
+const INVALID_PARAMS: i64 = -32602;
+
+/// Holds the notifications the server sends until they're written out.
+#[derive(Default)]
+struct Outbox(RefCell<Vec<Value>>);
+
+impl Client for Outbox {
+    fn publish_diagnostics(&self, params: PublishDiagnosticsParams) {
+        let notification = notification(PublishDiagnostics::METHOD, params);
+        self.0.borrow_mut().push(notification);
+    }
+
+    // Telemetry is only collected by the VS Code extension.
+    fn telemetry(&self, _: Value) {}
+}
+
+fn notification(method: &str, params: impl Serialize) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::Full),
+                ..Default::default()
+            },
+        )),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec!["\"".to_owned()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: legend(),
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        ..Default::default()
+    }
+}
+
+/// The workspace folders, or the root, from `initialize` params.
+fn workspace_roots(params: Value) -> Vec<PathBuf> {
+    let params: InitializeParams = match serde_json::from_value(params) {
+        Ok(params) => params,
+        Err(e) => {
+            log(&format!("\tinvalid initialize params: {}", e));
+            return vec![];
+        }
+    };
+    let uris = match (params.workspace_folders, params.root_uri) {
+        (Some(folders), _) => folders.into_iter().map(|folder| folder.uri).collect(),
+        (None, root) => root.into_iter().collect::<Vec<_>>(),
+    };
+    uris.iter()
+        .filter_map(|uri| uri.to_file_path().ok())
+        .collect()
+}
+
+/// The Polar files under `dir`, skipping hidden directories.
+fn polar_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return log(&format!("\tcannot read {}: {}", dir.display(), e)),
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let hidden = path
+            .file_name()
+            .map(|name| name.to_string_lossy().starts_with('.'))
+            .unwrap_or(false);
+        if path.is_dir() && !hidden {
+            polar_files(&path, files);
+        } else if path.extension().map(|ext| ext == "polar").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+fn open_workspace(server: &mut Server<Outbox>, roots: &[PathBuf]) {
+    let mut files = vec![];
+    for root in roots {
+        polar_files(root, &mut files);
+    }
+    files.sort();
+    for path in files {
+        let (uri, text) = match (Url::from_file_path(&path), fs::read_to_string(&path)) {
+            (Ok(uri), Ok(text)) => (uri, text),
+            _ => {
+                log(&format!("\tcannot open {}", path.display()));
+                continue;
+            }
+        };
+        let text_document = TextDocumentItem::new(uri, "polar".to_owned(), 0, text);
+        let params = DidOpenTextDocumentParams { text_document };
+        server.on_notification(
+            DidOpenTextDocument::METHOD,
+            serde_json::to_value(params).unwrap(),
+        );
+    }
+}
+
+/// The body of the next message, or `None` at the end of `input`. Bodies aren't parsed here, so
+/// that a malformed one doesn't end the session.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        match header.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => {
+                let value = value
+                    .trim()
+                    .parse()
+                    .map_err(|e| invalid(format!("{}", e)))?;
+                length = Some(value);
+            }
+            _ => (),
+        }
+    }
+    let length = length.ok_or_else(|| invalid("missing Content-Length header".to_owned()))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn error_response(id: &Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// Serve the LSP messages read from `input`, writing responses and notifications to `output`,
+/// until the client sends `exit` or closes `input`. Returns whether the client asked the server
+/// to shut down first.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::new(Outbox::default());
+    let mut roots = vec![];
+    let mut shut_down = false;
+
+    while let Some(body) = read_message(&mut input)? {
+        let mut message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let response = error_response(&Value::Null, PARSE_ERROR, e.to_string());
+                write_message(&mut output, &response)?;
+                continue;
+            }
+        };
+        let method = match message.get("method").and_then(Value::as_str) {
+            Some(method) => method.to_owned(),
+            // A response to a request, but the server doesn't send any.
+            None => continue,
+        };
+        let params = message
+            .get_mut("params")
+            .map(Value::take)
+            .unwrap_or_default();
+
+        match message.get("id") {
+            Some(id) => {
+                let result = match method.as_str() {
+                    Initialize::METHOD => {
+                        roots = workspace_roots(params);
+                        let result = InitializeResult {
+                            capabilities: capabilities(),
+                            server_info: Some(ServerInfo {
+                                name: env!("CARGO_PKG_NAME").to_owned(),
+                                version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+                            }),
+                        };
+                        Ok(serde_json::to_value(result).unwrap())
+                    }
+                    Shutdown::METHOD => {
+                        shut_down = true;
+                        Ok(Value::Null)
+                    }
+                    _ => server.on_request(&method, params),
+                };
+                let response = match result {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err(RequestError::MethodNotFound) => error_response(
+                        id,
+                        METHOD_NOT_FOUND,
+                        format!("unhandled method: {}", method),
+                    ),
+                    Err(RequestError::InvalidParams(e)) => error_response(id, INVALID_PARAMS, e),
+                };
+                write_message(&mut output, &response)?;
+            }
+            None => match method.as_str() {
+                Exit::METHOD => return Ok(shut_down),
+                Initialized::METHOD => open_workspace(&mut server, &roots),
+                _ => server.on_notification(&method, params),
+            },
+        }
+
+        for notification in server.client().0.take() {
+            write_message(&mut output, &notification)?;
+        }
+    }
+    Ok(shut_down)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(messages: &[Value]) -> Vec<u8> {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        input
+    }
+
+    fn request(id: u64, method: &str, params: Value) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    fn responses(mut output: &[u8]) -> Vec<Value> {
+        let mut responses = vec![];
+        while let Some(body) = read_message(&mut output).unwrap() {
+            responses.push(serde_json::from_slice(&body).unwrap());
+        }
+        responses
+    }
+
+    #[test]
+    fn test_run() {
+        let root = std::env::temp_dir().join(format!("pls-stdio-{}", std::process::id()));
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(root.join("policy.polar"), "f(1);\n").unwrap();
+        fs::write(root.join(".hidden/skipped.polar"), "f(").unwrap();
+        let policy = Url::from_file_path(root.join("policy.polar")).unwrap();
+        let root_uri = Url::from_file_path(&root).unwrap();
+
+        let input = messages(&[
+            request(
+                1,
+                Initialize::METHOD,
+                json!({ "capabilities": {}, "rootUri": root_uri }),
+            ),
+            notification(Initialized::METHOD, json!({})),
+            request(
+                2,
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": policy }, "options": { "tabSize": 2, "insertSpaces": true } }),
+            ),
+            request(3, "textDocument/unknown", json!({})),
+            request(4, Shutdown::METHOD, Value::Null),
+            notification(Exit::METHOD, Value::Null),
+        ]);
+        let mut output = vec![];
+        assert!(run(&input[..], &mut output).unwrap());
+        fs::remove_dir_all(&root).unwrap();
+
+        let responses = responses(&output);
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["hoverProvider"],
+            true
+        );
+        assert_eq!(responses[1]["method"], PublishDiagnostics::METHOD);
+        assert_eq!(responses[1]["params"]["uri"], policy.as_str());
+        assert_eq!(
+            responses[1]["params"]["diagnostics"][0]["code"],
+            "ValidationWarning::MissingAllowRule"
+        );
+        assert_eq!(
+            responses[2],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": [] })
+        );
+        assert_eq!(responses[3]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            responses[4],
+            json!({ "jsonrpc": "2.0", "id": 4, "result": null })
+        );
+    }
+
+    #[test]
+    fn test_run_with_invalid_messages() {
+        let mut input = b"Content-Length: 9\r\n\r\n{invalid}".to_vec();
+        input.extend(messages(&[
+            request(1, "textDocument/formatting", json!({})),
+            notification(DidOpenTextDocument::METHOD, json!({ "textDocument": {} })),
+            notification(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": "file:///a.polar", "version": 1 },
+                    "contentChanges": [],
+                }),
+            ),
+            request(2, Shutdown::METHOD, Value::Null),
+            notification(Exit::METHOD, Value::Null),
+        ]));
+        let mut output = vec![];
+        assert!(run(&input[..], &mut output).unwrap());
+
+        let responses = responses(&output);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["id"], Value::Null);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["id"], 1);
+        assert_eq!(responses[1]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(
+            responses[2],
+            json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+        );
+    }
+}
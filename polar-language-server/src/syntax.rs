@@ -0,0 +1,337 @@
+//! Semantic tokens from the lexer, refined by the parse, and folding ranges.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use lsp_types::{
+    DocumentSymbol, FoldingRange, FoldingRangeKind, Position, SemanticToken, SemanticTokenModifier,
+    SemanticTokenType, SemanticTokensLegend,
+};
+use polar_core::{
+    lexer::{Lexer, Token},
+    parser::{parse_lines, Line},
+    resource_block::Production,
+    rules::Rule,
+    sources::Source,
+    terms::{Pattern, Symbol, Term, Value},
+    visitor::{walk_term, Visitor},
+};
+
+/// The token types, in the order of the legend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Keyword,
+    Comment,
+    String,
+    Number,
+    Operator,
+    Function,
+    Method,
+    Parameter,
+    Variable,
+    Property,
+    Class,
+}
+
+/// The only token modifier, used for the names of rules, parameters and resource blocks where
+/// they're declared.
+const DECLARATION: u32 = 1;
+
+pub(crate) fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::COMMENT,
+            SemanticTokenType::STRING,
+            SemanticTokenType::NUMBER,
+            SemanticTokenType::OPERATOR,
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::METHOD,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::CLASS,
+        ],
+        token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+    }
+}
+
+/// Classifications from the parse that the tokens around a symbol can't tell, keyed by offset.
+#[derive(Default)]
+struct Parsed(HashMap<usize, (Kind, u32)>);
+
+impl Parsed {
+    /// Empty if `src` doesn't parse.
+    fn new(src: &str) -> Self {
+        let mut parsed = Self::default();
+        for line in parse_lines(Source::new(src)).unwrap_or_default() {
+            match line {
+                Line::Rule(rule) | Line::RuleType(rule) => parsed.rule(&rule),
+                Line::ResourceBlock {
+                    keyword,
+                    resource,
+                    productions,
+                } => {
+                    // The resource term spans the whole block, keyword included.
+                    let mut start = resource.parsed_context().map_or(0, |context| context.left);
+                    if let Some(keyword) = &keyword {
+                        parsed.set(keyword, Kind::Keyword, 0);
+                        start = keyword
+                            .parsed_context()
+                            .map_or(start, |context| context.right);
+                    }
+                    if let Value::Variable(name) = resource.value() {
+                        if let Some(offset) = src[start..].find(&name.0) {
+                            parsed.0.insert(start + offset, (Kind::Class, DECLARATION));
+                        }
+                    }
+                    for production in &productions {
+                        match production {
+                            Production::Declaration((section, value)) => {
+                                parsed.set(section, Kind::Keyword, 0);
+                                if let Value::Dictionary(relations) = value.value() {
+                                    for related in relations.fields.values() {
+                                        parsed.set(related, Kind::Class, 0);
+                                    }
+                                }
+                            }
+                            Production::ShorthandRule(_, (_, Some((on, _)))) => {
+                                parsed.set(on, Kind::Keyword, 0)
+                            }
+                            Production::ShorthandRule(..) => (),
+                        }
+                    }
+                }
+                Line::Test {
+                    keyword,
+                    statements,
+                    ..
+                } => {
+                    parsed.set(&keyword, Kind::Keyword, 0);
+                    for (keyword, _) in &statements {
+                        parsed.set(keyword, Kind::Keyword, 0);
+                    }
+                }
+                Line::Query(_) | Line::Table(_) => (),
+            }
+        }
+        parsed
+    }
+
+    fn set(&mut self, term: &Term, kind: Kind, modifiers: u32) {
+        if let Some(context) = term.parsed_context() {
+            self.0.insert(context.left, (kind, modifiers));
+        }
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        if let Some(context) = rule.parsed_context() {
+            self.0.insert(context.left, (Kind::Function, DECLARATION));
+        }
+        let mut parameters = Parameters::default();
+        for param in &rule.params {
+            if let Value::Variable(name) = param.parameter.value() {
+                parameters.names.insert(name.clone());
+                self.set(&param.parameter, Kind::Parameter, DECLARATION);
+            }
+            if let Some(specializer) = &param.specializer {
+                if let Value::Pattern(Pattern::Instance(_)) = specializer.value() {
+                    self.set(specializer, Kind::Class, 0);
+                }
+            }
+        }
+        parameters.visit_term(&rule.body);
+        for offset in parameters.uses {
+            self.0.insert(offset, (Kind::Parameter, 0));
+        }
+    }
+}
+
+/// Where the parameters of a rule are used in its body.
+#[derive(Default)]
+struct Parameters {
+    names: HashSet<Symbol>,
+    uses: Vec<usize>,
+}
+
+impl Visitor for Parameters {
+    fn visit_term(&mut self, term: &Term) {
+        if let (Value::Variable(name), Some(context)) = (term.value(), term.parsed_context()) {
+            if self.names.contains(name) {
+                self.uses.push(context.left);
+            }
+        }
+        walk_term(self, term);
+    }
+}
+
+/// How to highlight the token at `i` when the parse doesn't say.
+fn classify(
+    tokens: &[(usize, Token, usize)],
+    i: usize,
+    classes: &BTreeSet<String>,
+) -> Option<Kind> {
+    use Token::*;
+
+    let kind = match &tokens[i].1 {
+        Integer(_) | Float(_) => Kind::Number,
+        String(_) => Kind::String,
+        Boolean(_) | New | Mod | Rem | In | Cut | Debug | Print | Isa | ForAll | If | And | Or
+        | Not | Matches | Type | Table => Kind::Keyword,
+        Bang | Mul | Div | Add | Sub | Eq | Neq | Leq | Geq | Lt | Gt | Unify | Assign | Pipe
+        | Query => Kind::Operator,
+        Symbol(name) => {
+            let previous = i.checked_sub(1).map(|i| &tokens[i].1);
+            match (previous, tokens.get(i + 1).map(|(_, token, _)| token)) {
+                (Some(Dot), Some(LP)) => Kind::Method,
+                (Some(Dot), _) => Kind::Property,
+                (Some(New | Matches), _) => Kind::Class,
+                (_, Some(LP)) => Kind::Function,
+                (_, Some(Colon)) => Kind::Property,
+                _ if classes.contains(&name.0) => Kind::Class,
+                _ => Kind::Variable,
+            }
+        }
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Converts byte offsets to positions without rescanning the text before them.
+struct Lines<'a> {
+    src: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(src: &'a str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { src, starts }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.src[self.starts[line]..offset].encode_utf16().count();
+        Position::new(line as _, character as _)
+    }
+}
+
+/// The comments in `gap`, a stretch of `src` between tokens starting at `offset`.
+fn comments(gap: &str, offset: usize, spans: &mut Vec<(usize, usize, Kind, u32)>) {
+    let mut rest = gap;
+    while let Some(start) = rest.find('#') {
+        let end = rest[start..]
+            .find('\n')
+            .map_or(rest.len(), |end| start + end);
+        let left = offset + gap.len() - rest.len() + start;
+        spans.push((left, left + end - start, Kind::Comment, 0));
+        rest = &rest[end..];
+    }
+}
+
+/// The semantic tokens of `src`, with `classes` highlighted as classes wherever they're used.
+pub(crate) fn semantic_tokens(src: &str, classes: &BTreeSet<String>) -> Vec<SemanticToken> {
+    let parsed = Parsed::new(src);
+    let mut complete = true;
+    // The lexer doesn't advance past an invalid character, so stop at the first error.
+    let tokens: Vec<_> = Lexer::new(src)
+        .map_while(|token| {
+            complete &= token.is_ok();
+            token.ok()
+        })
+        .collect();
+
+    let mut spans = vec![];
+    let mut previous_end = 0;
+    for (i, (left, _, right)) in tokens.iter().enumerate() {
+        comments(&src[previous_end..*left], previous_end, &mut spans);
+        let kind = match parsed.0.get(left) {
+            Some(&kind) => Some(kind),
+            None => classify(&tokens, i, classes).map(|kind| (kind, 0)),
+        };
+        if let Some((kind, modifiers)) = kind {
+            spans.push((*left, *right, kind, modifiers));
+        }
+        previous_end = *right;
+    }
+    if complete {
+        comments(&src[previous_end..], previous_end, &mut spans);
+    }
+
+    // Positions are relative to the previous token's start.
+    let lines = Lines::new(src);
+    let mut previous = Position::new(0, 0);
+    let mut semantic_tokens = vec![];
+    for (left, right, kind, modifiers) in spans {
+        let (start, end) = (lines.position(left), lines.position(right));
+        if start.line != end.line {
+            continue;
+        }
+        let delta_line = start.line - previous.line;
+        let delta_start = if delta_line == 0 {
+            start.character - previous.character
+        } else {
+            start.character
+        };
+        semantic_tokens.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: end.character - start.character,
+            token_type: kind as u32,
+            token_modifiers_bitset: modifiers,
+        });
+        previous = start;
+    }
+    semantic_tokens
+}
+
+/// Folding ranges for the multi-line rules and blocks in `symbols`, the outline of `src`, and
+/// for runs of comment lines.
+pub(crate) fn folding_ranges(src: &str, symbols: &[DocumentSymbol]) -> Vec<FoldingRange> {
+    let lines: Vec<_> = src.lines().collect();
+    let mut ranges = vec![];
+
+    let mut stack: Vec<_> = symbols.iter().collect();
+    while let Some(symbol) = stack.pop() {
+        let start_line = symbol.range.start.line;
+        let mut end_line = symbol.range.end.line;
+        // Leave a closing brace on its own line visible.
+        let closing = lines.get(end_line as usize).map(|line| line.trim_start());
+        if matches!(closing, Some(line) if line.starts_with('}')) {
+            end_line = end_line.saturating_sub(1);
+        }
+        if end_line > start_line {
+            ranges.push(folding_range(start_line, end_line, None));
+        }
+        stack.extend(symbol.children.iter().flatten());
+    }
+
+    let mut comment_start = None;
+    for (line, text) in lines.iter().chain(std::iter::once(&"")).enumerate() {
+        match (text.trim_start().starts_with('#'), comment_start) {
+            (true, None) => comment_start = Some(line),
+            (false, Some(start)) => {
+                if line - 1 > start {
+                    let kind = Some(FoldingRangeKind::Comment);
+                    ranges.push(folding_range(start as _, (line - 1) as _, kind));
+                }
+                comment_start = None;
+            }
+            _ => (),
+        }
+    }
+
+    ranges.sort_by_key(|range| (range.start_line, range.end_line));
+    ranges
+}
+
+fn folding_range(start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) -> FoldingRange {
+    FoldingRange {
+        start_line,
+        start_character: None,
+        end_line,
+        end_character: None,
+        kind,
+    }
+}
@@ -3,6 +3,6 @@ mod polar;
 mod query;
 
 pub use polar::Polar;
-pub use query::Query;
+pub use query::{CancellationToken, Query};
 
 type JsResult<T> = Result<T, wasm_bindgen::JsValue>;
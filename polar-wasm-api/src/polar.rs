@@ -30,6 +30,21 @@ impl Polar {
         self.0.clear_rules()
     }
 
+    #[wasm_bindgen(js_class = Polar, js_name = compile)]
+    pub fn wasm_compile(&self) -> JsResult<JsValue> {
+        let compiled = self.0.compile();
+        serde_wasm_bindgen::to_value(&compiled).map_err(|e| serialization_error(e.to_string()))
+    }
+
+    #[wasm_bindgen(js_class = Polar, js_name = loadCompiled)]
+    pub fn wasm_load_compiled(&self, compiled: JsValue) -> JsResult<()> {
+        let compiled = serde_wasm_bindgen::from_value(compiled)?;
+        self.0
+            .load_compiled(compiled)
+            .map_err(Error::from)
+            .map_err(Error::into)
+    }
+
     #[wasm_bindgen(js_class = Polar, js_name = registerConstant)]
     pub fn wasm_register_constant(&mut self, name: &str, term: JsValue) -> JsResult<()> {
         let term = serde_wasm_bindgen::from_value(term)?;
@@ -79,6 +79,11 @@ impl Query {
             .map_err(Error::into)
     }
 
+    #[wasm_bindgen(js_class = Query, js_name = cancellationToken)]
+    pub fn wasm_cancellation_token(&self) -> CancellationToken {
+        CancellationToken(self.0.cancellation_token())
+    }
+
     #[cfg(target_arch = "wasm32")]
     #[wasm_bindgen(js_class = Query, js_name = setLoggingOptions)]
     pub fn wasm_set_logging_options(
@@ -89,3 +94,15 @@ impl Query {
         self.0.set_logging_options(rust_log, polar_log);
     }
 }
+
+/// Cancels a query, so that its next event is a `RuntimeError::QueryCancelled`.
+#[wasm_bindgen]
+pub struct CancellationToken(query::CancellationToken);
+
+#[wasm_bindgen]
+impl CancellationToken {
+    #[wasm_bindgen(js_class = CancellationToken, js_name = cancel)]
+    pub fn wasm_cancel(&self) {
+        self.0.cancel()
+    }
+}
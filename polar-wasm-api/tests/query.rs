@@ -93,6 +93,15 @@ fn app_error_succeeds() {
     assert!(err.message().includes(msg, 0));
 }
 
+#[wasm_bindgen_test]
+fn cancelled_query_errors() {
+    let polar = polar_wasm_api::Polar::wasm_new();
+    let mut query = polar.wasm_new_query_from_str("x = 1").unwrap();
+    query.wasm_cancellation_token().wasm_cancel();
+    let err: Error = query.wasm_next_event().unwrap_err().dyn_into().unwrap();
+    assert_eq!(err.name(), "RuntimeError::QueryCancelled");
+}
+
 #[wasm_bindgen_test]
 fn debug_command_succeeds() {
     let polar = polar_wasm_api::Polar::wasm_new();
@@ -4,7 +4,10 @@ import {
   PublishDiagnosticsParams,
   TextDocumentSyncKind,
 } from 'vscode-languageserver/node';
-import { PolarLanguageServer } from '../../out/polar_language_server'; // eslint-disable-line node/no-unpublished-import
+import {
+  PolarLanguageServer,
+  semanticTokensLegend,
+} from '../../out/polar_language_server'; // eslint-disable-line node/no-unpublished-import
 
 // Create LSP connection
 const connection = createConnection(ProposedFeatures.all);
@@ -16,6 +19,7 @@ const telemetryCallback = (event: unknown) =>
 const pls = new PolarLanguageServer(sendDiagnosticsCallback, telemetryCallback);
 
 connection.onNotification((...args) => pls.onNotification(...args));
+connection.onRequest((method, params) => pls.onRequest(method, params));
 
 connection.onInitialize(() => {
   return {
@@ -28,6 +32,16 @@ connection.onInitialize(() => {
         save: false,
         change: TextDocumentSyncKind.Full,
       },
+      documentFormattingProvider: true,
+      definitionProvider: true,
+      referencesProvider: true,
+      documentSymbolProvider: true,
+      completionProvider: { triggerCharacters: ['"'] },
+      hoverProvider: true,
+      renameProvider: { prepareProvider: true },
+      codeActionProvider: true,
+      semanticTokensProvider: { legend: semanticTokensLegend(), full: true },
+      foldingRangeProvider: true,
       workspace: {
         workspaceFolders: { supported: true },
         // NOTE(gj): There's [an open issue][1] when specifying the `matches`